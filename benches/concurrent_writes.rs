@@ -0,0 +1,133 @@
+//! Write-heavy throughput from several threads: one lock per bucket (`ConcurrentMap`) against
+//! one lock per shard, and against a single lock around the whole map.
+//!
+//! Run with `cargo bench --bench concurrent_writes`. Every thread inserts its own keys, then
+//! updates and removes them, so threads only ever contend on locks, never on the same entries.
+
+use salt_map::chaining_map::ChainingHashMap;
+use salt_map::concurrent_map::ConcurrentMap;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const KEYS_PER_THREAD: u64 = 200_000;
+
+const SHARDS: usize = 16;
+
+// the operations every contender supports
+trait Contender: Sync {
+    fn insert(&self, key: u64);
+
+    fn bump(&self, key: u64);
+
+    fn remove(&self, key: u64);
+}
+
+impl Contender for ConcurrentMap<u64, u64> {
+    fn insert(&self, key: u64) {
+        ConcurrentMap::insert(self, key, key);
+    }
+
+    fn bump(&self, key: u64) {
+        ConcurrentMap::update(self, &key, |value| *value += 1);
+    }
+
+    fn remove(&self, key: u64) {
+        ConcurrentMap::remove(self, &key);
+    }
+}
+
+// a `ChainingHashMap` per shard, each behind its own mutex, picked by hash
+struct Sharded {
+    shards: Vec<Mutex<ChainingHashMap<u64, u64>>>,
+    hash_builder: RandomState,
+}
+
+impl Sharded {
+    fn shard(&self, key: u64) -> &Mutex<ChainingHashMap<u64, u64>> {
+        &self.shards[self.hash_builder.hash_one(key) as usize % self.shards.len()]
+    }
+}
+
+impl Contender for Sharded {
+    fn insert(&self, key: u64) {
+        self.shard(key).lock().unwrap().insert(key, key);
+    }
+
+    fn bump(&self, key: u64) {
+        if let Some(value) = self.shard(key).lock().unwrap().get_mut(&key) {
+            *value += 1;
+        }
+    }
+
+    fn remove(&self, key: u64) {
+        self.shard(key).lock().unwrap().remove(&key);
+    }
+}
+
+impl Contender for Mutex<ChainingHashMap<u64, u64>> {
+    fn insert(&self, key: u64) {
+        self.lock().unwrap().insert(key, key);
+    }
+
+    fn bump(&self, key: u64) {
+        if let Some(value) = self.lock().unwrap().get_mut(&key) {
+            *value += 1;
+        }
+    }
+
+    fn remove(&self, key: u64) {
+        self.lock().unwrap().remove(&key);
+    }
+}
+
+fn measure(map: &impl Contender, threads: u64) -> Duration {
+    let start = Instant::now();
+
+    std::thread::scope(|scope| {
+        for thread in 0..threads {
+            scope.spawn(move || {
+                let keys = thread * KEYS_PER_THREAD..(thread + 1) * KEYS_PER_THREAD;
+                keys.clone().for_each(|key| map.insert(key));
+                keys.clone().for_each(|key| map.bump(key));
+                keys.for_each(|key| map.remove(key));
+            });
+        }
+    });
+
+    start.elapsed()
+}
+
+fn report(name: &str, threads: u64, elapsed: Duration) {
+    let ops = 3 * threads * KEYS_PER_THREAD;
+    println!(
+        "{:<12} {:>2} threads  {:>10.2?}  {:>8.1} Mops/s",
+        name,
+        threads,
+        elapsed,
+        ops as f64 / elapsed.as_secs_f64() / 1e6
+    );
+}
+
+fn main() {
+    let max_threads = std::thread::available_parallelism().map_or(4, |n| n.get() as u64);
+
+    let mut threads = 1;
+    while threads <= max_threads {
+        let per_bucket = ConcurrentMap::new();
+        report("per-bucket", threads, measure(&per_bucket, threads));
+
+        let sharded = Sharded {
+            shards: (0..SHARDS)
+                .map(|_| Mutex::new(ChainingHashMap::new()))
+                .collect(),
+            hash_builder: RandomState::new(),
+        };
+        report("sharded", threads, measure(&sharded, threads));
+
+        let single = Mutex::new(ChainingHashMap::new());
+        report("single lock", threads, measure(&single, threads));
+
+        threads *= 2;
+    }
+}
@@ -0,0 +1,57 @@
+//! Worst-case single insert latency: doubling (`ChainingHashMap`) against incremental splits
+//! (`LinearHashMap`).
+//!
+//! Run with `cargo bench --bench insert_latency`. The doubling map's worst insert is the one
+//! that triggers the last full rehash, so it grows with the map; the linear map's worst insert
+//! only ever splits a single bucket.
+
+use salt_map::chaining_map::ChainingHashMap;
+use salt_map::linear_map::LinearHashMap;
+use std::time::{Duration, Instant};
+
+const INSERTS: u64 = 2_000_000;
+
+// runs `insert` for every key, returning the total and the slowest single call
+fn measure(mut insert: impl FnMut(u64)) -> (Duration, Duration) {
+    let mut total = Duration::ZERO;
+    let mut worst = Duration::ZERO;
+
+    for key in 0..INSERTS {
+        let start = Instant::now();
+        insert(key);
+        let elapsed = start.elapsed();
+
+        total += elapsed;
+        worst = worst.max(elapsed);
+    }
+
+    (total, worst)
+}
+
+fn report(name: &str, (total, worst): (Duration, Duration)) {
+    println!(
+        "{:<16} total {:>10.2?}  mean {:>8.2?}  worst {:>10.2?}",
+        name,
+        total,
+        total / INSERTS as u32,
+        worst
+    );
+}
+
+fn main() {
+    let mut chaining = ChainingHashMap::new();
+    report(
+        "chaining (2x)",
+        measure(|key| {
+            chaining.insert(key, key);
+        }),
+    );
+
+    let mut linear = LinearHashMap::new();
+    report(
+        "linear",
+        measure(|key| {
+            linear.insert(key, key);
+        }),
+    );
+}
@@ -0,0 +1,75 @@
+//! Lookups of missing keys and scans over the keys with large values: entries stored together
+//! (`DenseMap`) against keys and values in separate arrays (`SoaMap`).
+//!
+//! Run with `cargo bench --bench large_values`. `DenseMap` drags each entry's 256-byte value
+//! through the cache just to compare its hash, where `SoaMap` only reads the hash array.
+
+use salt_map::dense_map::DenseMap;
+use salt_map::soa_map::SoaMap;
+use salt_map::Map;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const KEYS: u64 = 200_000;
+const LOOKUPS: u64 = 5_000_000;
+
+type Value = [u64; 32];
+
+// spreads the lookups over the keys without walking them in insertion order
+fn key(i: u64) -> u64 {
+    i.wrapping_mul(0x9e37_79b9_7f4a_7c15) % KEYS
+}
+
+fn time(f: impl FnOnce() -> u64) -> Duration {
+    let start = Instant::now();
+    black_box(f());
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    println!("{:<20} {:>10.2?}", name, elapsed);
+}
+
+fn main() {
+    let mut dense = DenseMap::new();
+    let mut soa = SoaMap::new();
+    for i in 0..KEYS {
+        dense.insert(i, [i; 32] as Value);
+        soa.insert(i, [i; 32] as Value);
+    }
+
+    // missing keys are shifted past the present ones
+    report(
+        "dense miss",
+        time(|| {
+            (0..LOOKUPS)
+                .filter(|i| dense.get(&(key(*i) + KEYS)).is_some())
+                .count() as u64
+        }),
+    );
+    report(
+        "soa miss",
+        time(|| {
+            (0..LOOKUPS)
+                .filter(|i| soa.contains_key(&(key(*i) + KEYS)))
+                .count() as u64
+        }),
+    );
+
+    report(
+        "dense key scan",
+        time(|| {
+            (0..100)
+                .map(|_| Map::iter(&dense).map(|(k, _)| k).sum::<u64>())
+                .sum()
+        }),
+    );
+    report(
+        "soa key scan",
+        time(|| {
+            (0..100)
+                .map(|_| Map::iter(&soa).map(|(k, _)| k).sum::<u64>())
+                .sum()
+        }),
+    );
+}
@@ -0,0 +1,56 @@
+//! Lookups of missing keys, the bulk of the work for negative caches, against lookups of
+//! present ones, for `ChainingHashMap` and std's `HashMap`.
+//!
+//! Run with `cargo bench --bench miss_lookup`. Most misses are settled by the bucket's
+//! fingerprint filter without touching its chain, so they should come out no slower than hits.
+
+use salt_map::chaining_map::ChainingHashMap;
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const KEYS: u64 = 1_000_000;
+const LOOKUPS: u64 = 10_000_000;
+
+// spreads the lookups over the keys without walking them in insertion order
+fn key(i: u64) -> u64 {
+    i.wrapping_mul(0x9e37_79b9_7f4a_7c15) % KEYS
+}
+
+// times `LOOKUPS` calls of `lookup`, which reports whether it found its key
+fn measure(mut lookup: impl FnMut(u64) -> bool) -> Duration {
+    let start = Instant::now();
+    let mut found = 0;
+    for i in 0..LOOKUPS {
+        found += lookup(black_box(i)) as u64;
+    }
+    black_box(found);
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    println!(
+        "{:<20} total {:>10.2?}  per lookup {:>8.2?}",
+        name,
+        elapsed,
+        elapsed / LOOKUPS as u32
+    );
+}
+
+fn main() {
+    let mut chaining = ChainingHashMap::new();
+    let mut std = HashMap::new();
+    for i in 0..KEYS {
+        chaining.insert(i, i);
+        std.insert(i, i);
+    }
+
+    // present keys are `0..KEYS`, missing ones are shifted past them
+    report("chaining hit", measure(|i| chaining.get(&key(i)).is_some()));
+    report(
+        "chaining miss",
+        measure(|i| chaining.get(&(key(i) + KEYS)).is_some()),
+    );
+    report("std hit", measure(|i| std.contains_key(&key(i))));
+    report("std miss", measure(|i| std.contains_key(&(key(i) + KEYS))));
+}
@@ -0,0 +1,44 @@
+#![no_main]
+
+// Interprets the input as a stream of 3-byte operations and applies each one to both a
+// `ChainingHashMap` and a `std::collections::HashMap`, asserting that every observable result
+// matches. Keys are a single byte so the stream revisits keys (overwrites, double removes) often.
+
+use libfuzzer_sys::fuzz_target;
+use salt_map::chaining_map::ChainingHashMap;
+use std::collections::HashMap;
+
+fuzz_target!(|data: &[u8]| {
+    let mut map = ChainingHashMap::with_capacity(1);
+    let mut model = HashMap::new();
+
+    for op in data.chunks_exact(3) {
+        let key = op[1];
+        let value = op[2];
+
+        match op[0] % 5 {
+            0 => assert_eq!(map.insert(key, value), model.insert(key, value)),
+            1 => assert_eq!(map.get(&key), model.get(&key)),
+            2 => assert_eq!(map.remove(&key), model.remove(&key)),
+            3 => assert_eq!(map.remove_entry(&key), model.remove_entry(&key)),
+            _ => {
+                let ours = map.get_mut(&key).map(|v| {
+                    *v = v.wrapping_add(value);
+                    *v
+                });
+                let theirs = model.get_mut(&key).map(|v| {
+                    *v = v.wrapping_add(value);
+                    *v
+                });
+                assert_eq!(ours, theirs);
+            }
+        }
+
+        assert_eq!(map.len(), model.len());
+        assert_eq!(map.is_empty(), model.is_empty());
+    }
+
+    for (key, value) in model.iter() {
+        assert_eq!(map.get(key), Some(value));
+    }
+});
@@ -0,0 +1,292 @@
+use crate::bounded_map::CapacityExceeded;
+use crate::concurrent_map::ConcurrentMap;
+use crate::equivalent::Equivalent;
+use std::hash;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// What `BoundedConcurrentMap::insert` does when the map is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Wait until another thread removes an entry
+    Block,
+    /// Hand the entry back straight away
+    Fail,
+}
+
+/// A `ConcurrentMap` with a hard limit on the number of entries, for admission control
+/// between pipeline stages.
+///
+/// Inserting a new key into a full map either blocks the thread until a removal makes room or
+/// fails with `CapacityExceeded`, depending on the `Backpressure` policy; `try_insert` and
+/// `insert_timeout` pick per call. Overwriting a key that is already present always succeeds.
+#[derive(Debug)]
+pub struct BoundedConcurrentMap<K, V, S = hash::RandomState> {
+    map: ConcurrentMap<K, V, S>,
+    max_len: usize,
+    policy: Backpressure,
+    // entries stored plus inserts that have claimed a place but not stored yet; never above
+    // `max_len`
+    claimed: AtomicUsize,
+    // blocked inserters wait here for a removal; the mutex guards nothing, it only orders the
+    // waiting against the notifying
+    waiting: Mutex<()>,
+    space: Condvar,
+}
+
+impl<K, V> BoundedConcurrentMap<K, V, hash::RandomState> {
+    pub fn new(max_len: usize, policy: Backpressure) -> Self {
+        BoundedConcurrentMap::with_hasher(max_len, policy, hash::RandomState::new())
+    }
+}
+
+impl<K, V, S> BoundedConcurrentMap<K, V, S> {
+    pub fn with_hasher(max_len: usize, policy: Backpressure, hash_builder: S) -> Self {
+        BoundedConcurrentMap {
+            map: ConcurrentMap::with_capacity_and_hasher(max_len, hash_builder),
+            max_len,
+            policy,
+            claimed: AtomicUsize::new(0),
+            waiting: Mutex::new(()),
+            space: Condvar::new(),
+        }
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    pub fn policy(&self) -> Backpressure {
+        self.policy
+    }
+
+    /// The number of entries stored
+    ///
+    /// Admission goes by places claimed instead, which also count inserts still in flight, so
+    /// `is_full` can already be true while `len` is below `max_len`.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Whether a new key would have to wait or fail right now; see `len`
+    pub fn is_full(&self) -> bool {
+        self.claimed.load(Ordering::Acquire) >= self.max_len
+    }
+
+    // claims a place for a new entry, if there is one
+    fn claim(&self) -> bool {
+        self.claimed
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |claimed| {
+                (claimed < self.max_len).then_some(claimed + 1)
+            })
+            .is_ok()
+    }
+
+    // gives a place back, waking a blocked inserter
+    fn release(&self) {
+        self.claimed.fetch_sub(1, Ordering::AcqRel);
+
+        // taking the mutex means a waiter is either not yet checking, and will see the place,
+        // or already waiting, and will get the notification
+        drop(self.waiting.lock().unwrap_or_else(PoisonError::into_inner));
+        self.space.notify_one();
+    }
+}
+
+impl<K, V, S> BoundedConcurrentMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    /// Inserts an entry, blocking or failing per the map's policy if it is full
+    pub fn insert(&self, key: K, value: V) -> Result<Option<V>, CapacityExceeded<K, V>> {
+        match self.policy {
+            Backpressure::Block => self.insert_until(key, value, None),
+            Backpressure::Fail => self.try_insert(key, value),
+        }
+    }
+
+    /// Inserts an entry, failing straight away if the map is full, whatever its policy
+    pub fn try_insert(&self, key: K, value: V) -> Result<Option<V>, CapacityExceeded<K, V>> {
+        self.insert_until(key, value, Some(Instant::now()))
+    }
+
+    /// Inserts an entry, waiting at most `timeout` for room if the map is full
+    pub fn insert_timeout(
+        &self,
+        key: K,
+        value: V,
+        timeout: Duration,
+    ) -> Result<Option<V>, CapacityExceeded<K, V>> {
+        self.insert_until(key, value, Instant::now().checked_add(timeout))
+    }
+
+    fn insert_until(
+        &self,
+        key: K,
+        value: V,
+        deadline: Option<Instant>,
+    ) -> Result<Option<V>, CapacityExceeded<K, V>> {
+        // overwrites never need a place of their own
+        let mut value = Some(value);
+        let replaced = self.map.update(&key, |slot| {
+            std::mem::replace(slot, value.take().expect("only taken once"))
+        });
+        if replaced.is_some() {
+            return Ok(replaced);
+        }
+        let value = value.expect("not taken by a missing key");
+
+        if !self.claim() && !self.wait_for_place(deadline) {
+            return Err(CapacityExceeded { key, value });
+        }
+
+        let previous = self.map.insert(key, value);
+        if previous.is_some() {
+            // another thread inserted the key in the meantime, so this became an overwrite
+            self.release();
+        }
+        Ok(previous)
+    }
+
+    // blocks until a place is claimed or `deadline` passes; `None` waits forever
+    fn wait_for_place(&self, deadline: Option<Instant>) -> bool {
+        let mut guard = self.waiting.lock().unwrap_or_else(PoisonError::into_inner);
+
+        loop {
+            if self.claim() {
+                return true;
+            }
+
+            guard = match deadline {
+                None => self
+                    .space
+                    .wait(guard)
+                    .unwrap_or_else(PoisonError::into_inner),
+                Some(deadline) => {
+                    let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+                        return false;
+                    };
+                    if left.is_zero() {
+                        return false;
+                    }
+                    self.space
+                        .wait_timeout(guard, left)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+            };
+        }
+    }
+
+    /// Gets a clone of the value for `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+        V: Clone,
+    {
+        self.map.get(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.map.contains_key(key)
+    }
+
+    /// Runs `f` on the value for `key`; see `ConcurrentMap::update`
+    pub fn update<Q, F, R>(&self, key: &Q, f: F) -> Option<R>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+        F: FnOnce(&mut V) -> R,
+    {
+        self.map.update(key, f)
+    }
+
+    /// Removes an entry, letting one blocked insert through
+    pub fn remove_entry<Q>(&self, key: &Q) -> Option<(K, V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let entry = self.map.remove_entry(key)?;
+        self.release();
+        Some(entry)
+    }
+
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.remove_entry(key).map(|entry| entry.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fail_fast() {
+        let map = BoundedConcurrentMap::new(2, Backpressure::Fail);
+
+        assert_eq!(map.insert(1, 1), Ok(None));
+        assert_eq!(map.insert(2, 2), Ok(None));
+        assert!(map.is_full());
+        assert_eq!(map.insert(3, 3), Err(CapacityExceeded { key: 3, value: 3 }));
+
+        // overwrites still go through
+        assert_eq!(map.insert(1, 10), Ok(Some(1)));
+
+        assert_eq!(map.remove(&2), Some(2));
+        assert_eq!(map.insert(3, 3), Ok(None));
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&1), Some(10));
+    }
+
+    #[test]
+    fn insert_timeout() {
+        let map = BoundedConcurrentMap::new(1, Backpressure::Block);
+        map.insert(1, 1).unwrap();
+
+        let started = Instant::now();
+        assert!(map.insert_timeout(2, 2, Duration::from_millis(20)).is_err());
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        assert!(map.try_insert(2, 2).is_err());
+    }
+
+    #[test]
+    fn blocked_inserts_resume_on_removal() {
+        let map = BoundedConcurrentMap::new(4, Backpressure::Block);
+        // both sides give up eventually, so a lost wakeup fails the test instead of hanging it
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        std::thread::scope(|scope| {
+            let producer = scope.spawn(|| {
+                for i in 0..100 {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    map.insert_timeout(i, i, left)
+                        .expect("consumer stopped draining");
+                }
+            });
+
+            // drain in insertion order, waiting for each key to arrive
+            for i in 0..100 {
+                while map.remove(&i).is_none() {
+                    assert!(Instant::now() < deadline, "producer stopped inserting");
+                    std::thread::yield_now();
+                }
+                assert!(map.len() <= 4);
+            }
+            producer.join().unwrap();
+        });
+
+        assert!(map.is_empty());
+        assert!(!map.is_full());
+    }
+}
@@ -0,0 +1,290 @@
+use crate::chaining_map::ChainingHashMap;
+use crate::equivalent::Equivalent;
+use std::error::Error;
+use std::fmt;
+use std::hash;
+
+/// Returned by `BoundedMap::insert` when the map is full, handing the rejected entry back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapacityExceeded<K, V> {
+    pub key: K,
+    pub value: V,
+}
+
+impl<K, V> fmt::Display for CapacityExceeded<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("bounded map is full")
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> Error for CapacityExceeded<K, V> {}
+
+/// Why an entry left a `BoundedMap`, as passed to its `on_evict` callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EvictionReason {
+    /// Evicted to make room for a new key
+    Capacity,
+    /// Removed by `remove` or `clear`
+    Explicit,
+}
+
+// `Send`, so a map with callbacks can still be moved to another thread; they're only called
+// through `&mut self`, so they needn't be `Sync`
+type Evictor<K, V> = Box<dyn FnMut(&K, &V) -> bool + Send>;
+
+type EvictionListener<K, V> = Box<dyn FnMut(&K, &V, EvictionReason) + Send>;
+
+/// A map with a hard limit on the number of entries.
+///
+/// Inserting a new key into a full map fails with `CapacityExceeded` instead of growing, for
+/// queues and backpressure where unbounded growth is a bug. Overwriting a key that is already
+/// present always succeeds. A map built `with_eviction` instead makes room by removing the
+/// first entry the eviction predicate accepts, and only fails if it accepts none.
+pub struct BoundedMap<K, V, S = hash::RandomState> {
+    map: ChainingHashMap<K, V, S>,
+    max_len: usize,
+    evictor: Option<Evictor<K, V>>,
+    on_evict: Option<EvictionListener<K, V>>,
+}
+
+impl<K, V> BoundedMap<K, V, hash::RandomState> {
+    pub fn new(max_len: usize) -> Self {
+        BoundedMap::with_hasher(max_len, hash::RandomState::new())
+    }
+
+    /// Creates a map that makes room when full by evicting the first entry `evict` accepts
+    pub fn with_eviction<F>(max_len: usize, evict: F) -> Self
+    where
+        F: FnMut(&K, &V) -> bool + Send + 'static,
+    {
+        let mut map = BoundedMap::new(max_len);
+        map.evictor = Some(Box::new(evict));
+        map
+    }
+}
+
+impl<K, V, S> BoundedMap<K, V, S> {
+    pub fn with_hasher(max_len: usize, hash_builder: S) -> Self {
+        BoundedMap {
+            map: ChainingHashMap::with_hasher(hash_builder),
+            max_len,
+            evictor: None,
+            on_evict: None,
+        }
+    }
+
+    /// Calls `listener` with every entry just before it leaves the map, and why, so evicted
+    /// resources can be flushed or closed; replaces any previous listener
+    ///
+    /// Entries replaced by an insert to the same key don't count as leaving.
+    pub fn on_evict<F>(&mut self, listener: F)
+    where
+        F: FnMut(&K, &V, EvictionReason) + Send + 'static,
+    {
+        self.on_evict = Some(Box::new(listener));
+    }
+
+    fn evicted(&mut self, key: &K, value: &V, reason: EvictionReason) {
+        if let Some(listener) = self.on_evict.as_mut() {
+            listener(key, value, reason);
+        }
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.map.len() >= self.max_len
+    }
+
+    pub fn clear(&mut self) {
+        if let Some(listener) = self.on_evict.as_mut() {
+            for (key, value) in self.map.iter() {
+                listener(key, value, EvictionReason::Explicit);
+            }
+        }
+        self.map.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.map.iter()
+    }
+}
+
+impl<K, V, S> BoundedMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    /// Inserts an entry, returning the old value if the key was present, or handing the entry
+    /// back if the map is full and nothing could be evicted
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>, CapacityExceeded<K, V>> {
+        if let Some(existing) = self.map.get_mut(&key) {
+            return Ok(Some(std::mem::replace(existing, value)));
+        }
+
+        if self.is_full() {
+            let evicted = match self.evictor.as_mut() {
+                Some(evict) => self.map.remove_first_where(|key, value| evict(key, value)),
+                None => None,
+            };
+
+            match evicted {
+                Some((key, value)) => self.evicted(&key, &value, EvictionReason::Capacity),
+                None => return Err(CapacityExceeded { key, value }),
+            }
+        }
+
+        Ok(self.map.insert(key, value))
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.map.get(key)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.map.get_mut(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.map.get(key).is_some()
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let (key, value) = self.map.remove_entry(key)?;
+        self.evicted(&key, &value, EvictionReason::Explicit);
+        Some(value)
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, S> fmt::Debug for BoundedMap<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoundedMap")
+            .field("max_len", &self.max_len)
+            .field("entries", &self.map.iter().collect::<Vec<_>>())
+            .field("evicts", &self.evictor.is_some())
+            .field("listens", &self.on_evict.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_when_full() {
+        let mut map = BoundedMap::new(3);
+
+        for i in 0..3 {
+            assert_eq!(map.insert(i, i), Ok(None));
+        }
+
+        assert!(map.is_full());
+        assert_eq!(
+            map.insert(3, 30),
+            Err(CapacityExceeded { key: 3, value: 30 })
+        );
+        // overwriting doesn't need room
+        assert_eq!(map.insert(1, 10), Ok(Some(1)));
+
+        assert_eq!(map.remove(&0), Some(0));
+        assert_eq!(map.insert(3, 30), Ok(None));
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.get(&3), Some(&30));
+    }
+
+    #[test]
+    fn zero_max_len() {
+        let mut map = BoundedMap::new(0);
+
+        assert!(map.insert("key", 1).is_err());
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn eviction() {
+        // only zero values may be evicted
+        let mut map = BoundedMap::with_eviction(4, |_, value: &i32| *value == 0);
+
+        for (key, value) in [(0, 1), (1, 1), (2, 0), (3, 1)] {
+            map.insert(key, value).unwrap();
+        }
+
+        assert_eq!(map.insert(4, 1), Ok(None));
+        assert!(!map.contains_key(&2));
+        assert_eq!(map.len(), 4);
+
+        // nothing left that may be evicted
+        assert_eq!(map.insert(5, 0), Err(CapacityExceeded { key: 5, value: 0 }));
+    }
+
+    #[test]
+    fn on_evict() {
+        use std::sync::{Arc, Mutex};
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut map = BoundedMap::with_eviction(2, |key: &i32, _: &&str| *key < 10);
+        let sink = log.clone();
+        map.on_evict(move |key, value, reason| sink.lock().unwrap().push((*key, *value, reason)));
+
+        map.insert(1, "a").unwrap();
+        map.insert(2, "b").unwrap();
+        map.insert(2, "replaced").unwrap();
+        map.insert(30, "c").unwrap();
+        map.remove(&30);
+        map.clear();
+
+        let log = std::mem::take(&mut *log.lock().unwrap());
+        assert_eq!(log.len(), 3);
+        // which of 1 and 2 made room for 30 depends on iteration order, the other is cleared
+        assert_eq!(log[0].2, EvictionReason::Capacity);
+        assert_eq!(log[1], (30, "c", EvictionReason::Explicit));
+        assert_eq!(log[2].2, EvictionReason::Explicit);
+        assert_eq!(log[0].0 + log[2].0, 3);
+    }
+
+    #[test]
+    fn send() {
+        fn assert_send<T: Send>(_: &T) {}
+
+        assert_send(&BoundedMap::<u32, u32>::new(4));
+
+        // callbacks only need to be `Send`, so they can keep state in a `Cell`
+        let mut map = BoundedMap::with_eviction(1, |_: &u32, _: &u32| true);
+        let evictions = std::cell::Cell::new(0);
+        map.on_evict(move |_, _, _| evictions.set(evictions.get() + 1));
+        assert_send(&map);
+
+        let len = std::thread::spawn(move || {
+            map.insert(1, 1).unwrap();
+            map.insert(2, 2).unwrap();
+            map.len()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(len, 1);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::chaining_map::{self, ChainingHashMap};
+use std::hash;
+
+/// A map keyed by byte strings, looked up by `&[u8]`.
+///
+/// Keys are stored as `Box<[u8]>` and hashed over the bytes directly, so keys parsed out of a
+/// network buffer can be looked up in place without copying them into a `Vec` first.
+#[derive(Debug)]
+pub struct BytesMap<V, S = hash::RandomState> {
+    map: ChainingHashMap<Box<[u8]>, V, S>,
+}
+
+impl<V> BytesMap<V, hash::RandomState> {
+    pub fn new() -> Self {
+        BytesMap {
+            map: ChainingHashMap::new(),
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        BytesMap {
+            map: ChainingHashMap::with_capacity(capacity),
+        }
+    }
+}
+
+impl<V> Default for BytesMap<V, hash::RandomState> {
+    fn default() -> Self {
+        BytesMap::new()
+    }
+}
+
+impl<V, S> BytesMap<V, S> {
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        BytesMap {
+            map: ChainingHashMap::with_capacity_and_hasher(capacity, hash_builder),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &V)> {
+        self.map.iter().map(|(key, value)| (&**key, value))
+    }
+
+    // the entries with their keys as stored, for the `Map` impl
+    pub(crate) fn entries(&self) -> chaining_map::Iter<'_, Box<[u8]>, V> {
+        self.map.iter()
+    }
+}
+
+impl<V, S: hash::BuildHasher> BytesMap<V, S> {
+    /// Inserts with a borrowed key, only copying it if it isn't already present
+    pub fn insert(&mut self, key: &[u8], value: V) -> Option<V> {
+        match self.map.get_mut(key) {
+            Some(existing) => Some(std::mem::replace(existing, value)),
+            None => self.map.insert(key.into(), value),
+        }
+    }
+
+    /// Inserts a key that is already owned, without copying it
+    pub fn insert_owned(&mut self, key: Vec<u8>, value: V) -> Option<V> {
+        self.map.insert(key.into_boxed_slice(), value)
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        self.map.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut V> {
+        self.map.get_mut(key)
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.map.get(key).is_some()
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        self.map.remove(key)
+    }
+
+    pub fn remove_entry(&mut self, key: &[u8]) -> Option<(Box<[u8]>, V)> {
+        self.map.remove_entry(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_and_get_by_slice() {
+        let mut map = BytesMap::new();
+        let buffer = b"GET /index HTTP/1.1\r\nHost: example.com\r\n";
+
+        assert_eq!(map.insert(&buffer[0..3], "method"), None);
+        assert_eq!(map.insert(b"GET", "verb"), Some("method"));
+        map.insert_owned(b"Host".to_vec(), "header");
+
+        assert_eq!(map.get(&buffer[0..3]), Some(&"verb"));
+        assert_eq!(map.get(&buffer[21..25]), Some(&"header"));
+        assert!(!map.contains_key(b"POST"));
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn get_mut_and_remove() {
+        let mut map = BytesMap::with_capacity(4);
+
+        for i in 0..50u8 {
+            map.insert(&[i, i], i as usize);
+        }
+
+        *map.get_mut(&[7, 7]).unwrap() += 100;
+
+        assert_eq!(map.remove(&[7, 7]), Some(107));
+        assert_eq!(map.remove_entry(&[8, 8]), Some((vec![8, 8].into(), 8)));
+        assert_eq!(map.get(&[7, 7]), None);
+        assert_eq!(map.iter().count(), 48);
+    }
+
+    #[test]
+    fn map_trait() {
+        use crate::map::Map;
+
+        let mut map = BytesMap::new();
+        let key = Box::<[u8]>::from(&b"key"[..]);
+
+        assert_eq!(Map::insert(&mut map, key.clone(), 1), None);
+        *Map::get_mut(&mut map, &key).unwrap() += 1;
+        assert_eq!(map.get(b"key"), Some(&2));
+        assert_eq!(Map::iter(&map).collect::<Vec<_>>(), [(&key, &2)]);
+        assert_eq!(Map::remove(&mut map, &key), Some(2));
+        assert!(Map::is_empty(&map));
+    }
+}
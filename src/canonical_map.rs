@@ -0,0 +1,161 @@
+use crate::chaining_map::ChainingHashMap;
+use std::fmt;
+use std::hash;
+
+/// A map that normalizes every key on the way in and on every lookup.
+///
+/// Keys are stored as `Q::Owned` in their canonical form, as produced by the normalization
+/// function given at construction (trimming, lowercasing, path canonicalization, ...), and
+/// looked up by `&Q`. Gives "logical" key equality without a newtype with hand written
+/// `Hash`/`Eq` at every call site, at the cost of normalizing (usually allocating) per lookup.
+pub struct CanonicalMap<Q, V, S = hash::RandomState>
+where
+    Q: ?Sized + ToOwned,
+{
+    map: ChainingHashMap<Q::Owned, V, S>,
+    normalize: fn(&Q) -> Q::Owned,
+}
+
+impl<Q, V> CanonicalMap<Q, V, hash::RandomState>
+where
+    Q: ?Sized + ToOwned,
+{
+    pub fn new(normalize: fn(&Q) -> Q::Owned) -> Self {
+        CanonicalMap::with_hasher(normalize, hash::RandomState::new())
+    }
+}
+
+impl<Q, V, S> CanonicalMap<Q, V, S>
+where
+    Q: ?Sized + ToOwned,
+{
+    pub fn with_hasher(normalize: fn(&Q) -> Q::Owned, hash_builder: S) -> Self {
+        CanonicalMap {
+            map: ChainingHashMap::with_hasher(hash_builder),
+            normalize,
+        }
+    }
+
+    /// The canonical form `key` is stored and looked up as
+    pub fn canonicalize(&self, key: &Q) -> Q::Owned {
+        (self.normalize)(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// Iterates over the entries, with keys in their canonical form
+    pub fn iter(&self) -> impl Iterator<Item = (&Q::Owned, &V)> {
+        self.map.iter()
+    }
+}
+
+impl<Q, V, S> CanonicalMap<Q, V, S>
+where
+    Q: ?Sized + ToOwned,
+    Q::Owned: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    pub fn insert(&mut self, key: &Q, value: V) -> Option<V> {
+        let key = self.canonicalize(key);
+        self.map.insert(key, value)
+    }
+
+    pub fn get(&self, key: &Q) -> Option<&V> {
+        self.map.get(&self.canonicalize(key))
+    }
+
+    pub fn get_mut(&mut self, key: &Q) -> Option<&mut V> {
+        let key = self.canonicalize(key);
+        self.map.get_mut(&key)
+    }
+
+    pub fn contains_key(&self, key: &Q) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn remove(&mut self, key: &Q) -> Option<V> {
+        let key = self.canonicalize(key);
+        self.map.remove(&key)
+    }
+
+    /// Removes an entry, returning the stored canonical key with its value
+    pub fn remove_entry(&mut self, key: &Q) -> Option<(Q::Owned, V)> {
+        let key = self.canonicalize(key);
+        self.map.remove_entry(&key)
+    }
+}
+
+impl<Q, V, S> fmt::Debug for CanonicalMap<Q, V, S>
+where
+    Q: ?Sized + ToOwned,
+    Q::Owned: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.map.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Map;
+    use std::path::{Component, Path, PathBuf};
+
+    crate::testkit::map_conformance_tests!(CanonicalMap::<usize, usize>::new(|key| *key));
+
+    #[test]
+    fn case_insensitive_keys() {
+        let mut map = CanonicalMap::new(|key: &str| key.trim().to_lowercase());
+
+        assert_eq!(map.insert("Content-Type", 1), None);
+        assert_eq!(map.insert("  content-type ", 2), Some(1));
+
+        assert_eq!(map.get("CONTENT-TYPE"), Some(&2));
+        assert!(map.contains_key("content-type"));
+        assert_eq!(map.iter().next(), Some((&"content-type".to_string(), &2)));
+
+        *map.get_mut("Content-type").unwrap() += 1;
+        assert_eq!(
+            map.remove_entry("CONTENT-TYPE"),
+            Some(("content-type".to_string(), 3))
+        );
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn path_keys() {
+        // drops `.` components and empty segments, without touching the filesystem
+        fn normalize(path: &Path) -> PathBuf {
+            path.components()
+                .filter(|component| *component != Component::CurDir)
+                .collect()
+        }
+
+        let mut map = CanonicalMap::new(normalize);
+        map.insert(Path::new("src/./lib.rs"), "lib");
+
+        assert_eq!(map.get(Path::new("src//lib.rs")), Some(&"lib"));
+        assert_eq!(map.remove(Path::new("./src/lib.rs")), Some("lib"));
+    }
+
+    #[test]
+    fn map_trait_normalizes_owned_keys() {
+        let mut map = CanonicalMap::new(|key: &str| key.to_lowercase());
+
+        assert_eq!(Map::insert(&mut map, "Accept".to_string(), 1), None);
+        assert_eq!(Map::insert(&mut map, "ACCEPT".to_string(), 2), Some(1));
+        assert_eq!(Map::get(&map, &"accept".to_string()), Some(&2));
+        assert_eq!(Map::len(&map), 1);
+    }
+}
@@ -1,353 +1,3690 @@
+use crate::equivalent::Equivalent;
+use crate::error::Error;
+use crate::seeded::{LazySeededState, SeededState};
+use std::collections::TryReserveError;
+use std::convert::Infallible;
 use std::hash;
+use std::marker::PhantomData;
 use std::mem;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed};
+
+/// The seed used by `ChainingHashMap::deterministic`; part of the crate's stable behaviour, so
+/// layouts recorded with one release reproduce with the next
+pub const DETERMINISTIC_SEED: u64 = 0x5a17_5a17_5a17_5a17;
+
+/// The number of buckets an unallocated map starts with on its first insert, unless changed with
+/// `set_initial_buckets`
+pub const DEFAULT_INITIAL_BUCKETS: usize = 8;
+
+static INITIAL_BUCKETS: AtomicUsize = AtomicUsize::new(DEFAULT_INITIAL_BUCKETS);
+
+/// Sets the number of buckets maps allocate on their first insert, crate-wide; rounded up to a
+/// power of two. Only affects maps created without an explicit capacity, and only the first
+/// allocation: growth from there still doubles.
+pub fn set_initial_buckets(buckets: usize) {
+    INITIAL_BUCKETS.store(buckets.max(1).next_power_of_two(), Relaxed);
+}
+
+pub fn initial_buckets() -> usize {
+    INITIAL_BUCKETS.load(Relaxed)
+}
+
+/// Chain length statistics of a chaining table, see `ChainingHashMap::chain_stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChainStats {
+    pub buckets: usize,
+    pub entries: usize,
+    /// Buckets with no entries
+    pub empty: usize,
+    /// Entries in the longest chain, which bounds the worst-case lookup
+    pub longest: usize,
+}
+
+impl ChainStats {
+    pub(crate) fn from_lengths(lengths: impl Iterator<Item = usize>) -> Self {
+        lengths.fold(ChainStats::default(), |stats, len| ChainStats {
+            buckets: stats.buckets + 1,
+            entries: stats.entries + len,
+            empty: stats.empty + (len == 0) as usize,
+            longest: stats.longest.max(len),
+        })
+    }
+
+    /// The average length of the chains that aren't empty
+    pub fn mean_chain(&self) -> f64 {
+        match self.buckets - self.empty {
+            0 => 0.0,
+            occupied => self.entries as f64 / occupied as f64,
+        }
+    }
+}
+
+/// Lifetime operation counts of a map, see `ChainingHashMap::enable_stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Inserts that added a new key, rather than replacing a value
+    pub inserts: u64,
+    /// Lookups that found their key
+    pub hits: u64,
+    pub misses: u64,
+    pub removes: u64,
+    pub resizes: u64,
+    /// Chain entries compared against a key, across every insert and lookup; divide by
+    /// `hits + misses` for the average probe length
+    pub probes: u64,
+}
+
+// atomic so lookups can count through `&self` without costing the map its `Sync`
+#[derive(Debug, Default)]
+struct Counters {
+    inserts: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    removes: AtomicU64,
+    resizes: AtomicU64,
+    probes: AtomicU64,
+}
+
+impl Counters {
+    fn snapshot(&self) -> Stats {
+        Stats {
+            inserts: self.inserts.load(Relaxed),
+            hits: self.hits.load(Relaxed),
+            misses: self.misses.load(Relaxed),
+            removes: self.removes.load(Relaxed),
+            resizes: self.resizes.load(Relaxed),
+            probes: self.probes.load(Relaxed),
+        }
+    }
+}
 
 // this is still memory inefficient, since each element is a Vec
 #[derive(Debug)]
-pub struct ChainingHashMap<K, V, S = hash::RandomState> {
-    backing: Vec<Option<Vec<(K, V)>>>,
+pub struct ChainingHashMap<K, V, S = hash::RandomState, M = (), B = Vec<StoredEntry<K, V, M>>> {
+    // every entry carries a metadata slot next to its value; zero-sized unless opted into, as is
+    // the value of a set-like `V = ()` map, so such entries are exactly as big as their keys
+    backing: Vec<Option<B>>,
+    // one bit per bucket, set while the bucket has a chain allocated, so iteration can skip a
+    // whole word's worth of empty buckets at once; words past the end of the vec count as clear
+    occupied: Vec<u64>,
+    // a 64-bit filter per bucket, with the `fingerprint` bit of every entry in its chain set, so
+    // most lookups of a missing key never touch the chain; removals leave their bits behind
+    // until the next rehash, and missing trailing filters count as empty
+    fingerprints: Vec<u64>,
     load: usize,
-    load_factor: f32, // reduce the result to the scale expected by a bucket
+    load_factor: LoadFactor,
     hash_builder: S,
+    // only allocated once stats are enabled, so maps that don't use them pay a null check
+    stats: Option<Box<Counters>>,
+    // the entries are only reachable through `B`'s `Bucket` impl, so the map owns them here
+    entries: PhantomData<StoredEntry<K, V, M>>,
+}
+
+// the most entries per bucket before the table grows, kept as an exact fraction so the check
+// every insert makes is integer math, with no float conversions
+#[derive(Debug, Clone, Copy)]
+struct LoadFactor {
+    entries: usize,
+    buckets: usize,
+}
+
+impl LoadFactor {
+    const DEFAULT: LoadFactor = LoadFactor {
+        entries: 7,
+        buckets: 10,
+    };
+
+    // whether `len` entries in `buckets` buckets is over the limit; widened, so it's exact for
+    // any length
+    fn exceeded(self, len: usize, buckets: usize) -> bool {
+        len as u128 * self.buckets as u128 > buckets as u128 * self.entries as u128
+    }
+
+    // the most entries `buckets` buckets hold within the limit
+    fn max_len(self, buckets: usize) -> usize {
+        buckets.saturating_mul(self.entries) / self.buckets
+    }
+
+    // the buckets that hold `len` entries at exactly the limit, rounded down
+    fn buckets_at(self, len: usize) -> usize {
+        len.saturating_mul(self.buckets) / self.entries
+    }
+
+    fn as_f32(self) -> f32 {
+        self.entries as f32 / self.buckets as f32
+    }
 }
 
-fn make_backing_with_capacity<K, V>(capacity: usize, load_factor: f32) -> Vec<Option<Vec<(K, V)>>> {
+fn make_backing_with_capacity<B>(capacity: usize, load_factor: LoadFactor) -> Vec<Option<B>> {
     // makes a backing with an effective capacity of the given capacity, actual capacity of
     // capacity / load factor; this ensures the map can hold at least `capacity` before
     // reallocating
-    let modified_capacity = (capacity as f32 / load_factor) as usize;
-    let mut backing_vec = Vec::with_capacity(modified_capacity);
-    for _ in 0..modified_capacity {
+    make_backing_with_buckets(load_factor.buckets_at(capacity))
+}
+
+fn make_backing_with_buckets<B>(buckets: usize) -> Vec<Option<B>> {
+    let mut backing_vec = Vec::with_capacity(buckets);
+    for _ in 0..buckets {
         backing_vec.push(None);
     }
     backing_vec
 }
 
+// chains this long or longer are reported, with the `tracing` feature
+#[cfg(feature = "tracing")]
+const LONG_CHAIN: usize = 16;
+
+// chains with more than this many slots allocated per entry are shrunk by `shrink_chains`
+const CHAIN_SLACK_RATIO: usize = 4;
+
+const BUCKETS_PER_WORD: usize = u64::BITS as usize;
+
+// how an entry is stored in its chain
+pub(crate) type StoredEntry<K, V, M> = (K, V, M);
+
+/// The container a `ChainingHashMap` keeps each bucket's chain of entries in, picked with its last
+/// type parameter so chain representations can be compared without a new map type
+///
+/// Entries are addressed by their position in the chain, and iteration walks chains as slices, so
+/// a bucket has to keep its entries contiguous; a linked list can't be one. `Vec` is the default,
+/// and `Box<[T]>` trades a reallocation on every insert for chains with no spare slots. Maps with
+/// another bucket type start out from `Default` or `with_metadata_and_hasher`.
+pub trait Bucket<T>: Default {
+    fn as_slice(&self) -> &[T];
+
+    fn as_mut_slice(&mut self) -> &mut [T];
+
+    /// Adds an entry after the last one
+    fn push(&mut self, entry: T);
+
+    /// Removes and returns the entry at `pos`, keeping the rest in order
+    fn remove(&mut self, pos: usize) -> T;
+
+    /// Removes every entry `extract` accepts, appending them to `out` in chain order
+    fn extract_into<F>(&mut self, mut extract: F, out: &mut Vec<T>)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        let mut pos = 0;
+        while pos < self.as_slice().len() {
+            if extract(&mut self.as_mut_slice()[pos]) {
+                out.push(self.remove(pos));
+            } else {
+                pos += 1;
+            }
+        }
+    }
+
+    /// Drops every entry `keep` rejects
+    fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        let mut pos = 0;
+        while pos < self.as_slice().len() {
+            if keep(&mut self.as_mut_slice()[pos]) {
+                pos += 1;
+            } else {
+                self.remove(pos);
+            }
+        }
+    }
+
+    /// The entries the bucket holds room for without reallocating
+    fn capacity(&self) -> usize;
+
+    fn shrink_to_fit(&mut self);
+
+    fn into_vec(self) -> Vec<T>;
+}
+
+impl<T> Bucket<T> for Vec<T> {
+    fn as_slice(&self) -> &[T] {
+        self
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        self
+    }
+
+    fn push(&mut self, entry: T) {
+        Vec::push(self, entry);
+    }
+
+    fn remove(&mut self, pos: usize) -> T {
+        Vec::remove(self, pos)
+    }
+
+    fn extract_into<F>(&mut self, extract: F, out: &mut Vec<T>)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        out.extend(self.extract_if(.., extract));
+    }
+
+    fn retain<F>(&mut self, keep: F)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        self.retain_mut(keep);
+    }
+
+    fn capacity(&self) -> usize {
+        Vec::capacity(self)
+    }
+
+    fn shrink_to_fit(&mut self) {
+        Vec::shrink_to_fit(self);
+    }
+
+    fn into_vec(self) -> Vec<T> {
+        self
+    }
+}
+
+impl<T> Bucket<T> for Box<[T]> {
+    fn as_slice(&self) -> &[T] {
+        self
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        self
+    }
+
+    fn push(&mut self, entry: T) {
+        let mut entries = mem::take(self).into_vec();
+        entries.push(entry);
+        *self = entries.into_boxed_slice();
+    }
+
+    fn remove(&mut self, pos: usize) -> T {
+        let mut entries = mem::take(self).into_vec();
+        let entry = entries.remove(pos);
+        *self = entries.into_boxed_slice();
+        entry
+    }
+
+    fn capacity(&self) -> usize {
+        self.len()
+    }
+
+    fn shrink_to_fit(&mut self) {}
+
+    fn into_vec(self) -> Vec<T> {
+        <[T]>::into_vec(self)
+    }
+}
+
+// the buckets in `range` whose bit is set in an occupancy bitmap, in ascending order; each word
+// is visited once, and each of its set bits found with `trailing_zeros`
+fn occupied_buckets(occupied: &[u64], range: Range<usize>) -> OccupiedBuckets<'_> {
+    let first = range.start / BUCKETS_PER_WORD;
+    let last = range.end.div_ceil(BUCKETS_PER_WORD).min(occupied.len());
+
+    let mut buckets = OccupiedBuckets {
+        occupied,
+        range,
+        word_idx: first,
+        last,
+        word: 0,
+    };
+    if first < last {
+        buckets.word = buckets.masked_word(first);
+    }
+    buckets
+}
+
+// the iterator `occupied_buckets` returns; a named type so `Iter` can hold one
+#[derive(Debug, Clone)]
+struct OccupiedBuckets<'a> {
+    occupied: &'a [u64],
+    range: Range<usize>,
+    word_idx: usize,
+    // one past the last word to visit
+    last: usize,
+    // the bits of the current word not yet yielded
+    word: u64,
+}
+
+impl OccupiedBuckets<'_> {
+    // word `word_idx` of the bitmap, with the bits outside the range cleared
+    fn masked_word(&self, word_idx: usize) -> u64 {
+        let base = word_idx * BUCKETS_PER_WORD;
+        let mut word = self.occupied[word_idx];
+        if self.range.start > base {
+            word &= u64::MAX << (self.range.start - base);
+        }
+        if self.range.end < base + BUCKETS_PER_WORD {
+            word &= !(u64::MAX << (self.range.end - base));
+        }
+        word
+    }
+}
+
+impl Iterator for OccupiedBuckets<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.word == 0 {
+            self.word_idx += 1;
+            if self.word_idx >= self.last {
+                return None;
+            }
+            self.word = self.masked_word(self.word_idx);
+        }
+
+        let bit = self.word.trailing_zeros() as usize;
+        self.word &= self.word - 1;
+        Some(self.word_idx * BUCKETS_PER_WORD + bit)
+    }
+}
+
+// the bit an entry sets in its bucket's filter; taken from the top of the hash, which the bucket
+// index hardly depends on, so the entries sharing a bucket still spread over the bits
+fn fingerprint(hash: u64) -> u64 {
+    1 << (hash >> 58)
+}
+
+fn bucket_index(hash: u64, buckets: usize) -> usize {
+    // reduce while still a `u64`: casting the hash to `usize` first would throw away the top
+    // half of it on 32-bit targets
+    (hash % buckets as u64) as usize
+}
+
+// with the `randomize-iteration` feature, every iteration starts from a random bucket (wrapping
+// around), so code that accidentally relies on iteration order breaks straight away instead of
+// when the hasher or capacity changes
+#[cfg(feature = "randomize-iteration")]
+fn iteration_start(buckets: usize) -> usize {
+    use hash::{BuildHasher, Hasher};
+
+    if buckets == 0 {
+        return 0;
+    }
+
+    // every `RandomState` is keyed differently, which is all the randomness this needs
+    (hash::RandomState::new().build_hasher().finish() % buckets as u64) as usize
+}
+
+#[cfg(not(feature = "randomize-iteration"))]
+fn iteration_start(_buckets: usize) -> usize {
+    0
+}
+
 impl<K, V> ChainingHashMap<K, V, hash::RandomState> {
     pub fn with_capacity(capacity: usize) -> Self {
-        let load_factor = 0.7;
+        let load_factor = LoadFactor::DEFAULT;
         ChainingHashMap {
-            backing: make_backing_with_capacity::<K, V>(capacity, load_factor),
+            backing: make_backing_with_capacity(capacity, load_factor),
+            occupied: Vec::new(),
+            fingerprints: Vec::new(),
             load: 0,
-            load_factor: load_factor,
+            load_factor,
             hash_builder: hash::RandomState::new(),
+            stats: None,
+            entries: PhantomData,
         }
     }
 
+    /// Like `with_capacity`, but hands back allocation failure instead of aborting
+    pub fn try_with_capacity(capacity: usize) -> Result<Self, Error> {
+        ChainingHashMap::try_with_capacity_and_hasher(capacity, hash::RandomState::new())
+    }
+
+    /// Creates an empty map without allocating; the first insert allocates `initial_buckets()`
+    /// buckets, so maps that are never written to cost nothing
     pub fn new() -> Self {
-        // TODO: figure out if this is a good starting capacity, or if we can go lower
-        ChainingHashMap::with_capacity(20)
+        ChainingHashMap::with_capacity(0)
+    }
+
+    /// Creates a map with exactly `buckets` buckets (at least one), instead of deriving the
+    /// count from a capacity and the load factor; useful for forcing a particular collision
+    /// pattern. The map still resizes once the load factor is exceeded.
+    pub fn with_bucket_count(buckets: usize) -> Self {
+        ChainingHashMap::with_bucket_count_and_hasher(buckets, hash::RandomState::new())
+    }
+}
+
+impl<K, V> ChainingHashMap<K, V, LazySeededState> {
+    /// Creates an empty map in a `const` context, e.g. for a `static Mutex<ChainingHashMap<..>>`
+    ///
+    /// Nothing is allocated until the first insert, and the hasher draws its random seed on
+    /// first use.
+    pub const fn const_new() -> Self {
+        ChainingHashMap {
+            backing: Vec::new(),
+            occupied: Vec::new(),
+            fingerprints: Vec::new(),
+            load: 0,
+            load_factor: LoadFactor::DEFAULT,
+            hash_builder: LazySeededState::new(),
+            stats: None,
+            entries: PhantomData,
+        }
+    }
+}
+
+impl<K, V> ChainingHashMap<K, V, SeededState> {
+    /// Creates a map hashed with an explicit seed instead of OS randomness, for targets without
+    /// an entropy source and for reproducible bucket layouts
+    pub fn with_seed(seed: u64) -> Self {
+        ChainingHashMap::with_hasher(SeededState::new(seed))
+    }
+
+    pub fn with_capacity_and_seed(capacity: usize, seed: u64) -> Self {
+        ChainingHashMap::with_capacity_and_hasher(capacity, SeededState::new(seed))
+    }
+
+    /// Creates a map with a fixed, crate-wide seed, so the same sequence of operations produces
+    /// the same bucket layout and iteration order on every run (unless the
+    /// `randomize-iteration` feature is on), for golden-file tests and debugging sessions
+    pub fn deterministic() -> Self {
+        ChainingHashMap::with_seed(DETERMINISTIC_SEED)
+    }
+
+    pub fn deterministic_with_capacity(capacity: usize) -> Self {
+        ChainingHashMap::with_capacity_and_seed(capacity, DETERMINISTIC_SEED)
+    }
+}
+
+impl<K, V, B> Default for ChainingHashMap<K, V, hash::RandomState, (), B>
+where
+    B: Bucket<StoredEntry<K, V, ()>>,
+{
+    fn default() -> Self {
+        ChainingHashMap::with_metadata_and_hasher(0, hash::RandomState::new())
     }
 }
 
 impl<K, V, S> ChainingHashMap<K, V, S> {
     pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
-        let load_factor = 0.7;
+        let load_factor = LoadFactor::DEFAULT;
+        ChainingHashMap {
+            backing: make_backing_with_capacity(capacity, load_factor),
+            occupied: Vec::new(),
+            fingerprints: Vec::new(),
+            load: 0,
+            load_factor,
+            hash_builder,
+            stats: None,
+            entries: PhantomData,
+        }
+    }
+
+    /// Like `with_capacity_and_hasher`, but hands back allocation failure, including a capacity
+    /// too large to allocate at all, instead of aborting
+    pub fn try_with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Result<Self, Error> {
+        let mut map = ChainingHashMap::with_hasher(hash_builder);
+
+        let buckets = map.load_factor.buckets_at(capacity);
+        map.backing.try_reserve_exact(buckets)?;
+        map.backing.resize_with(buckets, || None);
+
+        Ok(map)
+    }
+
+    pub fn with_hasher(hash_builder: S) -> Self {
+        ChainingHashMap::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    pub fn with_bucket_count_and_hasher(buckets: usize, hash_builder: S) -> Self {
+        ChainingHashMap {
+            backing: make_backing_with_buckets(buckets.max(1)),
+            occupied: Vec::new(),
+            fingerprints: Vec::new(),
+            load: 0,
+            load_factor: LoadFactor::DEFAULT,
+            hash_builder,
+            stats: None,
+            entries: PhantomData,
+        }
+    }
+}
+
+impl<K, V, M> ChainingHashMap<K, V, hash::RandomState, M> {
+    /// Creates a map with a metadata slot of type `M` next to every value, for layers that need
+    /// to track something per entry (last access, flags) without changing the value type
+    pub fn with_metadata() -> Self {
+        ChainingHashMap::with_metadata_and_hasher(0, hash::RandomState::new())
+    }
+}
+
+impl<K, V, S, M, B: Bucket<StoredEntry<K, V, M>>> ChainingHashMap<K, V, S, M, B> {
+    pub fn with_metadata_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        let load_factor = LoadFactor::DEFAULT;
         ChainingHashMap {
-            backing: make_backing_with_capacity::<K, V>(capacity, load_factor),
+            backing: make_backing_with_capacity(capacity, load_factor),
+            occupied: Vec::new(),
+            fingerprints: Vec::new(),
             load: 0,
-            load_factor: load_factor,
-            hash_builder: hash_builder,
+            load_factor,
+            hash_builder,
+            stats: None,
+            entries: PhantomData,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        // TODO: go over the semantics of capacity to make sure they make sense; i.e. need to make
+        // sure the rules for when reallocation happens make sense
+        self.backing.capacity()
+    }
+
+    pub fn len(&self) -> usize {
+        self.load
+    }
+
+    /// The occupancy ratio above which the next insert resizes
+    pub fn load_factor(&self) -> f32 {
+        self.load_factor.as_f32()
+    }
+
+    /// Entries per bucket right now, to compare against `load_factor()`
+    pub fn current_load(&self) -> f32 {
+        match self.capacity() {
+            0 => 0.0,
+            capacity => self.load as f32 / capacity as f32,
+        }
+    }
+
+    /// How many more new keys can be inserted before one of them triggers a resize
+    pub fn spare_capacity(&self) -> usize {
+        if self.capacity() == 0 {
+            return 0;
+        }
+
+        // the check runs on the length before inserting, so an insert at the limit still fits
+        let limit = self.load_factor.max_len(self.capacity());
+        (limit + 1).saturating_sub(self.load)
+    }
+
+    fn over_load_factor(&self, len: usize) -> bool {
+        // an unallocated map is always due a resize, which allocates it
+        self.capacity() == 0 || self.load_factor.exceeded(len, self.capacity())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.load == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.load = 0;
+        self.backing.iter_mut().for_each(|x| *x = None);
+        self.occupied.clear();
+        self.fingerprints.clear();
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    /// Starts recording lifetime operation counts, retrievable with `stats`; a no-op if they're
+    /// already being recorded
+    pub fn enable_stats(&mut self) {
+        self.stats.get_or_insert_with(Box::default);
+    }
+
+    /// The operation counts recorded since `enable_stats` or the last `reset_stats`, or `None`
+    /// if stats were never enabled
+    pub fn stats(&self) -> Option<Stats> {
+        self.stats.as_ref().map(|counters| counters.snapshot())
+    }
+
+    pub fn reset_stats(&mut self) {
+        if let Some(counters) = self.stats.as_mut() {
+            **counters = Counters::default();
+        }
+    }
+
+    fn count(&self, record: impl FnOnce(&Counters)) {
+        if let Some(counters) = self.stats.as_deref() {
+            record(counters);
+        }
+    }
+
+    pub fn chain_stats(&self) -> ChainStats {
+        ChainStats::from_lengths(
+            self.backing
+                .iter()
+                .map(|bucket| bucket.as_ref().map_or(0, |chain| chain.as_slice().len())),
+        )
+    }
+
+    // the allocated chains of the buckets in `range`, in bucket order
+    fn chains_in(&self, range: Range<usize>) -> impl Iterator<Item = &B> {
+        occupied_buckets(&self.occupied, range).filter_map(|idx| self.backing[idx].as_ref())
+    }
+
+    // every allocated chain, in bucket order; a word of the bitmap with no bits set skips its
+    // buckets without looking at them
+    fn chains_mut(&mut self) -> impl Iterator<Item = &mut B> {
+        self.backing
+            .chunks_mut(BUCKETS_PER_WORD)
+            .zip(&self.occupied)
+            .filter(|(_, word)| **word != 0)
+            .flat_map(|(buckets, _)| buckets.iter_mut().flatten())
+    }
+
+    // the chain in bucket `idx`, allocated if it wasn't already
+    fn chain_mut(&mut self, idx: usize) -> &mut B {
+        let word = idx / BUCKETS_PER_WORD;
+        if word >= self.occupied.len() {
+            self.occupied.resize(word + 1, 0);
+        }
+        self.occupied[word] |= 1 << (idx % BUCKETS_PER_WORD);
+
+        self.backing[idx].get_or_insert_with(B::default)
+    }
+
+    // records an entry with this hash in bucket `idx`'s filter
+    fn mark_fingerprint(&mut self, idx: usize, hash: u64) {
+        if idx >= self.fingerprints.len() {
+            self.fingerprints.resize(idx + 1, 0);
+        }
+        self.fingerprints[idx] |= fingerprint(hash);
+    }
+
+    /// Iterates over the entries, in no particular order
+    pub fn iter(&self) -> Iter<'_, K, V, M, B> {
+        let buckets = self.backing.len();
+        let start = iteration_start(buckets);
+
+        Iter {
+            backing: &self.backing,
+            buckets: occupied_buckets(&self.occupied, start..buckets)
+                .chain(occupied_buckets(&self.occupied, 0..start)),
+            chain: [].iter(),
+            remaining: self.load,
+        }
+    }
+
+    /// Iterates over the keys, in no particular order
+    pub fn keys(&self) -> Keys<'_, K, V, M, B> {
+        Keys { inner: self.iter() }
+    }
+
+    /// Iterates over the values, in no particular order
+    pub fn values(&self) -> Values<'_, K, V, M, B> {
+        Values { inner: self.iter() }
+    }
+
+    // mutable counterpart of `iter`, over the values alone
+    pub(crate) fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.chains_mut()
+            .flat_map(B::as_mut_slice)
+            .map(|item| &mut item.1)
+    }
+
+    /// Iterates over the entries with mutable references to the values, in no particular order
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V, M, B> {
+        let buckets = self.backing.len();
+        let start = iteration_start(buckets);
+        let (front, back) = self.backing.split_at_mut(start);
+
+        IterMut {
+            buckets: occupied_buckets(&self.occupied, start..buckets)
+                .chain(occupied_buckets(&self.occupied, 0..start)),
+            back: UnvisitedBuckets {
+                rest: back,
+                first: start,
+            },
+            front: UnvisitedBuckets {
+                rest: front,
+                first: 0,
+            },
+            start,
+            chain: [].iter_mut(),
+            remaining: self.load,
+        }
+    }
+
+    // removes and returns every entry `extract` accepts
+    pub(crate) fn extract_where<F>(&mut self, mut extract: F) -> Vec<(K, V)>
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        let mut extracted = Vec::new();
+        for chain in self.chains_mut() {
+            chain.extract_into(|item| extract(&item.0, &mut item.1), &mut extracted);
+        }
+
+        self.load -= extracted.len();
+        extracted
+            .into_iter()
+            .map(|(key, value, _)| (key, value))
+            .collect()
+    }
+
+    // drops every entry `keep` rejects
+    pub(crate) fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        let mut removed = 0;
+        for chain in self.chains_mut() {
+            let before = chain.as_slice().len();
+            chain.retain(|item| keep(&item.0, &mut item.1));
+            removed += before - chain.as_slice().len();
+        }
+        self.load -= removed;
+    }
+
+    /// Iterates over the entries in ascending key order, for deterministic output
+    ///
+    /// Collects and sorts references to every entry up front, so this costs O(n log n) before
+    /// the first item is yielded
+    pub fn iter_sorted_by_key(&self) -> std::vec::IntoIter<(&K, &V)>
+    where
+        K: Ord,
+    {
+        self.iter_sorted_by(|a, b| a.0.cmp(b.0))
+    }
+
+    /// Iterates over the entries in the order given by the comparator
+    pub fn iter_sorted_by<F>(&self, mut compare: F) -> std::vec::IntoIter<(&K, &V)>
+    where
+        F: FnMut(&(&K, &V), &(&K, &V)) -> std::cmp::Ordering,
+    {
+        let mut entries = self.iter().collect::<Vec<_>>();
+        entries.sort_by(|a, b| compare(a, b));
+        entries.into_iter()
+    }
+
+    /// Consumes the map, returning its entries in ascending key order
+    pub fn into_sorted_vec(self) -> Vec<(K, V)>
+    where
+        K: Ord,
+    {
+        self.into_sorted_vec_by(|a, b| a.0.cmp(&b.0))
+    }
+
+    /// Consumes the map, returning its entries in the order given by the comparator
+    pub fn into_sorted_vec_by<F>(self, mut compare: F) -> Vec<(K, V)>
+    where
+        F: FnMut(&(K, V), &(K, V)) -> std::cmp::Ordering,
+    {
+        let mut entries = self.into_iter().collect::<Vec<_>>();
+        entries.sort_by(|a, b| compare(a, b));
+        entries
+    }
+}
+
+impl<K, V, S, M, B> ChainingHashMap<K, V, S, M, B>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+    B: Bucket<StoredEntry<K, V, M>>,
+{
+    fn get_index<Q>(&self, key: &Q) -> usize
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.hash_and_index(key).1
+    }
+
+    // the hash of `key` along with the bucket it belongs in
+    fn hash_and_index<Q>(&self, key: &Q) -> (u64, usize)
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        if self.backing.is_empty() {
+            // nothing is stored yet, and every lookup checks the bucket exists
+            return (0, 0);
+        }
+
+        // builds a hash with the instance's `hash_builder`, using the `BuildHasher` trait
+        let hash = self.hash_builder.hash_one(key);
+        (hash, bucket_index(hash, self.backing.len()))
+    }
+
+    /// Gets mutable references to the values of several keys at once, `None` for keys that
+    /// aren't present
+    ///
+    /// Panics if any two of the keys are the same key.
+    pub fn get_disjoint_mut<Q, const N: usize>(&mut self, keys: [&Q; N]) -> [Option<&mut V>; N]
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        match self.try_get_disjoint_mut(keys) {
+            Ok(values) => values,
+            Err(_) => panic!("get_disjoint_mut called with the same key twice"),
         }
     }
 
-    pub fn with_hasher(hash_builder: S) -> Self {
-        ChainingHashMap::with_capacity_and_hasher(20, hash_builder)
-    }
+    /// Like `get_disjoint_mut`, but fails with `Error::DuplicateKey` instead of panicking
+    pub fn try_get_disjoint_mut<Q, const N: usize>(
+        &mut self,
+        keys: [&Q; N],
+    ) -> Result<[Option<&mut V>; N], Error>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let mut found = keys
+            .iter()
+            .enumerate()
+            .filter_map(|(i, key)| Some((self.locate(*key)?, i)))
+            .collect::<Vec<_>>();
+        found.sort_unstable();
+        if found.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return Err(Error::DuplicateKey);
+        }
+
+        // hand out the entries in bucket order, splitting each borrow off the rest of the table
+        let mut values = [const { None }; N];
+        let mut buckets = &mut self.backing[..];
+        let mut consumed = 0;
+
+        for group in found.chunk_by(|a, b| a.0 .0 == b.0 .0) {
+            let idx = group[0].0 .0;
+            let (bucket, rest) = mem::take(&mut buckets)[idx - consumed..]
+                .split_first_mut()
+                .expect("located buckets exist");
+            buckets = rest;
+            consumed = idx + 1;
+
+            let mut chain = bucket
+                .as_mut()
+                .expect("located chains exist")
+                .as_mut_slice();
+            let mut chain_consumed = 0;
+            for ((_, pos), i) in group {
+                let (item, rest) = mem::take(&mut chain)[pos - chain_consumed..]
+                    .split_first_mut()
+                    .expect("located entries exist");
+                chain = rest;
+                chain_consumed = pos + 1;
+                values[*i] = Some(&mut item.1);
+            }
+        }
+
+        Ok(values)
+    }
+
+    /// Inserts a value, returning the previous one if the key was present
+    ///
+    /// A key that is already present keeps its metadata; a new one starts with `M::default()`.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V>
+    where
+        M: Default,
+    {
+        let result = self.insert_inner(key, value, M::default(), true);
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        result.map(|(value, _)| value)
+    }
+
+    /// Inserts a value along with its metadata, returning the previous pair if the key was
+    /// present
+    pub fn insert_with_meta(&mut self, key: K, value: V, meta: M) -> Option<(V, M)> {
+        let result = self.insert_inner(key, value, meta, false);
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        result
+    }
+
+    /// Inserts an entry, replacing both the stored key and value if an equal key is present and
+    /// returning the displaced pair, like `HashSet::replace`
+    ///
+    /// Useful when equal keys still differ in ways that matter, e.g. to swap in a key that
+    /// shares its allocation with something else.
+    pub fn replace(&mut self, key: K, value: V) -> Option<(K, V)>
+    where
+        M: Default,
+    {
+        let idx = self.get_index(&key);
+        let existing = self
+            .backing
+            .get_mut(idx)
+            .and_then(Option::as_mut)
+            .and_then(|chain| chain.as_mut_slice().iter_mut().find(|item| key == item.0));
+
+        if let Some(item) = existing {
+            return Some((
+                mem::replace(&mut item.0, key),
+                mem::replace(&mut item.1, value),
+            ));
+        }
+
+        self.insert(key, value);
+        None
+    }
+
+    // with `keep_meta`, an existing entry's metadata is left alone and `meta` is handed back
+    // unused
+    fn insert_inner(&mut self, key: K, value: V, mut meta: M, keep_meta: bool) -> Option<(V, M)> {
+        // resize before getting index, otherwise it will be the index for the previous capacity
+        if self.over_load_factor(self.len()) {
+            self.resize();
+        }
+
+        let (hash, idx) = self.hash_and_index(&key);
+
+        // the chain is updated in place, so a panicking `Eq` leaves it untouched; at worst the
+        // fingerprint is marked for a key that never made it in, which lookups tolerate
+        self.mark_fingerprint(idx, hash);
+        let chain = self.chain_mut(idx);
+
+        let found = chain.as_slice().iter().position(|item| key == item.0);
+        let probes = found.map_or(chain.as_slice().len(), |pos| pos + 1);
+
+        let result = match found {
+            Some(pos) => {
+                let item = &mut chain.as_mut_slice()[pos];
+                if !keep_meta {
+                    mem::swap(&mut item.2, &mut meta);
+                }
+                Some((mem::replace(&mut item.1, value), meta))
+            }
+            None => {
+                chain.push((key, value, meta));
+                #[cfg(feature = "tracing")]
+                let chain_len = chain.as_slice().len();
+
+                self.load += 1;
+                #[cfg(feature = "tracing")]
+                self.trace_chain_growth(chain_len);
+                None
+            }
+        };
+
+        self.count(|stats| {
+            stats.probes.fetch_add(probes as u64, Relaxed);
+            if result.is_none() {
+                stats.inserts.fetch_add(1, Relaxed);
+            }
+        });
+
+        result
+    }
+
+    /// Gets the value for `key`, inserting `default()` first if it's missing
+    ///
+    /// Hashes the key once, whereas `get_mut` followed by `insert` hashes a missing key twice,
+    /// which adds up in tight counting loops.
+    pub fn get_or_insert_with<F>(&mut self, key: K, default: F) -> &mut V
+    where
+        F: FnOnce() -> V,
+        M: Default,
+    {
+        match self.get_or_try_insert_with(key, || Ok::<_, Infallible>(default())) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Gets the value for `key`, inserting the result of `init()` first if it's missing
+    ///
+    /// If `init` fails the error is returned and nothing is inserted, so a failed load doesn't
+    /// need cleaning up.
+    pub fn get_or_try_insert_with<F, E>(&mut self, key: K, init: F) -> Result<&mut V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+        M: Default,
+    {
+        if self.over_load_factor(self.len()) {
+            self.resize();
+        }
+
+        let (hash, idx) = self.hash_and_index(&key);
+        self.mark_fingerprint(idx, hash);
+        let chain = self.chain_mut(idx);
+
+        let found = chain.as_slice().iter().position(|item| key == item.0);
+        let probes = found.map_or(chain.as_slice().len(), |pos| pos + 1);
+        self.count(|stats| {
+            stats.probes.fetch_add(probes as u64, Relaxed);
+        });
+
+        let pos = match found {
+            Some(pos) => {
+                self.count(|stats| {
+                    stats.hits.fetch_add(1, Relaxed);
+                });
+                pos
+            }
+            None => {
+                let value = init()?;
+
+                let chain = self.backing[idx].as_mut().expect("bucket was just filled");
+                chain.push((key, value, M::default()));
+                let pos = chain.as_slice().len() - 1;
+
+                self.load += 1;
+                #[cfg(feature = "tracing")]
+                self.trace_chain_growth(pos + 1);
+                self.count(|stats| {
+                    stats.inserts.fetch_add(1, Relaxed);
+                });
+                pos
+            }
+        };
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        Ok(&mut self.backing[idx]
+            .as_mut()
+            .expect("bucket was just filled")
+            .as_mut_slice()[pos]
+            .1)
+    }
+
+    /// Gets the given key's place in the map, to read, update, insert or remove it with a single
+    /// lookup
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S, M, B> {
+        // grow up front, so a vacant entry's bucket is still the right one when it's filled
+        if self.over_load_factor(self.len()) {
+            self.resize();
+        }
+
+        let (hash, idx) = self.hash_and_index(&key);
+        let chain = match self.fingerprints.get(idx).copied().unwrap_or(0) & fingerprint(hash) {
+            0 => None,
+            _ => self.backing[idx].as_ref(),
+        };
+        let found = chain.and_then(|chain| chain.as_slice().iter().position(|item| key == item.0));
+
+        self.count(|stats| {
+            let probes = found.map_or(chain.map_or(0, |chain| chain.as_slice().len()), |pos| {
+                pos + 1
+            });
+            stats.probes.fetch_add(probes as u64, Relaxed);
+            if found.is_some() {
+                stats.hits.fetch_add(1, Relaxed);
+            }
+        });
+
+        match found {
+            Some(pos) => Entry::Occupied(OccupiedEntry {
+                map: self,
+                hash,
+                idx,
+                pos,
+            }),
+            None => Entry::Vacant(VacantEntry {
+                map: self,
+                key,
+                hash,
+                idx,
+            }),
+        }
+    }
+
+    /// Looks up every key in `keys`, returning the results in the same order
+    pub fn get_all<'a, 'q, Q, I>(&'a self, keys: I) -> Vec<Option<&'a V>>
+    where
+        I: IntoIterator<Item = &'q Q>,
+        Q: ?Sized + hash::Hash + Equivalent<K> + 'q,
+    {
+        self.get_all_iter(keys).collect()
+    }
+
+    /// Like `get_all`, but yields the results lazily instead of collecting them
+    pub fn get_all_iter<'a, 'q, Q, I>(
+        &'a self,
+        keys: I,
+    ) -> impl Iterator<Item = Option<&'a V>> + use<'a, 'q, K, V, S, M, B, Q, I>
+    where
+        I: IntoIterator<Item = &'q Q>,
+        Q: ?Sized + hash::Hash + Equivalent<K> + 'q,
+    {
+        keys.into_iter().map(move |key| self.get(key))
+    }
+
+    /// Gets reference to value based on the input key
+    ///
+    /// The key may be any borrowed form of the map's key type, e.g. `&str` for `String` keys, or
+    /// anything else implementing `Equivalent` for it
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.find(key).map(|item| &item.1)
+    }
+
+    /// Gets the stored key along with the value
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.find(key).map(|item| (&item.0, &item.1))
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.find_mut(key).map(|item| &mut item.1)
+    }
+
+    pub fn get_meta<Q>(&self, key: &Q) -> Option<&M>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.find(key).map(|item| &item.2)
+    }
+
+    /// Gets the value and metadata of an entry together, e.g. to bump a last-access time on a
+    /// read
+    pub fn get_with_meta_mut<Q>(&mut self, key: &Q) -> Option<(&mut V, &mut M)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.find_mut(key).map(|item| (&mut item.1, &mut item.2))
+    }
+
+    // the bucket and chain position of the entry for `key`; every lookup goes through here, so
+    // this is where lookups are counted
+    fn locate<Q>(&self, key: &Q) -> Option<(usize, usize)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let (hash, idx) = self.hash_and_index(key);
+
+        // a key whose fingerprint bit is clear can't be in the chain, which settles most misses
+        // with a single load and without comparing any keys
+        let filter = self.fingerprints.get(idx).copied().unwrap_or(0);
+        let chain = match filter & fingerprint(hash) {
+            0 => None,
+            _ => self.backing.get(idx).and_then(Option::as_ref),
+        };
+        let pos = chain.and_then(|chain| {
+            chain
+                .as_slice()
+                .iter()
+                .position(|item| key.equivalent(&item.0))
+        });
+
+        self.count(|stats| {
+            let probes = pos.map_or(chain.map_or(0, |chain| chain.as_slice().len()), |pos| {
+                pos + 1
+            });
+            stats.probes.fetch_add(probes as u64, Relaxed);
+            match pos {
+                Some(_) => stats.hits.fetch_add(1, Relaxed),
+                None => stats.misses.fetch_add(1, Relaxed),
+            };
+        });
+
+        pos.map(|pos| (idx, pos))
+    }
+
+    fn find<Q>(&self, key: &Q) -> Option<&StoredEntry<K, V, M>>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let (idx, pos) = self.locate(key)?;
+        self.backing[idx]
+            .as_ref()
+            .map(|chain| &chain.as_slice()[pos])
+    }
+
+    fn find_mut<Q>(&mut self, key: &Q) -> Option<&mut StoredEntry<K, V, M>>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let (idx, pos) = self.locate(key)?;
+        self.backing[idx]
+            .as_mut()
+            .map(|chain| &mut chain.as_mut_slice()[pos])
+    }
+
+    // warns about a chain that has just grown to `len`, once each time it doubles past
+    // `LONG_CHAIN`; chains that long point at a poor hasher or at keys chosen to collide
+    #[cfg(feature = "tracing")]
+    fn trace_chain_growth(&self, len: usize) {
+        if len >= LONG_CHAIN && len.is_power_of_two() {
+            tracing::warn!(
+                chain_len = len,
+                buckets = self.backing.len(),
+                entries = self.load,
+                "long collision chain"
+            );
+        }
+    }
+
+    fn resize(&mut self) {
+        self.rehash_into(self.grown_capacity());
+        self.count(|stats| {
+            stats.resizes.fetch_add(1, Relaxed);
+        });
+    }
+
+    // resizes by exponentially doubling the capacity, allocating lazily created maps
+    fn grown_capacity(&self) -> usize {
+        match self.capacity() {
+            0 => initial_buckets(),
+            capacity => capacity.saturating_mul(2),
+        }
+    }
+
+    /// Like `insert`, but hands back allocation failure when the table has to grow, instead of
+    /// aborting
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<Option<V>, Error>
+    where
+        M: Default,
+    {
+        if self.over_load_factor(self.len()) {
+            let new_cap = self.grown_capacity();
+            let mut new_backing = Vec::new();
+            new_backing.try_reserve_exact(new_cap)?;
+
+            self.rehash_into_backing(new_backing, new_cap);
+            self.count(|stats| {
+                stats.resizes.fetch_add(1, Relaxed);
+            });
+        }
+
+        Ok(self.insert(key, value))
+    }
+
+    /// Rebuilds every chain at the current bucket count, dropping the allocations of chains that
+    /// have been emptied by removals
+    pub fn rehash_in_place(&mut self) {
+        self.rehash_into(self.backing.len());
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+    }
+
+    /// Rehashes into the smallest table that holds the current entries within the load factor,
+    /// with every chain's allocation shrunk to fit
+    pub fn compact(&mut self) {
+        let buckets = self.buckets_for(self.load);
+        self.rehash_into(buckets);
+
+        self.backing.shrink_to_fit();
+        for chain in self.backing.iter_mut().flatten() {
+            chain.shrink_to_fit();
+        }
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+    }
+
+    /// Shrinks the allocation of every chain with more than four slots per entry, as left behind
+    /// when most keys of a long collision chain are removed, and frees chains that have been
+    /// emptied; returns the number of bytes freed
+    ///
+    /// Nothing is rehashed, so this is much cheaper than `compact` and suits being called
+    /// periodically, e.g. once `chain_slack_bytes` passes some budget.
+    pub fn shrink_chains(&mut self) -> usize {
+        let mut freed = 0;
+
+        let words = self
+            .backing
+            .chunks_mut(BUCKETS_PER_WORD)
+            .zip(&mut self.occupied);
+        for (buckets, word) in words.filter(|(_, word)| **word != 0) {
+            for (bit, bucket) in buckets.iter_mut().enumerate() {
+                let Some(chain) = bucket else { continue };
+                let before = chain.capacity();
+
+                if chain.as_slice().is_empty() {
+                    *bucket = None;
+                    *word &= !(1 << bit);
+                } else if before > chain.as_slice().len() * CHAIN_SLACK_RATIO {
+                    chain.shrink_to_fit();
+                } else {
+                    continue;
+                }
+
+                freed += before - bucket.as_ref().map_or(0, B::capacity);
+            }
+        }
+
+        freed * mem::size_of::<StoredEntry<K, V, M>>()
+    }
+
+    /// The bytes allocated to chains beyond what their entries take up
+    pub fn chain_slack_bytes(&self) -> usize {
+        let slots = self
+            .chains_in(0..self.backing.len())
+            .map(|chain| chain.capacity() - chain.as_slice().len())
+            .sum::<usize>();
+        slots * mem::size_of::<StoredEntry<K, V, M>>()
+    }
+
+    // the fewest buckets that hold `len` entries within the load factor
+    fn buckets_for(&self, len: usize) -> usize {
+        len.saturating_mul(self.load_factor.buckets)
+            .div_ceil(self.load_factor.entries)
+            .max(1)
+    }
+
+    /// Grows the table so that `additional` more keys fit without a resize
+    pub fn reserve(&mut self, additional: usize) {
+        let buckets = self.buckets_for(self.load.saturating_add(additional));
+        if additional > 0 && buckets > self.backing.len() {
+            self.rehash_into(buckets);
+        }
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+    }
+
+    /// Like `reserve`, but hands back allocation failure instead of aborting
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let Some(len) = self.load.checked_add(additional) else {
+            // too many entries to count can't be allocated either; a request past `isize::MAX`
+            // bytes is the only way to produce the matching error
+            return Vec::<u8>::new().try_reserve(usize::MAX);
+        };
+
+        let buckets = self.buckets_for(len);
+        if additional > 0 && buckets > self.backing.len() {
+            let mut new_backing = Vec::new();
+            new_backing.try_reserve_exact(buckets)?;
+            self.rehash_into_backing(new_backing, buckets);
+        }
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        Ok(())
+    }
+
+    /// Shrinks the table as far as the current entries and `min_capacity` allow
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        let buckets = self.buckets_for(self.load.max(min_capacity));
+        if buckets < self.backing.len() {
+            self.rehash_into(buckets);
+            self.backing.shrink_to_fit();
+        }
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+    }
+
+    fn rehash_into(&mut self, new_cap: usize) {
+        self.rehash_into_backing(Vec::with_capacity(new_cap), new_cap);
+    }
+
+    fn rehash_into_backing(&mut self, mut new_backing: Vec<Option<B>>, new_cap: usize) {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "rehash",
+            entries = self.load,
+            from = self.backing.len(),
+            to = new_cap
+        )
+        .entered();
+
+        // hash every key before moving anything: if a `Hash` impl panics part way through, the
+        // map is left exactly as it was. walks the buckets directly rather than through `iter`,
+        // whose order may be randomized, so the indices line up with the moves below
+        let indices = self
+            .backing
+            .iter()
+            .flatten()
+            .flat_map(B::as_slice)
+            .map(|(key, _, _)| {
+                let hash = self.hash_builder.hash_one(key);
+                (bucket_index(hash, new_cap), hash)
+            })
+            .collect::<Vec<_>>();
+
+        // fill the new backing
+        for _ in 0..new_cap {
+            new_backing.push(None);
+        }
+
+        // replace the old backing and extract it; no user code runs from here on, and keys are
+        // already known to be distinct, so entries are moved without comparing them
+        let old_backing = mem::replace(&mut self.backing, new_backing);
+        self.occupied.clear();
+        self.fingerprints.clear();
+
+        let entries = old_backing.into_iter().flatten().flat_map(B::into_vec);
+        for (entry, (idx, hash)) in entries.zip(indices) {
+            self.mark_fingerprint(idx, hash);
+            self.chain_mut(idx).push(entry);
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!("rehashed");
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.remove_with_meta_inner(key)
+            .map(|(key, value, _)| (key, value))
+    }
+
+    /// Removes an entry, returning its value and metadata
+    pub fn remove_with_meta<Q>(&mut self, key: &Q) -> Option<(V, M)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.remove_with_meta_inner(key)
+            .map(|(_, value, meta)| (value, meta))
+    }
+
+    fn remove_with_meta_inner<Q>(&mut self, key: &Q) -> Option<StoredEntry<K, V, M>>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let result = self.remove_entry_inner(key);
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        result
+    }
+
+    fn remove_entry_inner<Q>(&mut self, key: &Q) -> Option<StoredEntry<K, V, M>>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let idx = self.get_index(key);
+
+        let indices_vec = self
+            .backing
+            .get(idx)?
+            .as_ref()?
+            .as_slice()
+            .iter()
+            .enumerate()
+            .filter(|item: &(usize, &StoredEntry<K, V, M>)| key.equivalent(&item.1 .0))
+            .map(|item: (usize, &StoredEntry<K, V, M>)| item.0)
+            .collect::<Vec<usize>>();
+
+        indices_vec.first().and_then(|internal_idx| {
+            let item = self.backing[idx]
+                .as_mut()
+                .map(|chain| chain.remove(*internal_idx));
+            if item.is_some() {
+                self.load -= 1;
+                self.count(|stats| {
+                    stats.removes.fetch_add(1, Relaxed);
+                });
+            }
+
+            item
+        })
+    }
+
+    /// Removes the value related to the given key, returning an Option containing its value if it
+    /// is present
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.remove_entry(key).map(|entry| entry.1)
+    }
+
+    /// Removes every entry whose key is in `keys`, returning the removed pairs in no particular
+    /// order
+    ///
+    /// The keys are grouped by bucket first, so each chain is walked once however many of the
+    /// keys land in it, rather than once per key as with `remove` in a loop.
+    pub fn remove_batch<'q, Q, I>(&mut self, keys: I) -> Vec<(K, V)>
+    where
+        I: IntoIterator<Item = &'q Q>,
+        Q: ?Sized + hash::Hash + Equivalent<K> + 'q,
+    {
+        let mut keys = keys
+            .into_iter()
+            .map(|key| (self.get_index(key), key))
+            .collect::<Vec<_>>();
+        keys.sort_unstable_by_key(|(idx, _)| *idx);
+
+        let mut removed = Vec::new();
+        for group in keys.chunk_by(|a, b| a.0 == b.0) {
+            let Some(chain) = self.backing.get_mut(group[0].0).and_then(Option::as_mut) else {
+                continue;
+            };
+
+            chain.extract_into(
+                |item| group.iter().any(|(_, key)| key.equivalent(&item.0)),
+                &mut removed,
+            );
+        }
+        let removed = removed
+            .into_iter()
+            .map(|(key, value, _)| (key, value))
+            .collect::<Vec<_>>();
+
+        self.load -= removed.len();
+        self.count(|stats| {
+            stats.removes.fetch_add(removed.len() as u64, Relaxed);
+        });
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        removed
+    }
+
+    /// Removes an entry by a borrowed key, handing back the stored key, like `HashSet::take`;
+    /// lets interners recover the owned key they handed out
+    pub fn take<Q>(&mut self, key: &Q) -> Option<K>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.remove_entry(key).map(|entry| entry.0)
+    }
+
+    /// Moves every entry into `other`, which may use a different hasher, leaving this map empty
+    /// with its buckets still allocated
+    pub fn drain_into<S2, B2>(&mut self, other: &mut ChainingHashMap<K, V, S2, M, B2>)
+    where
+        S2: hash::BuildHasher,
+        B2: Bucket<StoredEntry<K, V, M>>,
+    {
+        let words = self
+            .backing
+            .chunks_mut(BUCKETS_PER_WORD)
+            .zip(&mut self.occupied);
+        for (buckets, word) in words.filter(|(_, word)| **word != 0) {
+            for (bit, bucket) in buckets.iter_mut().enumerate() {
+                let Some(chain) = bucket.take() else { continue };
+                *word &= !(1 << bit);
+
+                // keeps `load` honest if hashing into `other` panics part way through
+                let chain = chain.into_vec();
+                self.load -= chain.len();
+                for (key, value, meta) in chain {
+                    other.insert_with_meta(key, value, meta);
+                }
+            }
+        }
+        self.fingerprints.clear();
+    }
+
+    /// Clones every entry into a new map using the given hasher, sized up front so it never
+    /// resizes while being filled
+    pub fn clone_into_with_hasher<S2>(&self, hash_builder: S2) -> ChainingHashMap<K, V, S2, M, B>
+    where
+        K: Clone,
+        V: Clone,
+        M: Clone,
+        S2: hash::BuildHasher,
+    {
+        let mut map = ChainingHashMap::with_metadata_and_hasher(self.len().max(1), hash_builder);
+
+        let chains = self.chains_in(0..self.backing.len());
+        for (key, value, meta) in chains.flat_map(B::as_slice) {
+            map.insert_with_meta(key.clone(), value.clone(), meta.clone());
+        }
+
+        map
+    }
+
+    // removes the first entry (in bucket order) the predicate accepts; lets wrappers in this
+    // crate pick a victim without cloning its key out first
+    pub(crate) fn remove_first_where<F>(&mut self, mut predicate: F) -> Option<(K, V)>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let removed = self.chains_mut().find_map(|chain| {
+            let pos = chain
+                .as_slice()
+                .iter()
+                .position(|(key, value, _)| predicate(key, value))?;
+            let (key, value, _) = chain.remove(pos);
+            Some((key, value))
+        });
+
+        if removed.is_some() {
+            self.load -= 1;
+        }
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        removed
+    }
+
+    /// Panics if an entry is outside its bucket or missing from that bucket's fingerprint filter, a
+    /// key repeats, the occupancy bitmap disagrees with the allocated chains, or `len()` is off
+    #[cfg(any(test, feature = "validate"))]
+    pub fn debug_validate(&self) {
+        let mut seen = std::collections::HashSet::with_capacity(self.load);
+
+        for (idx, chain) in self.backing.iter().enumerate() {
+            let Some(chain) = chain else { continue };
+
+            for item in chain.as_slice() {
+                let (hash, expected) = self.hash_and_index(&item.0);
+                assert_eq!(expected, idx, "entry is stored in the wrong bucket");
+                assert!(
+                    self.fingerprints.get(idx).copied().unwrap_or(0) & fingerprint(hash) != 0,
+                    "entry is missing from its bucket's fingerprint filter"
+                );
+                assert!(seen.insert(&item.0), "key is stored more than once");
+            }
+        }
+
+        for (idx, chain) in self.backing.iter().enumerate() {
+            let word = self
+                .occupied
+                .get(idx / BUCKETS_PER_WORD)
+                .copied()
+                .unwrap_or(0);
+            assert_eq!(
+                word >> (idx % BUCKETS_PER_WORD) & 1 == 1,
+                chain.is_some(),
+                "occupancy bitmap does not match the allocated chains"
+            );
+        }
+        assert!(
+            occupied_buckets(&self.occupied, self.backing.len()..usize::MAX)
+                .next()
+                .is_none(),
+            "occupancy bitmap marks buckets past the end of the table"
+        );
+
+        assert_eq!(
+            seen.len(),
+            self.load,
+            "load does not match the number of stored entries"
+        );
+    }
+}
+
+/// An iterator over a `ChainingHashMap`'s entries, from `ChainingHashMap::iter`.
+///
+/// Walks the buckets in order, skipping empty ones a word of the occupancy bitmap at a time.
+pub struct Iter<'a, K, V, M = (), B = Vec<StoredEntry<K, V, M>>> {
+    backing: &'a [Option<B>],
+    // two runs, so iteration can start part way through the table and wrap around
+    buckets: std::iter::Chain<OccupiedBuckets<'a>, OccupiedBuckets<'a>>,
+    chain: std::slice::Iter<'a, StoredEntry<K, V, M>>,
+    remaining: usize,
+}
+
+impl<'a, K, V, M, B: Bucket<StoredEntry<K, V, M>>> Iterator for Iter<'a, K, V, M, B> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            if let Some(item) = self.chain.next() {
+                self.remaining -= 1;
+                return Some((&item.0, &item.1));
+            }
+
+            let idx = self.buckets.next()?;
+            if let Some(chain) = &self.backing[idx] {
+                self.chain = chain.as_slice().iter();
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V, M, B: Bucket<StoredEntry<K, V, M>>> ExactSizeIterator for Iter<'_, K, V, M, B> {}
+
+impl<K, V, M, B: Bucket<StoredEntry<K, V, M>>> std::iter::FusedIterator for Iter<'_, K, V, M, B> {}
+
+impl<K, V, M, B> Clone for Iter<'_, K, V, M, B> {
+    fn clone(&self) -> Self {
+        Iter {
+            backing: self.backing,
+            buckets: self.buckets.clone(),
+            chain: self.chain.clone(),
+            remaining: self.remaining,
+        }
+    }
+}
+
+impl<K, V, M, B> std::fmt::Debug for Iter<'_, K, V, M, B>
+where
+    K: std::fmt::Debug,
+    V: std::fmt::Debug,
+    B: Bucket<StoredEntry<K, V, M>>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+crate::iter::keys_and_values!(
+    ChainingHashMap,
+    Iter<K, V, M = (), B = Vec<StoredEntry<K, V, M>>> where B: Bucket<StoredEntry<K, V, M>>
+);
+
+/// A mutable iterator over a `ChainingHashMap`'s entries, from `ChainingHashMap::iter_mut`.
+///
+/// Visits the buckets in the same order as `Iter`.
+pub struct IterMut<'a, K, V, M = (), B = Vec<StoredEntry<K, V, M>>> {
+    buckets: std::iter::Chain<OccupiedBuckets<'a>, OccupiedBuckets<'a>>,
+    // the table split where iteration starts, so each half can hand out its buckets in order
+    back: UnvisitedBuckets<'a, B>,
+    front: UnvisitedBuckets<'a, B>,
+    start: usize,
+    chain: std::slice::IterMut<'a, StoredEntry<K, V, M>>,
+    remaining: usize,
+}
+
+// a run of buckets not yet visited, borrowed mutably; each one handed out is split off the front,
+// so the borrows never overlap
+struct UnvisitedBuckets<'a, B> {
+    rest: &'a mut [Option<B>],
+    // the index of `rest[0]` in the table
+    first: usize,
+}
+
+impl<'a, B> UnvisitedBuckets<'a, B> {
+    // bucket `idx`, which must come after every bucket taken before it
+    fn take(&mut self, idx: usize) -> &'a mut Option<B> {
+        let (bucket, rest) = mem::take(&mut self.rest)[idx - self.first..]
+            .split_first_mut()
+            .expect("occupied buckets exist");
+        self.rest = rest;
+        self.first = idx + 1;
+        bucket
+    }
+}
+
+impl<'a, K, V, M, B: Bucket<StoredEntry<K, V, M>>> Iterator for IterMut<'a, K, V, M, B> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        loop {
+            if let Some(item) = self.chain.next() {
+                self.remaining -= 1;
+                return Some((&item.0, &mut item.1));
+            }
+
+            let idx = self.buckets.next()?;
+            let bucket = if idx >= self.start {
+                self.back.take(idx)
+            } else {
+                self.front.take(idx)
+            };
+            if let Some(chain) = bucket {
+                self.chain = chain.as_mut_slice().iter_mut();
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V, M, B: Bucket<StoredEntry<K, V, M>>> ExactSizeIterator for IterMut<'_, K, V, M, B> {}
+
+impl<K, V, M, B: Bucket<StoredEntry<K, V, M>>> std::iter::FusedIterator
+    for IterMut<'_, K, V, M, B>
+{
+}
+
+impl<K, V, M, B> std::fmt::Debug for IterMut<'_, K, V, M, B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IterMut")
+            .field("remaining", &self.remaining)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An iterator moving the entries out of a `ChainingHashMap`, from its `IntoIterator` impl.
+///
+/// Takes the buckets over one at a time, dropping each one's metadata and freeing its chain as
+/// soon as it has been emptied.
+pub struct IntoIter<K, V, M = (), B = Vec<StoredEntry<K, V, M>>> {
+    buckets: std::vec::IntoIter<Option<B>>,
+    chain: std::vec::IntoIter<StoredEntry<K, V, M>>,
+    remaining: usize,
+}
+
+impl<K, V, M, B: Bucket<StoredEntry<K, V, M>>> Iterator for IntoIter<K, V, M, B> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        loop {
+            if let Some((key, value, _)) = self.chain.next() {
+                self.remaining -= 1;
+                return Some((key, value));
+            }
+
+            if let Some(chain) = self.buckets.next()? {
+                self.chain = chain.into_vec().into_iter();
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V, M, B: Bucket<StoredEntry<K, V, M>>> ExactSizeIterator for IntoIter<K, V, M, B> {}
+
+impl<K, V, M, B: Bucket<StoredEntry<K, V, M>>> std::iter::FusedIterator for IntoIter<K, V, M, B> {}
+
+impl<K, V, M, B> std::fmt::Debug for IntoIter<K, V, M, B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IntoIter")
+            .field("remaining", &self.remaining)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K, V, S, M, B: Bucket<StoredEntry<K, V, M>>> IntoIterator for ChainingHashMap<K, V, S, M, B> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V, M, B>;
+
+    fn into_iter(self) -> IntoIter<K, V, M, B> {
+        // start where `iter` would
+        let mut backing = self.backing;
+        let start = iteration_start(backing.len());
+        backing.rotate_left(start);
+
+        IntoIter {
+            buckets: backing.into_iter(),
+            chain: Vec::new().into_iter(),
+            remaining: self.load,
+        }
+    }
+}
+
+impl<'a, K, V, S, M, B: Bucket<StoredEntry<K, V, M>>> IntoIterator
+    for &'a ChainingHashMap<K, V, S, M, B>
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V, M, B>;
+
+    fn into_iter(self) -> Iter<'a, K, V, M, B> {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S, M, B: Bucket<StoredEntry<K, V, M>>> IntoIterator
+    for &'a mut ChainingHashMap<K, V, S, M, B>
+{
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V, M, B>;
+
+    fn into_iter(self) -> IterMut<'a, K, V, M, B> {
+        self.iter_mut()
+    }
+}
+
+/// A view into one key's place in a `ChainingHashMap`, from `ChainingHashMap::entry`.
+pub enum Entry<'a, K, V, S = hash::RandomState, M = (), B = Vec<StoredEntry<K, V, M>>> {
+    Occupied(OccupiedEntry<'a, K, V, S, M, B>),
+    Vacant(VacantEntry<'a, K, V, S, M, B>),
+}
+
+/// An entry for a key that is in the map.
+pub struct OccupiedEntry<'a, K, V, S = hash::RandomState, M = (), B = Vec<StoredEntry<K, V, M>>> {
+    map: &'a mut ChainingHashMap<K, V, S, M, B>,
+    hash: u64,
+    // the bucket and chain position of the entry
+    idx: usize,
+    pos: usize,
+}
+
+/// An entry for a key that isn't in the map.
+pub struct VacantEntry<'a, K, V, S = hash::RandomState, M = (), B = Vec<StoredEntry<K, V, M>>> {
+    map: &'a mut ChainingHashMap<K, V, S, M, B>,
+    key: K,
+    hash: u64,
+    idx: usize,
+}
+
+impl<'a, K, V, S, M, B> Entry<'a, K, V, S, M, B>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+    M: Default,
+    B: Bucket<StoredEntry<K, V, M>>,
+{
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = default(entry.key());
+                entry.insert(value)
+            }
+        }
+    }
+
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Sets the value, returning the now occupied entry
+    pub fn insert_entry(self, value: V) -> OccupiedEntry<'a, K, V, S, M, B> {
+        match self {
+            Entry::Occupied(mut entry) => {
+                entry.insert(value);
+                entry
+            }
+            Entry::Vacant(entry) => entry.insert_entry(value),
+        }
+    }
+}
+
+impl<K, V, S, M, B: Bucket<StoredEntry<K, V, M>>> Entry<'_, K, V, S, M, B> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// The key's hash, as computed by the map's hasher for the lookup; see
+    /// `OccupiedEntry::hash`
+    pub fn hash(&self) -> u64 {
+        match self {
+            Entry::Occupied(entry) => entry.hash(),
+            Entry::Vacant(entry) => entry.hash(),
+        }
+    }
+
+    /// Runs `f` on the value if the key is present, passing the entry on either way
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<'a, K, V, S, M, B: Bucket<StoredEntry<K, V, M>>> OccupiedEntry<'a, K, V, S, M, B> {
+    fn item(&self) -> &StoredEntry<K, V, M> {
+        &self.map.backing[self.idx]
+            .as_ref()
+            .expect("occupied entries have a chain")
+            .as_slice()[self.pos]
+    }
+
+    fn item_mut(&mut self) -> &mut StoredEntry<K, V, M> {
+        &mut self.map.backing[self.idx]
+            .as_mut()
+            .expect("occupied entries have a chain")
+            .as_mut_slice()[self.pos]
+    }
+
+    /// The key stored in the map, rather than the one passed to `entry`
+    pub fn key(&self) -> &K {
+        &self.item().0
+    }
+
+    /// The key's hash, computed once by `entry`, e.g. to place the entry in a secondary index
+    /// built with the same hasher without hashing the key again
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn get(&self) -> &V {
+        &self.item().1
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.item_mut().1
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.map.backing[self.idx]
+            .as_mut()
+            .expect("occupied entries have a chain")
+            .as_mut_slice()[self.pos]
+            .1
+    }
+
+    /// Replaces the value, returning the old one
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    pub fn remove_entry(self) -> (K, V)
+    where
+        K: Eq + hash::Hash,
+        S: hash::BuildHasher,
+    {
+        let (key, value, _) = self.map.backing[self.idx]
+            .as_mut()
+            .expect("occupied entries have a chain")
+            .remove(self.pos);
+        self.map.load -= 1;
+        self.map.count(|stats| {
+            stats.removes.fetch_add(1, Relaxed);
+        });
+
+        #[cfg(any(test, feature = "validate"))]
+        self.map.debug_validate();
+
+        (key, value)
+    }
+
+    pub fn remove(self) -> V
+    where
+        K: Eq + hash::Hash,
+        S: hash::BuildHasher,
+    {
+        self.remove_entry().1
+    }
+}
+
+impl<'a, K, V, S, M, B> VacantEntry<'a, K, V, S, M, B> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// The key's hash, computed once by `entry` and reused by `insert`
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Inserts the value, with default metadata, into the bucket found by the lookup
+    pub fn insert(self, value: V) -> &'a mut V
+    where
+        K: Eq + hash::Hash,
+        S: hash::BuildHasher,
+        M: Default,
+        B: Bucket<StoredEntry<K, V, M>>,
+    {
+        self.insert_entry(value).into_mut()
+    }
+
+    /// Like `insert`, but returns the new entry, to go on reading or removing it
+    pub fn insert_entry(self, value: V) -> OccupiedEntry<'a, K, V, S, M, B>
+    where
+        K: Eq + hash::Hash,
+        S: hash::BuildHasher,
+        M: Default,
+        B: Bucket<StoredEntry<K, V, M>>,
+    {
+        let map = self.map;
+        map.mark_fingerprint(self.idx, self.hash);
+        let chain = map.chain_mut(self.idx);
+        chain.push((self.key, value, M::default()));
+        let pos = chain.as_slice().len() - 1;
+
+        map.load += 1;
+        #[cfg(feature = "tracing")]
+        map.trace_chain_growth(pos + 1);
+        map.count(|stats| {
+            stats.inserts.fetch_add(1, Relaxed);
+        });
+
+        #[cfg(any(test, feature = "validate"))]
+        map.debug_validate();
+
+        OccupiedEntry {
+            map,
+            hash: self.hash,
+            idx: self.idx,
+            pos,
+        }
+    }
+}
+
+impl<K, V, S, M, B> std::fmt::Debug for Entry<'_, K, V, S, M, B>
+where
+    K: std::fmt::Debug,
+    V: std::fmt::Debug,
+    B: Bucket<StoredEntry<K, V, M>>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Entry::Occupied(entry) => f.debug_tuple("Entry").field(entry).finish(),
+            Entry::Vacant(entry) => f.debug_tuple("Entry").field(entry).finish(),
+        }
+    }
+}
+
+impl<K, V, S, M, B> std::fmt::Debug for OccupiedEntry<'_, K, V, S, M, B>
+where
+    K: std::fmt::Debug,
+    V: std::fmt::Debug,
+    B: Bucket<StoredEntry<K, V, M>>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OccupiedEntry")
+            .field("key", self.key())
+            .field("value", self.get())
+            .finish()
+    }
+}
+
+impl<K: std::fmt::Debug, V, S, M, B> std::fmt::Debug for VacantEntry<'_, K, V, S, M, B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("VacantEntry").field(&self.key).finish()
+    }
+}
+
+impl<K, S, M> ChainingHashMap<K, usize, S, M>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+    M: Default,
+{
+    /// Counts every item into the map, adding to any existing counts
+    ///
+    /// For borrowed `Copy` keys, pass `iter.copied()`.
+    pub fn count_items<I>(&mut self, items: I)
+    where
+        I: IntoIterator<Item = K>,
+    {
+        for item in items {
+            *self.get_or_insert_with(item, || 0) += 1;
+        }
+    }
+}
+
+impl<K, V, S> ChainingHashMap<K, V, S>
+where
+    K: Eq + hash::Hash + Send,
+    V: Send,
+    S: hash::BuildHasher + Default + Sync,
+{
+    /// Builds a map from `entries` using every available core; see
+    /// `from_iter_parallel_with_hasher`
+    pub fn from_iter_parallel<I>(entries: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let threads = std::thread::available_parallelism().map_or(1, usize::from);
+        ChainingHashMap::from_iter_parallel_with_hasher(entries, S::default(), threads)
+    }
+}
+
+impl<K, V, S> ChainingHashMap<K, V, S>
+where
+    K: Eq + hash::Hash + Send,
+    V: Send,
+    S: hash::BuildHasher + Sync,
+{
+    /// Builds a map from `entries` on up to `threads` threads, with the same result as inserting
+    /// them one by one: a repeated key keeps its last value
+    ///
+    /// The table is sized for every entry up front and its buckets are divided into one
+    /// contiguous range per thread. The input is hashed in parallel and sorted by range, then
+    /// each thread fills its own range of buckets, so no locking is needed and nothing is
+    /// rehashed.
+    pub fn from_iter_parallel_with_hasher<I>(entries: I, hash_builder: S, threads: usize) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let entries = entries.into_iter().collect::<Vec<_>>();
+        let mut map = ChainingHashMap::with_capacity_and_hasher(entries.len(), hash_builder);
+
+        let buckets = map.backing.len();
+        if buckets == 0 {
+            return map;
+        }
+        let threads = threads.clamp(1, entries.len());
+        let per_range = buckets.div_ceil(threads);
+
+        let chunk_len = entries.len().div_ceil(threads);
+        let mut entries = entries.into_iter();
+        let chunks = (0..threads)
+            .map(|_| entries.by_ref().take(chunk_len).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        map.fingerprints.resize(buckets, 0);
+        let ChainingHashMap {
+            backing,
+            fingerprints,
+            load,
+            hash_builder,
+            ..
+        } = &mut map;
+
+        std::thread::scope(|scope| {
+            // hash each chunk, sorting its entries into one outbox per bucket range
+            let sorted = chunks
+                .into_iter()
+                .map(|chunk| {
+                    let hash_builder = &*hash_builder;
+                    scope.spawn(move || {
+                        let mut outboxes = (0..threads).map(|_| Vec::new()).collect::<Vec<_>>();
+                        for (key, value) in chunk {
+                            let hash = hash_builder.hash_one(&key);
+                            let idx = bucket_index(hash, buckets);
+                            outboxes[idx / per_range].push((idx, hash, key, value));
+                        }
+                        outboxes
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            // gathered in chunk order, so each range sees its entries in input order
+            let mut inboxes = (0..threads).map(|_| Vec::new()).collect::<Vec<_>>();
+            for handle in sorted {
+                let outboxes = handle
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+                for (inbox, outbox) in inboxes.iter_mut().zip(outboxes) {
+                    inbox.push(outbox);
+                }
+            }
+
+            let filled = backing
+                .chunks_mut(per_range)
+                .zip(fingerprints.chunks_mut(per_range))
+                .zip(inboxes)
+                .enumerate()
+                .map(|(range, ((range_buckets, range_filters), inbox))| {
+                    scope.spawn(move || {
+                        let start = range * per_range;
+                        let mut added = 0;
+
+                        for (idx, hash, key, value) in inbox.into_iter().flatten() {
+                            range_filters[idx - start] |= fingerprint(hash);
+                            let chain = range_buckets[idx - start].get_or_insert_with(Vec::new);
+                            match chain.iter_mut().find(|item| item.0 == key) {
+                                Some(item) => item.1 = value,
+                                None => {
+                                    chain.push((key, value, ()));
+                                    added += 1;
+                                }
+                            }
+                        }
+
+                        added
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            for handle in filled {
+                *load += handle
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+            }
+        });
+
+        // the threads only see their own range of buckets, so the bitmap is filled in after
+        for idx in 0..buckets {
+            if map.backing[idx].is_some() {
+                map.chain_mut(idx);
+            }
+        }
+
+        #[cfg(any(test, feature = "validate"))]
+        map.debug_validate();
+
+        map
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a, K, V, S> arbitrary::Arbitrary<'a> for ChainingHashMap<K, V, S>
+where
+    K: arbitrary::Arbitrary<'a> + Eq + hash::Hash,
+    V: arbitrary::Arbitrary<'a>,
+    S: hash::BuildHasher + Default,
+{
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        // the configuration is drawn first so it stays stable as the fuzzer grows the contents
+        let capacity = u.int_in_range(1..=64)?;
+        let load_factor = LoadFactor {
+            entries: u.int_in_range(25..=95)?,
+            buckets: 100,
+        };
+
+        let mut map = ChainingHashMap {
+            backing: make_backing_with_capacity(capacity, load_factor),
+            occupied: Vec::new(),
+            fingerprints: Vec::new(),
+            load: 0,
+            load_factor,
+            hash_builder: S::default(),
+            stats: None,
+            entries: PhantomData,
+        };
+
+        for entry in u.arbitrary_iter::<(K, V)>()? {
+            let (key, value) = entry?;
+            map.insert(key, value);
+        }
+
+        Ok(map)
+    }
+}
+
+// TODO: implement benchmarks for insert/get
+// See: https://doc.rust-lang.org/unstable-book/library-features/test.html
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::testkit::map_conformance_tests!(ChainingHashMap::<usize, usize>::new());
+
+    mod boxed_buckets {
+        use super::*;
+
+        crate::testkit::map_conformance_tests!(ChainingHashMap::<
+            usize,
+            usize,
+            hash::RandomState,
+            (),
+            Box<[_]>,
+        >::default());
+    }
+
+    #[test]
+    fn insert() {
+        let mut map = ChainingHashMap::new();
+
+        let result1 = map.insert("yes".to_string(), 123);
+        assert_eq!(result1, None);
+
+        let result2 = map.insert("no".to_string(), 456);
+        assert_eq!(result2, None);
+
+        let result3 = map.insert("yes".to_string(), 456);
+        assert_eq!(result3.unwrap(), 123);
+
+        assert_eq!(map.len(), 2)
+    }
+
+    #[test]
+    fn insert_and_resize() {
+        let cap = 10;
+        let mut map = ChainingHashMap::with_capacity(cap);
+
+        for i in 0..cap {
+            let result = map.insert(i.to_string(), i);
+            assert_eq!(result, None);
+        }
+
+        for i in 0..cap {
+            let result = map.get(&i.to_string());
+            assert_eq!(result, Some(i).as_ref());
+        }
+    }
+
+    #[test]
+    fn get() {
+        let mut map = ChainingHashMap::new();
+
+        map.insert("yes".to_string(), 123);
+        map.insert("no".to_string(), 456);
+
+        assert_eq!(map.get(&"yes".to_string()), Some(123).as_ref());
+        assert_eq!(map.get(&"no".to_string()), Some(456).as_ref());
+        assert_eq!(map.get(&"maybe".to_string()), None.as_ref());
+    }
+
+    #[test]
+    fn get_borrowed() {
+        let mut map = ChainingHashMap::new();
+
+        map.insert("yes".to_string(), 123);
+        map.insert("no".to_string(), 456);
+
+        assert_eq!(map.get("yes"), Some(&123));
+        assert_eq!(map.get("maybe"), None);
+
+        if let Some(value) = map.get_mut("no") {
+            *value += 1;
+        }
+
+        assert_eq!(map.remove("no"), Some(457));
+        assert_eq!(map.remove_entry("yes"), Some(("yes".to_string(), 123)));
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn get_mut() {
+        let mut map = ChainingHashMap::new();
+
+        map.insert("yes".to_string(), 123);
+
+        assert_eq!(map.get(&"yes".to_string()), Some(123).as_ref());
+
+        let item = map.get_mut(&"yes".to_string());
+
+        assert!(item.is_some(), "Expected entry is not present");
+
+        if let Some(value) = item {
+            *value += 1;
+        }
+
+        assert_eq!(map.get(&"yes".to_string()), Some(124).as_ref());
+    }
+
+    #[test]
+    fn remove_entry() {
+        let cap = 10;
+        let mut map = ChainingHashMap::with_capacity(cap);
+
+        for i in 0..cap {
+            let result = map.insert(i.to_string(), i);
+            assert_eq!(result, None);
+        }
+
+        assert_eq!(map.remove(&"100".to_string()), None);
+
+        for i in 0..cap {
+            let result = map.remove_entry(&i.to_string());
+            assert_eq!(result.unwrap(), (i.to_string(), i));
+        }
+    }
+
+    #[test]
+    fn remove() {
+        let cap = 10;
+        let mut map = ChainingHashMap::with_capacity(cap);
+
+        for i in 0..cap {
+            let result = map.insert(i.to_string(), i);
+            assert_eq!(result, None);
+        }
+
+        assert_eq!(map.remove(&"100".to_string()), None);
+
+        for i in 0..cap {
+            let result = map.remove(&i.to_string());
+            assert_eq!(result.unwrap(), i);
+        }
+    }
+
+    #[test]
+    fn grow_and_shrink_len() {
+        let cap = 10;
+        let mut map = ChainingHashMap::with_capacity(cap);
+
+        for i in 0..cap {
+            let result = map.insert(i.to_string(), i);
+            assert_eq!(result, None);
+            assert_eq!(map.len(), i + 1);
+        }
+
+        assert_eq!(map.remove(&"100".to_string()), None);
+
+        for i in 0..cap {
+            let result = map.remove(&i.to_string());
+            assert_eq!(result.unwrap(), i);
+            assert_eq!(map.len(), 10 - i - 1);
+        }
+
+        for i in 0..cap {
+            let result = map.insert(i.to_string(), i);
+            assert_eq!(result, None);
+            assert_eq!(map.len(), i + 1);
+        }
+
+        assert_eq!(map.remove(&"100".to_string()), None);
+
+        for i in 0..cap {
+            let result = map.remove_entry(&i.to_string());
+            assert_eq!(result.unwrap(), (i.to_string(), i));
+            assert_eq!(map.len(), 10 - i - 1);
+        }
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn arbitrary() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        // odd bytes keep `arbitrary_iter` asking for more entries
+        let bytes = (0..4096).map(|i| (i * 31) as u8 | 1).collect::<Vec<u8>>();
+        let mut u = Unstructured::new(&bytes);
+
+        let map = ChainingHashMap::<u8, u16>::arbitrary(&mut u).unwrap();
+
+        assert!(!map.is_empty());
+        assert!(map.load_factor() >= 0.25 && map.load_factor() <= 0.95);
+        assert_eq!(map.iter().count(), map.len());
+    }
+
+    #[test]
+    #[should_panic(expected = "load does not match")]
+    fn debug_validate_catches_bad_load() {
+        let mut map = ChainingHashMap::new();
+
+        map.insert(1, 1);
+        map.load += 1;
+
+        map.debug_validate();
+    }
+
+    #[test]
+    #[should_panic(expected = "wrong bucket")]
+    fn debug_validate_catches_misplaced_entry() {
+        let mut map = ChainingHashMap::with_capacity(10);
+
+        map.insert(1, 1);
+
+        let idx = map.get_index(&1);
+        let entry = map.backing[idx].take().unwrap();
+        let other = (idx + 1) % map.backing.len();
+        map.backing[other] = Some(entry);
+
+        map.debug_validate();
+    }
+
+    #[test]
+    #[should_panic(expected = "more than once")]
+    fn debug_validate_catches_duplicates() {
+        let mut map = ChainingHashMap::new();
+
+        map.insert(1, 1);
+
+        let idx = map.get_index(&1);
+        map.backing[idx].as_mut().unwrap().push((1, 2, ()));
+        map.load += 1;
+
+        map.debug_validate();
+    }
+
+    // hashes like `RandomState`, but panics once its countdown of `build_hasher` calls runs out
+    #[derive(Clone)]
+    struct PanickingState {
+        countdown: std::rc::Rc<std::cell::Cell<usize>>,
+        inner: hash::RandomState,
+    }
+
+    impl PanickingState {
+        fn new() -> Self {
+            PanickingState {
+                countdown: std::rc::Rc::new(std::cell::Cell::new(usize::MAX)),
+                inner: hash::RandomState::new(),
+            }
+        }
+    }
+
+    impl hash::BuildHasher for PanickingState {
+        type Hasher = hash::DefaultHasher;
+
+        fn build_hasher(&self) -> Self::Hasher {
+            let remaining = self.countdown.get();
+            if remaining == 0 {
+                panic!("hasher ran out of calls");
+            }
+            self.countdown.set(remaining - 1);
+            self.inner.build_hasher()
+        }
+    }
+
+    // a key whose `Eq` can be made to panic
+    #[derive(Debug)]
+    struct TrickyKey(usize, std::rc::Rc<std::cell::Cell<bool>>);
+
+    impl hash::Hash for TrickyKey {
+        fn hash<H: hash::Hasher>(&self, state: &mut H) {
+            self.0.hash(state);
+        }
+    }
+
+    impl PartialEq for TrickyKey {
+        fn eq(&self, other: &Self) -> bool {
+            if self.1.get() {
+                panic!("comparison panicked");
+            }
+            self.0 == other.0
+        }
+    }
+
+    impl Eq for TrickyKey {}
+
+    #[test]
+    fn panic_during_resize_leaves_map_intact() {
+        let state = PanickingState::new();
+        let countdown = state.countdown.clone();
+        let mut map = ChainingHashMap::with_capacity_and_hasher(10, state);
+
+        let mut i = 0;
+        while map.len() as f32 / map.capacity() as f32 <= map.load_factor() {
+            map.insert(i, i);
+            i += 1;
+        }
+        let capacity = map.capacity();
+
+        // the next insert resizes; let the resize hash a few keys and then blow up
+        countdown.set(3);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            map.insert(i, i);
+        }));
+        assert!(result.is_err());
+        countdown.set(usize::MAX);
+
+        assert_eq!(map.len(), i);
+        assert_eq!(map.capacity(), capacity);
+        for j in 0..i {
+            assert_eq!(map.get(&j), Some(&j));
+        }
+
+        map.insert(i, i);
+        assert_eq!(map.len(), i + 1);
+    }
+
+    #[test]
+    fn panic_in_key_comparison_leaves_chain_intact() {
+        let armed = std::rc::Rc::new(std::cell::Cell::new(false));
+        let mut map =
+            ChainingHashMap::with_capacity_and_hasher(4, crate::hashers::ConstantState::default());
+
+        for i in 0..3 {
+            map.insert(TrickyKey(i, armed.clone()), i);
+        }
+
+        armed.set(true);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            map.insert(TrickyKey(10, armed.clone()), 10);
+        }));
+        assert!(result.is_err());
+        armed.set(false);
+
+        assert_eq!(map.len(), 3);
+        for i in 0..3 {
+            assert_eq!(map.get(&TrickyKey(i, armed.clone())), Some(&i));
+        }
+    }
+
+    #[test]
+    fn bucket_index_uses_high_bits() {
+        // hashes that only differ above bit 32 still spread out
+        let buckets = (0..16u64)
+            .map(|i| bucket_index(i << 32, 28))
+            .collect::<std::collections::HashSet<_>>();
+
+        assert!(buckets.len() > 1);
+        assert_eq!(bucket_index(u64::MAX, 28), (u64::MAX % 28) as usize);
+    }
+
+    #[test]
+    fn seeded() {
+        let mut a = ChainingHashMap::with_seed(1234);
+        let mut b = ChainingHashMap::with_capacity_and_seed(0, 1234);
+
+        for i in 0..100 {
+            a.insert(i, i);
+            b.insert(i, i);
+        }
+
+        // same seed, same layout
+        assert_eq!(a.backing, b.backing);
+        assert_eq!(a.get(&50), Some(&50));
+    }
+
+    #[test]
+    fn deterministic() {
+        let mut a = ChainingHashMap::deterministic();
+        let mut b = ChainingHashMap::deterministic_with_capacity(0);
+
+        for i in 0..200 {
+            a.insert(i.to_string(), i);
+            b.insert(i.to_string(), i);
+        }
+
+        assert_eq!(a.backing, b.backing);
+        assert_eq!(
+            a.hasher(),
+            ChainingHashMap::<String, i32, _>::with_seed(DETERMINISTIC_SEED).hasher()
+        );
+    }
+
+    #[test]
+    fn sorted_iteration() {
+        let mut map = ChainingHashMap::new();
+
+        for i in [5, 3, 9, 1, 7] {
+            map.insert(i, i * 10);
+        }
+
+        let keys = map
+            .iter_sorted_by_key()
+            .map(|(k, _)| *k)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec![1, 3, 5, 7, 9]);
+
+        let by_value_desc = map
+            .iter_sorted_by(|a, b| b.1.cmp(a.1))
+            .map(|(_, v)| *v)
+            .collect::<Vec<_>>();
+        assert_eq!(by_value_desc, vec![90, 70, 50, 30, 10]);
+
+        assert_eq!(
+            map.into_sorted_vec(),
+            vec![(1, 10), (3, 30), (5, 50), (7, 70), (9, 90)]
+        );
+    }
+
+    #[test]
+    fn into_sorted_vec_by() {
+        let mut map = ChainingHashMap::new();
+
+        map.insert("b".to_string(), 1);
+        map.insert("a".to_string(), 2);
+        map.insert("c".to_string(), 0);
+
+        let sorted = map.into_sorted_vec_by(|a, b| a.1.cmp(&b.1));
+        assert_eq!(sorted[0].0, "c");
+        assert_eq!(sorted[2].0, "a");
+    }
+
+    #[cfg(feature = "randomize-iteration")]
+    #[test]
+    fn randomized_iteration_order() {
+        // seeded, so every map built here has the same layout and only the start differs
+        let build = || {
+            let mut map = ChainingHashMap::deterministic();
+            for i in 0..100 {
+                map.insert(i, i);
+            }
+            map
+        };
+        let mut map = build();
+
+        let first = map.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        let reordered = (0..50).any(|_| !map.iter().map(|(k, _)| *k).eq(first.iter().copied()));
+        assert!(reordered, "50 iterations all came out in the same order");
+
+        // mutable and consuming iteration are shuffled too, and still visit every entry once
+        let reordered = (0..50).any(|_| {
+            let keys = map.iter_mut().map(|(k, _)| *k).collect::<Vec<_>>();
+            assert_eq!(keys.len(), 100);
+            keys != first
+        });
+        assert!(
+            reordered,
+            "50 mutable iterations all came out in the same order"
+        );
+        let reordered = (0..50).any(|_| {
+            !build()
+                .into_iter()
+                .map(|(k, _)| k)
+                .eq(first.iter().copied())
+        });
+        assert!(
+            reordered,
+            "50 consuming iterations all came out in the same order"
+        );
+        map.iter_mut().for_each(|(_, value)| *value += 1);
+        assert!(map.iter().all(|(key, value)| *value == key + 1));
+
+        let mut keys = first;
+        keys.sort();
+        assert_eq!(keys, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn occupied_buckets_in_range() {
+        let occupied = [1 << 3 | 1 << 63, 0, 1 << 0 | 1 << 5];
+
+        let all = occupied_buckets(&occupied, 0..192).collect::<Vec<_>>();
+        assert_eq!(all, [3, 63, 128, 133]);
+        let middle = occupied_buckets(&occupied, 4..133).collect::<Vec<_>>();
+        assert_eq!(middle, [63, 128]);
+        assert_eq!(occupied_buckets(&occupied, 64..128).count(), 0);
+        assert_eq!(occupied_buckets(&occupied, 150..1000).count(), 0);
+    }
+
+    #[test]
+    fn sparse_iteration() {
+        let mut map = ChainingHashMap::with_capacity(100_000);
+
+        for i in 0..10 {
+            map.insert(i, i);
+        }
+        assert!(map.occupied.iter().filter(|word| **word == 0).count() > 1000);
+
+        let mut keys = map.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, (0..10).collect::<Vec<_>>());
+
+        map.values_mut().for_each(|value| *value *= 2);
+        map.retain(|k, _| k % 2 == 0);
+        assert_eq!(map.len(), 5);
+        assert_eq!(map.get(&4), Some(&8));
+
+        let mut to = ChainingHashMap::new();
+        map.drain_into(&mut to);
+        assert!(map.occupied.iter().all(|word| *word == 0));
+        assert_eq!(map.iter().count(), 0);
+        assert_eq!(to.len(), 5);
+
+        map.insert(1, 1);
+        map.clear();
+        assert!(map.occupied.is_empty());
+    }
+
+    #[test]
+    fn iter_and_iter_mut() {
+        let mut map = ChainingHashMap::with_capacity(1000);
+        assert_eq!(map.iter().len(), 0);
+        assert_eq!(map.iter_mut().next(), None);
+
+        for i in (0..1000).step_by(10) {
+            map.insert(i, i);
+        }
+
+        let mut iter = map.iter();
+        assert_eq!(iter.len(), 100);
+        iter.next();
+        assert_eq!(iter.len(), 99);
+        assert_eq!(iter.clone().count(), 99);
+        assert_eq!(iter.count(), 99);
+
+        for (key, value) in map.iter_mut() {
+            *value += key;
+        }
+        let mut iter = map.iter_mut();
+        assert_eq!(iter.len(), 100);
+        iter.nth(49);
+        assert_eq!(iter.len(), 50);
+
+        let mut pairs = map.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+        pairs.sort();
+        assert_eq!(
+            pairs,
+            (0..1000)
+                .step_by(10)
+                .map(|i| (i, i * 2))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn keys_and_values() {
+        let mut map = ChainingHashMap::new();
+        for i in 0..20 {
+            map.insert(i, i * 3);
+        }
+
+        let keys = map.keys();
+        assert_eq!(keys.len(), 20);
+        assert_eq!(keys.clone().count(), 20);
+        assert_eq!(keys.sum::<i32>(), 190);
+        assert_eq!(map.values().len(), 20);
+        assert_eq!(map.values().sum::<i32>(), 570);
+    }
+
+    #[test]
+    fn into_iter() {
+        let mut map = ChainingHashMap::with_hasher(crate::hashers::LowBitsState::new(2));
+        for i in 0..50 {
+            map.insert(i.to_string(), vec![i]);
+        }
+
+        let mut iter = map.into_iter();
+        assert_eq!(iter.len(), 50);
+        let (key, value) = iter.next().unwrap();
+        assert_eq!(key, value[0].to_string());
+        assert_eq!(iter.len(), 49);
+
+        let mut rest = iter.map(|(_, value)| value[0]).collect::<Vec<_>>();
+        rest.push(value[0]);
+        rest.sort();
+        assert_eq!(rest, (0..50).collect::<Vec<_>>());
+
+        // dropping a part consumed iterator drops the entries it didn't get to
+        let marker = std::rc::Rc::new(());
+        let mut map = ChainingHashMap::new();
+        for i in 0..10 {
+            map.insert(i, marker.clone());
+        }
+        let mut iter = map.into_iter();
+        iter.nth(4);
+        assert_eq!(std::rc::Rc::strong_count(&marker), 6);
+        drop(iter);
+        assert_eq!(std::rc::Rc::strong_count(&marker), 1);
+    }
+
+    #[test]
+    fn for_loops() {
+        let mut map = ChainingHashMap::new();
+        for i in 0..10 {
+            map.insert(i, i);
+        }
+
+        for (key, value) in &mut map {
+            *value += key;
+        }
+        let mut sum = 0;
+        for (key, value) in &map {
+            assert_eq!(*value, key * 2);
+            sum += value;
+        }
+        assert_eq!(sum, 90);
+    }
+
+    #[test]
+    fn iter_chains() {
+        let mut map = ChainingHashMap::with_hasher(crate::hashers::ConstantState::default());
+        for i in 0..20 {
+            map.insert(i, i);
+        }
+
+        assert_eq!(map.iter().len(), 20);
+        map.iter_mut().for_each(|(_, value)| *value = 0);
+        assert!(map.iter().all(|(_, value)| *value == 0));
+        assert_eq!(map.iter().map(|(k, _)| k).sum::<i32>(), 190);
+    }
+
+    #[test]
+    fn entry_layout() {
+        use std::num::NonZeroUsize;
+
+        let word = mem::size_of::<usize>();
+        // set-like maps and unused metadata store no bytes beyond the key
+        assert_eq!(
+            mem::size_of::<StoredEntry<u64, (), ()>>(),
+            mem::size_of::<u64>()
+        );
+        assert_eq!(
+            mem::size_of::<StoredEntry<String, (), ()>>(),
+            mem::size_of::<String>()
+        );
+        // values with a niche don't pay for their `None`
+        assert_eq!(
+            mem::size_of::<StoredEntry<usize, Option<NonZeroUsize>, ()>>(),
+            2 * word
+        );
+        assert_eq!(
+            mem::size_of::<StoredEntry<usize, Option<Box<u8>>, ()>>(),
+            2 * word
+        );
+        // nor do empty buckets
+        assert_eq!(
+            mem::size_of::<Option<Vec<StoredEntry<usize, (), ()>>>>(),
+            mem::size_of::<Vec<StoredEntry<usize, (), ()>>>()
+        );
+
+        let mut set = ChainingHashMap::with_hasher(crate::hashers::ConstantState::default());
+        for i in 0..100u64 {
+            set.insert(i, ());
+        }
+        for i in 2..100 {
+            set.remove(&i);
+        }
+        assert_eq!(set.shrink_chains() % mem::size_of::<u64>(), 0);
+        assert!(set.get(&1).is_some());
+    }
+
+    #[test]
+    fn shrink_chains() {
+        let mut map = ChainingHashMap::with_hasher(crate::hashers::ConstantState::default());
+
+        for i in 0..100u64 {
+            map.insert(i, i);
+        }
+        for i in 2..100 {
+            map.remove(&i);
+        }
+        let slack = map.chain_slack_bytes();
+        assert!(slack >= 98 * mem::size_of::<(u64, u64)>());
+
+        assert_eq!(map.shrink_chains(), slack);
+        assert_eq!(map.chain_slack_bytes(), 0);
+        assert_eq!(map.shrink_chains(), 0);
+        assert_eq!(map.get(&1), Some(&1));
+
+        // an emptied chain is freed outright
+        map.remove(&0);
+        map.remove(&1);
+        assert!(map.shrink_chains() > 0);
+        assert!(map.backing.iter().all(Option::is_none));
+        assert!(map.occupied.iter().all(|word| *word == 0));
+    }
+
+    #[test]
+    fn boxed_buckets_have_no_slack() {
+        let mut map = ChainingHashMap::<_, _, _, (), Box<[_]>>::with_metadata_and_hasher(
+            0,
+            crate::hashers::ConstantState::default(),
+        );
+
+        for i in 0..100u64 {
+            map.insert(i, i);
+        }
+        assert_eq!(map.chain_slack_bytes(), 0);
+
+        // every removal path keeps the chain exactly as long as its entries
+        map.remove(&0);
+        map.remove_batch(&[1, 2, 3]);
+        map.retain(|key, _| key % 2 == 0);
+        assert_eq!(map.entry(4).or_insert(0), &mut 4);
+        assert_eq!(map.len(), 48);
+        assert_eq!(map.chain_slack_bytes(), 0);
+        assert_eq!(map.shrink_chains(), 0);
+
+        let mut pairs = map.into_iter().collect::<Vec<_>>();
+        pairs.sort();
+        assert_eq!(
+            pairs,
+            (4..100).step_by(2).map(|i| (i, i)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn misses_skip_chains() {
+        let mut map = ChainingHashMap::deterministic();
+        map.enable_stats();
+
+        for i in 0..1000 {
+            map.insert(i, i);
+        }
+        map.reset_stats();
+        for i in 1000..2000 {
+            assert_eq!(map.get(&i), None);
+        }
+
+        // without the filters every miss would compare against its whole chain, most of which
+        // aren't empty at this load
+        let stats = map.stats().unwrap();
+        assert_eq!(stats.misses, 1000);
+        assert!(stats.probes < 100, "{} probes", stats.probes);
+
+        map.remove(&5);
+        assert_eq!(map.get(&5), None);
+        map.rehash_in_place();
+        assert_eq!(map.get(&6), Some(&6));
+    }
+
+    #[test]
+    fn replace_and_take() {
+        use std::rc::Rc;
+
+        let mut map = ChainingHashMap::new();
+        let first: Rc<str> = Rc::from("key");
+        let second: Rc<str> = Rc::from("key");
 
-    pub fn capacity(&self) -> usize {
-        // TODO: go over the semantics of capacity to make sure they make sense; i.e. need to make
-        // sure the rules for when reallocation happens make sense
-        self.backing.capacity()
-    }
+        assert_eq!(map.replace(first.clone(), 1), None);
+        let (displaced, value) = map.replace(second.clone(), 2).unwrap();
 
-    pub fn len(&self) -> usize {
-        self.load
-    }
+        assert!(Rc::ptr_eq(&displaced, &first));
+        assert_eq!(value, 1);
+        assert_eq!(map.get("key"), Some(&2));
 
-    pub fn is_empty(&self) -> bool {
-        self.load == 0
+        let taken = map.take("key").unwrap();
+        assert!(Rc::ptr_eq(&taken, &second));
+        assert!(map.is_empty());
+        assert_eq!(map.take("key"), None);
     }
 
-    pub fn clear(&mut self) {
-        self.load = 0;
-        self.backing.iter_mut().for_each(|x| *x = None)
-    }
+    #[test]
+    fn drain_into() {
+        let mut from = ChainingHashMap::new();
+        let mut to = ChainingHashMap::with_seed(1);
+        to.insert(0, "kept");
 
-    pub fn hasher(&self) -> &S {
-        &self.hash_builder
+        for i in 1..100 {
+            from.insert(i, "moved");
+        }
+        let buckets = from.backing.len();
+
+        from.drain_into(&mut to);
+
+        assert!(from.is_empty());
+        assert_eq!(from.backing.len(), buckets);
+        assert_eq!(to.len(), 100);
+        assert_eq!(to.get(&0), Some(&"kept"));
+        assert_eq!(to.get(&99), Some(&"moved"));
     }
-}
 
-impl<K, V, S> ChainingHashMap<K, V, S>
-where
-    K: Eq + hash::Hash,
-    S: hash::BuildHasher,
-{
-    fn get_index(&self, key: &K) -> usize {
-        // builds a hash with the instance's `hash_builder`, using the `BuildHasher` trait
-        let mut hasher = self.hash_builder.build_hasher();
+    #[test]
+    fn clone_into_with_hasher() {
+        let mut map = ChainingHashMap::with_metadata();
+
+        for i in 0..100 {
+            map.insert_with_meta(i, i * 2, i * 3);
+        }
 
-        key.hash(&mut hasher);
+        let clone = map.clone_into_with_hasher(SeededState::new(7));
+        let buckets = clone.backing.len();
 
-        use hash::Hasher;
-        hasher.finish() as usize % self.backing.capacity()
+        assert_eq!(clone.len(), 100);
+        assert_eq!(clone.get(&10), Some(&20));
+        assert_eq!(clone.get_meta(&10), Some(&30));
+        // sized for its contents up front
+        assert_eq!(buckets, (100.0 / 0.7) as usize);
+        assert_eq!(map.len(), 100);
+
+        let empty = ChainingHashMap::<i32, i32>::new().clone_into_with_hasher(SeededState::new(7));
+        assert!(empty.is_empty());
     }
 
-    // TODO: try to make this more idiomatic
-    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
-        // resize before getting index, otherwise it will be the index for the previous capacity
-        // TODO: possibly make internal insert to make the reserve/shrink functions work on
-        // reallocation
-        if self.len() as f32 / self.capacity() as f32 > self.load_factor {
-            self.resize();
+    #[test]
+    fn chain_stats() {
+        let mut map =
+            ChainingHashMap::with_capacity_and_hasher(4, crate::hashers::ConstantState::default());
+
+        assert_eq!(map.chain_stats().entries, 0);
+        assert_eq!(map.chain_stats().mean_chain(), 0.0);
+
+        for i in 0..10 {
+            map.insert(i, i);
         }
+        let stats = map.chain_stats();
 
-        let idx = self.get_index(&key);
+        assert_eq!(stats.buckets, map.backing.len());
+        assert_eq!(stats.entries, 10);
+        assert_eq!(stats.empty, stats.buckets - 1);
+        assert_eq!(stats.longest, 10);
+        assert_eq!(stats.mean_chain(), 10.0);
+    }
 
-        match mem::replace(&mut self.backing[idx], None) {
-            None => {
-                self.backing[idx] = Some(vec![(key, value)]);
-                self.load += 1;
-                None
-            }
-            Some(mut vec) => {
-                for item in vec.iter_mut() {
-                    if key == item.0 {
-                        let result = Some(mem::replace(&mut item.1, value));
-                        self.backing[idx] = Some(vec);
-                        return result;
-                    }
-                }
+    #[test]
+    fn get_or_insert_with() {
+        let mut map = ChainingHashMap::new();
 
-                vec.push((key, value));
-                self.load += 1;
+        map.get_or_insert_with("a", Vec::new).push(1);
+        map.get_or_insert_with("a", || unreachable!()).push(2);
 
-                self.backing[idx] = Some(vec);
+        assert_eq!(map.get("a"), Some(&vec![1, 2]));
+        assert_eq!(map.len(), 1);
+    }
 
-                None
+    #[test]
+    fn entry() {
+        let mut map = ChainingHashMap::new();
+
+        for word in "a b a c a b".split(' ') {
+            *map.entry(word).or_insert(0) += 1;
+        }
+        assert_eq!(map.get("a"), Some(&3));
+        assert_eq!(map.get("b"), Some(&2));
+        assert_eq!(map.len(), 3);
+
+        map.entry("b").and_modify(|count| *count *= 10).or_default();
+        map.entry("d").and_modify(|count| *count *= 10).or_default();
+        assert_eq!(map.get("b"), Some(&20));
+        assert_eq!(map.get("d"), Some(&0));
+        assert_eq!(*map.entry("ee").or_insert_with_key(|key| key.len()), 2);
+
+        match map.entry("a") {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.key(), &"a");
+                assert_eq!(entry.insert(7), 3);
+                assert_eq!(entry.remove_entry(), ("a", 7));
             }
+            Entry::Vacant(_) => unreachable!(),
         }
+        match map.entry("a") {
+            Entry::Vacant(entry) => assert_eq!(entry.into_key(), "a"),
+            Entry::Occupied(_) => unreachable!(),
+        }
+        assert_eq!(map.get("a"), None);
+        assert_eq!(map.len(), 4);
+
+        let entry = map.entry("f").insert_entry(5);
+        assert_eq!((entry.key(), entry.get()), (&"f", &5));
+        assert_eq!(entry.remove(), 5);
+        let mut entry = map.entry("b").insert_entry(2);
+        assert_eq!(entry.insert(3), 2);
+        assert_eq!(map.get("b"), Some(&3));
+        assert_eq!(map.len(), 4);
     }
 
-    /// Gets reference to value based on the input key
-    pub fn get(&self, key: &K) -> Option<&V> {
-        self.backing
-            .get(self.get_index(&key))?
-            .as_ref()?
-            .iter()
-            .find(|item| *key == item.0)
-            .map(|item| &item.1)
-    }
+    #[test]
+    fn entry_hash() {
+        use hash::BuildHasher;
+
+        let mut map = ChainingHashMap::deterministic();
+        // a secondary index over the same keys, hashed with the same hasher
+        let mut by_hash = std::collections::BTreeMap::new();
+
+        for word in ["salt", "pepper", "salt"] {
+            let hash = map.hasher().hash_one(word);
+            let entry = map.entry(word);
+            assert_eq!(entry.key(), &word);
+            assert_eq!(entry.hash(), hash);
+            match entry {
+                Entry::Vacant(entry) => {
+                    by_hash.insert(entry.hash(), word);
+                    entry.insert(1);
+                }
+                Entry::Occupied(mut entry) => *entry.get_mut() += 1,
+            }
+        }
 
-    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
-        let idx = self.get_index(&key);
-        self.backing
-            .get_mut(idx)?
-            .as_mut()?
-            .iter_mut()
-            .find(|item| *key == item.0)
-            .map(|item| &mut item.1)
+        match map.entry("salt") {
+            Entry::Occupied(entry) => assert_eq!(by_hash[&entry.hash()], "salt"),
+            Entry::Vacant(_) => unreachable!(),
+        }
+        assert_eq!(by_hash.len(), 2);
     }
 
-    fn resize(&mut self) {
-        // resizes by exponentially doubling the capacity
+    #[test]
+    fn entry_grows_and_collides() {
+        let mut map = ChainingHashMap::with_hasher(crate::hashers::ConstantState::default());
 
-        // double the capacity
-        let new_cap = self.capacity() * 2;
+        for i in 0..100 {
+            *map.entry(i % 50).or_insert_with(|| i) += 1;
+        }
+        assert_eq!(map.len(), 50);
+        assert!((0..50).all(|i| map.get(&i) == Some(&(i + 2))));
 
-        // fill the new backing
-        let mut new_backing = Vec::with_capacity(new_cap);
-        for _ in 0..new_cap {
-            new_backing.push(None);
+        for i in 0..25 {
+            match map.entry(i) {
+                Entry::Occupied(entry) => assert_eq!(entry.remove(), i + 2),
+                Entry::Vacant(_) => unreachable!(),
+            }
         }
+        assert_eq!(map.len(), 25);
+        assert!(map.capacity() >= 50);
+    }
 
-        // reset the load
-        self.load = 0;
+    #[test]
+    fn reserve_and_shrink_to() {
+        let mut map = ChainingHashMap::new();
+        map.reserve(100);
+        let capacity = map.capacity();
+        assert!(map.spare_capacity() >= 100);
 
-        // replace the old backing and extract it
-        let old_backing = mem::replace(&mut self.backing, new_backing);
+        for i in 0..100 {
+            map.insert(i, i);
+        }
+        assert_eq!(map.capacity(), capacity);
+        assert!(map.try_reserve(usize::MAX).is_err());
+        assert!(map.try_reserve(1000).is_ok());
+        assert!(map.spare_capacity() >= 1000);
 
-        for item in old_backing.into_iter() {
-            // for each item in the old backing, check if it has a vec inside, iterate over the vec
-            if let Some(vec) = item {
-                for entry in vec {
-                    self.insert(entry.0, entry.1);
-                }
-            }
+        for i in 10..100 {
+            map.remove(&i);
         }
+        map.shrink_to(50);
+        assert!(map.capacity() < capacity);
+        assert!(map.spare_capacity() >= 40);
+        assert!((0..10).all(|i| map.get(&i) == Some(&i)));
     }
 
-    pub fn remove_entry(&mut self, key: &K) -> Option<(K, V)> {
-        let idx = self.get_index(key);
-
-        let indices_vec = self
-            .backing
-            .get(idx)?
-            .as_ref()?
-            .iter()
-            .enumerate()
-            .filter(|item: &(usize, &(K, V))| *key == item.1 .0)
-            .map(|item: (usize, &(K, V))| item.0)
-            .collect::<Vec<usize>>();
+    #[test]
+    fn get_disjoint_mut() {
+        let mut map = ChainingHashMap::with_hasher(crate::hashers::ConstantState::default());
+        for i in 0..5 {
+            map.insert(i, i);
+        }
 
-        indices_vec.first().and_then(|internal_idx| {
-            let item = self.backing[idx]
-                .as_mut()
-                .map(|vec| vec.remove(*internal_idx));
-            if item.is_some() {
-                self.load -= 1;
-            }
+        let [a, b, missing, c] = map.get_disjoint_mut([&3, &0, &9, &4]);
+        mem::swap(a.unwrap(), b.unwrap());
+        *c.unwrap() += 10;
+        assert!(missing.is_none());
 
-            item
-        })
+        assert_eq!(map.get(&0), Some(&3));
+        assert_eq!(map.get(&3), Some(&0));
+        assert_eq!(map.get(&4), Some(&14));
     }
 
-    /// Removes the value related to the given key, returning an Option containing its value if it
-    /// is present
-    pub fn remove(&mut self, key: &K) -> Option<V> {
-        self.remove_entry(key).map(|entry| entry.1)
+    #[test]
+    #[should_panic(expected = "same key twice")]
+    fn get_disjoint_mut_overlapping() {
+        let mut map = ChainingHashMap::new();
+        map.insert(1, 1);
+        map.get_disjoint_mut([&1, &1]);
     }
-}
 
-// TODO: implement benchmarks for insert/get
-// See: https://doc.rust-lang.org/unstable-book/library-features/test.html
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn from_iter_parallel() {
+        // every key appears twice, the second time with the value that should win
+        let entries = (0..5000)
+            .chain(0..5000)
+            .enumerate()
+            .map(|(i, key)| (key, i));
+
+        let map = ChainingHashMap::from_iter_parallel_with_hasher(
+            entries.clone(),
+            SeededState::new(1),
+            4,
+        );
+        let mut expected = ChainingHashMap::with_seed(1);
+        for (key, value) in entries {
+            expected.insert(key, value);
+        }
+
+        assert_eq!(map.len(), 5000);
+        for key in 0..5000 {
+            assert_eq!(map.get(&key), Some(&(key + 5000)));
+        }
+        assert_eq!(
+            map.iter_sorted_by_key().collect::<Vec<_>>(),
+            expected.iter_sorted_by_key().collect::<Vec<_>>()
+        );
+
+        let map = ChainingHashMap::<u8, u8>::from_iter_parallel([(1, 1), (1, 2)]);
+        assert_eq!(map.get(&1), Some(&2));
+        assert!(ChainingHashMap::<u8, u8>::from_iter_parallel([]).is_empty());
+    }
 
     #[test]
-    fn insert() {
+    fn get_or_try_insert_with() {
         let mut map = ChainingHashMap::new();
 
-        let result1 = map.insert("yes".to_string(), 123);
-        assert_eq!(result1, None);
+        assert_eq!(
+            map.get_or_try_insert_with("a", || "1".parse::<i32>()),
+            Ok(&mut 1)
+        );
+        assert!(map
+            .get_or_try_insert_with("b", || "x".parse::<i32>())
+            .is_err());
+        assert_eq!(map.get("b"), None);
+        assert_eq!(map.len(), 1);
+
+        // present keys never run `init`
+        *map.get_or_try_insert_with("a", || "x".parse::<i32>())
+            .unwrap() += 1;
+        assert_eq!(map.get("a"), Some(&2));
+    }
 
-        let result2 = map.insert("no".to_string(), 456);
-        assert_eq!(result2, None);
+    #[test]
+    fn count_items() {
+        let mut map = ChainingHashMap::new();
+        let rolls = [3u8, 1, 3, 6, 3, 1];
 
-        let result3 = map.insert("yes".to_string(), 456);
-        assert_eq!(result3.unwrap(), 123);
+        map.count_items(rolls.iter().copied());
+        map.count_items([6]);
 
-        assert_eq!(map.len(), 2)
+        assert_eq!(map.get(&3), Some(&3));
+        assert_eq!(map.get(&1), Some(&2));
+        assert_eq!(map.get(&6), Some(&2));
+        assert_eq!(map.len(), 3);
     }
 
     #[test]
-    fn insert_and_resize() {
-        let cap = 10;
-        let mut map = ChainingHashMap::with_capacity(cap);
+    fn remove_batch() {
+        let mut map =
+            ChainingHashMap::with_capacity_and_hasher(4, crate::hashers::ConstantState::default());
 
-        for i in 0..cap {
-            let result = map.insert(i.to_string(), i);
-            assert_eq!(result, None);
+        for i in 0..10 {
+            map.insert(i, i * 10);
         }
 
-        for i in 0..cap {
-            let result = map.get(&i.to_string());
-            assert_eq!(result, Some(i).as_ref());
-        }
+        let mut removed = map.remove_batch(&[8, 2, 42, 2, 5]);
+        removed.sort();
+
+        assert_eq!(removed, [(2, 20), (5, 50), (8, 80)]);
+        assert_eq!(map.len(), 7);
+        assert_eq!(map.get(&2), None);
+        assert_eq!(map.get(&3), Some(&30));
+        assert!(ChainingHashMap::<u8, u8>::new()
+            .remove_batch(&[1])
+            .is_empty());
     }
 
     #[test]
-    fn get() {
+    fn get_all() {
         let mut map = ChainingHashMap::new();
 
-        map.insert("yes".to_string(), 123);
-        map.insert("no".to_string(), 456);
+        for i in 0..10 {
+            map.insert(i.to_string(), i);
+        }
 
-        assert_eq!(map.get(&"yes".to_string()), Some(123).as_ref());
-        assert_eq!(map.get(&"no".to_string()), Some(456).as_ref());
-        assert_eq!(map.get(&"maybe".to_string()), None.as_ref());
+        assert_eq!(
+            map.get_all(["3", "x", "0", "3"]),
+            [Some(&3), None, Some(&0), Some(&3)]
+        );
+        assert!(map.get_all_iter(["9"; 2]).eq([Some(&9), Some(&9)]));
+        assert!(map.get_all(std::iter::empty::<&str>()).is_empty());
     }
 
     #[test]
-    fn get_mut() {
+    fn const_new() {
+        static MAP: std::sync::Mutex<ChainingHashMap<&str, u32, LazySeededState>> =
+            std::sync::Mutex::new(ChainingHashMap::const_new());
+
+        let mut map = MAP.lock().unwrap();
+        for (i, key) in ["a", "b", "c", "d", "e", "f", "g", "h", "i"]
+            .into_iter()
+            .enumerate()
+        {
+            map.insert(key, i as u32);
+        }
+
+        assert_eq!(map.len(), 9);
+        assert_eq!(map.get("a"), Some(&0));
+        assert_eq!(map.get("i"), Some(&8));
+    }
+
+    #[test]
+    fn allocates_on_first_insert() {
         let mut map = ChainingHashMap::new();
 
-        map.insert("yes".to_string(), 123);
+        assert_eq!(map.capacity(), 0);
+        assert_eq!(map.get(&1), None);
+        assert_eq!(map.remove(&1), None);
+        assert_eq!(map.current_load(), 0.0);
+        assert_eq!(map.spare_capacity(), 0);
 
-        assert_eq!(map.get(&"yes".to_string()), Some(123).as_ref());
+        map.insert(1, 1);
+        assert!(map.capacity().is_power_of_two());
+        assert_eq!(map.get(&1), Some(&1));
 
-        let item = map.get_mut(&"yes".to_string());
+        let mut map = ChainingHashMap::with_capacity(0);
+        map.replace(1, 1);
+        assert_eq!(map.get(&1), Some(&1));
+    }
 
-        assert!(item.is_some(), "Expected entry is not present");
+    #[test]
+    fn with_bucket_count() {
+        let mut map = ChainingHashMap::with_bucket_count(7);
+        assert_eq!(map.capacity(), 7);
+        assert_eq!(
+            ChainingHashMap::<u8, u8>::with_bucket_count(0).capacity(),
+            1
+        );
+
+        // 4 / 7 is within the load factor, 5 / 7 is not
+        for i in 0..5 {
+            map.insert(i, i);
+        }
+        assert_eq!(map.capacity(), 7);
+        map.insert(5, 5);
+        assert_eq!(map.capacity(), 14);
+    }
 
-        if let Some(value) = item {
-            *value += 1;
+    #[test]
+    fn integer_load_factor() {
+        let load_factor = LoadFactor::DEFAULT;
+
+        for buckets in 1..200 {
+            let max_len = load_factor.max_len(buckets);
+            assert!(!load_factor.exceeded(max_len, buckets));
+            assert!(load_factor.exceeded(max_len + 1, buckets));
+            assert_eq!(max_len, (buckets * 7) / 10);
         }
+        assert_eq!(load_factor.buckets_at(100), 142);
+        assert_eq!(load_factor.as_f32(), 0.7);
 
-        assert_eq!(map.get(&"yes".to_string()), Some(124).as_ref());
+        // nothing overflows at the top of the range
+        assert!(!load_factor.exceeded(usize::MAX / 2, usize::MAX));
+        assert!(load_factor.exceeded(usize::MAX, usize::MAX / 2));
     }
 
+    #[cfg(feature = "tracing")]
     #[test]
-    fn remove_entry() {
-        let cap = 10;
-        let mut map = ChainingHashMap::with_capacity(cap);
+    fn traces_rehashes_and_long_chains() {
+        let traces = crate::testkit::record_traces(|| {
+            let mut map = ChainingHashMap::with_hasher(crate::hashers::ConstantState::default());
+            for i in 0..40 {
+                map.insert(i, i);
+            }
+        });
 
-        for i in 0..cap {
-            let result = map.insert(i.to_string(), i);
-            assert_eq!(result, None);
+        let count = |name: &str| traces.iter().filter(|trace| *trace == name).count();
+        assert!(count("rehash") > 0);
+        assert_eq!(count("rehash"), count("rehashed"));
+        // the one chain reaches 16 and 32 entries
+        assert_eq!(count("long collision chain"), 2);
+    }
+
+    #[test]
+    fn try_variants() {
+        assert!(matches!(
+            ChainingHashMap::<u64, u64>::try_with_capacity(usize::MAX),
+            Err(Error::Allocation(_))
+        ));
+
+        let mut map = ChainingHashMap::try_with_capacity(10).unwrap();
+        assert_eq!(map.capacity(), 14);
+        for i in 0..100 {
+            assert_eq!(map.try_insert(i, i), Ok(None));
         }
+        assert_eq!(map.try_insert(1, 10), Ok(Some(1)));
+
+        assert_eq!(
+            map.try_get_disjoint_mut([&1, &2, &1]),
+            Err(Error::DuplicateKey)
+        );
+        let [one, missing] = map.try_get_disjoint_mut([&1, &100]).unwrap();
+        assert_eq!((one, missing), (Some(&mut 10), None));
+    }
 
-        assert_eq!(map.remove(&"100".to_string()), None);
+    #[test]
+    fn stats() {
+        let mut map = ChainingHashMap::with_hasher(crate::hashers::ConstantState::default());
 
-        for i in 0..cap {
-            let result = map.remove_entry(&i.to_string());
-            assert_eq!(result.unwrap(), (i.to_string(), i));
+        map.insert(0, 0);
+        assert_eq!(map.stats(), None);
+
+        map.enable_stats();
+        for i in 1..4 {
+            map.insert(i, i);
         }
+        map.insert(1, 10);
+        map.get(&3);
+        map.get(&7);
+        map.remove(&2);
+
+        // every key shares one chain, so the probes are just chain positions
+        assert_eq!(
+            map.stats(),
+            Some(Stats {
+                inserts: 3,
+                hits: 1,
+                misses: 1,
+                removes: 1,
+                resizes: 0,
+                probes: 1 + 2 + 3 + 2 + 4 + 4,
+            })
+        );
+
+        map.reset_stats();
+        assert_eq!(map.stats(), Some(Stats::default()));
     }
 
     #[test]
-    fn remove() {
-        let cap = 10;
-        let mut map = ChainingHashMap::with_capacity(cap);
+    fn occupancy() {
+        let mut map = ChainingHashMap::with_capacity(20);
 
-        for i in 0..cap {
-            let result = map.insert(i.to_string(), i);
-            assert_eq!(result, None);
-        }
+        assert_eq!(map.load_factor(), 0.7);
+        assert_eq!(map.current_load(), 0.0);
 
-        assert_eq!(map.remove(&"100".to_string()), None);
+        let spare = map.spare_capacity();
+        let capacity = map.capacity();
+        assert!(spare > 0);
 
-        for i in 0..cap {
-            let result = map.remove(&i.to_string());
-            assert_eq!(result.unwrap(), i);
+        for i in 0..spare {
+            map.insert(i, i);
         }
+        assert_eq!(map.capacity(), capacity);
+        assert_eq!(map.spare_capacity(), 0);
+        assert!(map.current_load() > 0.0);
+
+        map.insert(spare, spare);
+        assert!(map.capacity() > capacity);
+        assert!(map.current_load() <= map.load_factor());
     }
 
     #[test]
-    fn grow_and_shrink_len() {
-        let cap = 10;
-        let mut map = ChainingHashMap::with_capacity(cap);
+    fn rehash_in_place_drops_empty_chains() {
+        let mut map = ChainingHashMap::new();
 
-        for i in 0..cap {
-            let result = map.insert(i.to_string(), i);
-            assert_eq!(result, None);
-            assert_eq!(map.len(), i + 1);
+        for i in 0..10 {
+            map.insert(i, i);
         }
+        for i in 0..10 {
+            map.remove(&i);
+        }
+        assert!(map.backing.iter().any(|bucket| bucket.is_some()));
 
-        assert_eq!(map.remove(&"100".to_string()), None);
+        map.rehash_in_place();
+        assert!(map.backing.iter().all(|bucket| bucket.is_none()));
+    }
 
-        for i in 0..cap {
-            let result = map.remove(&i.to_string());
-            assert_eq!(result.unwrap(), i);
-            assert_eq!(map.len(), 10 - i - 1);
-        }
+    #[test]
+    fn compact() {
+        let mut map = ChainingHashMap::new();
 
-        for i in 0..cap {
-            let result = map.insert(i.to_string(), i);
-            assert_eq!(result, None);
-            assert_eq!(map.len(), i + 1);
+        for i in 0..1000 {
+            map.insert(i, i);
         }
+        for i in 100..1000 {
+            map.remove(&i);
+        }
+        let before = map.backing.len();
 
-        assert_eq!(map.remove(&"100".to_string()), None);
+        map.compact();
 
-        for i in 0..cap {
-            let result = map.remove_entry(&i.to_string());
-            assert_eq!(result.unwrap(), (i.to_string(), i));
-            assert_eq!(map.len(), 10 - i - 1);
+        // 100 / 0.7 rounded up; rounding down would leave the table over the load factor
+        assert_eq!(map.backing.len(), 143);
+        assert!(map.backing.len() < before);
+        assert_eq!(map.len(), 100);
+        for i in 0..100 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+
+        map.clear();
+        map.compact();
+        assert_eq!(map.backing.len(), 1);
+        map.insert(1, 1);
+        assert_eq!(map.get(&1), Some(&1));
+
+        // the compacted table is within the load factor at every size, so the next insert
+        // doesn't have to grow it straight back
+        for len in 1..50 {
+            let mut map = ChainingHashMap::new();
+            for i in 0..len {
+                map.insert(i, i);
+            }
+            map.compact();
+            assert!(!map.load_factor.exceeded(map.len(), map.capacity()));
         }
     }
 
+    #[test]
+    fn metadata() {
+        let mut map = ChainingHashMap::with_metadata();
+
+        assert_eq!(map.insert_with_meta("a", 1, 10u64), None);
+        map.insert_with_meta("b", 2, 20);
+        map.insert("c", 3);
+
+        // a plain insert keeps existing metadata and defaults new metadata
+        assert_eq!(map.insert("a", 4), Some(1));
+        assert_eq!(map.get_meta(&"a"), Some(&10));
+        assert_eq!(map.get_meta(&"c"), Some(&0));
+
+        let (value, meta) = map.get_with_meta_mut(&"b").unwrap();
+        *value += 1;
+        *meta += 1;
+
+        assert_eq!(map.insert_with_meta("b", 5, 30), Some((3, 21)));
+        assert_eq!(map.remove_with_meta(&"b"), Some((5, 30)));
+        assert_eq!(map.get_meta(&"b"), None);
+        assert_eq!(map.len(), 2);
+    }
+
     #[test]
     fn clear() {
         let cap = 100;
@@ -364,3 +3701,78 @@ mod tests {
         assert_eq!(map.len(), 0);
     }
 }
+
+#[cfg(test)]
+mod proptests {
+    use super::*;
+    use crate::hashers::ConstantState;
+    use proptest::prelude::*;
+    use std::collections::HashMap;
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Insert(u16, u32),
+        Remove(u16),
+        Get(u16),
+    }
+
+    fn ops(key_space: u16) -> impl Strategy<Value = Vec<Op>> {
+        let op = prop_oneof![
+            3 => (0..key_space, any::<u32>()).prop_map(|(k, v)| Op::Insert(k, v)),
+            1 => (0..key_space).prop_map(Op::Remove),
+            1 => (0..key_space).prop_map(Op::Get),
+        ];
+        prop::collection::vec(op, 0..400)
+    }
+
+    fn check<S: hash::BuildHasher>(
+        mut map: ChainingHashMap<u16, u32, S>,
+        ops: Vec<Op>,
+    ) -> Result<(), TestCaseError> {
+        let mut model = HashMap::new();
+
+        for op in ops {
+            match op {
+                Op::Insert(k, v) => {
+                    prop_assert_eq!(map.insert(k, v), model.insert(k, v));
+                    prop_assert_eq!(map.get(&k), Some(&v));
+                }
+                Op::Remove(k) => prop_assert_eq!(map.remove(&k), model.remove(&k)),
+                Op::Get(k) => prop_assert_eq!(map.get(&k), model.get(&k)),
+            }
+
+            prop_assert_eq!(map.len(), model.len());
+        }
+
+        // iteration yields exactly the live entries, which also checks nothing was lost on resize
+        let mut pairs = map.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+        pairs.sort();
+        let mut expected = model.into_iter().collect::<Vec<_>>();
+        expected.sort();
+
+        prop_assert_eq!(pairs.len(), map.len());
+        prop_assert_eq!(pairs, expected);
+
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn dense_keys(ops in ops(32)) {
+            check(ChainingHashMap::with_capacity(1), ops)?;
+        }
+
+        #[test]
+        fn sparse_keys(ops in ops(u16::MAX)) {
+            check(ChainingHashMap::with_capacity(1), ops)?;
+        }
+
+        #[test]
+        fn colliding_keys(ops in ops(64)) {
+            check(
+                ChainingHashMap::with_capacity_and_hasher(1, ConstantState::default()),
+                ops,
+            )?;
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use crate::chaining_map::ChainingHashMap;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash;
+use std::ops::{Deref, DerefMut};
+
+/// A `ChainingHashMap` that mirrors every operation into a `std::collections::HashMap` and
+/// panics as soon as the two disagree.
+///
+/// Meant for validating the crate against a real workload: swap it in through a type alias,
+/// run the workload (or its test suite), and swap it back out. Everything is done twice, so it
+/// is not meant for production use.
+///
+/// Doesn't implement `Map`: its `get_mut` hands out a plain `&mut V`, and the shadow copy can only
+/// follow changes made through the `CheckedMut` guard.
+#[derive(Debug)]
+pub struct CheckedMap<K, V> {
+    map: ChainingHashMap<K, V>,
+    shadow: HashMap<K, V>,
+}
+
+fn check<T: PartialEq + fmt::Debug>(operation: &str, ours: T, theirs: T) -> T {
+    if ours != theirs {
+        panic!(
+            "CheckedMap divergence in `{}`: ChainingHashMap returned {:?}, std HashMap returned {:?}",
+            operation, ours, theirs
+        );
+    }
+    ours
+}
+
+impl<K, V> CheckedMap<K, V> {
+    pub fn new() -> Self {
+        CheckedMap {
+            map: ChainingHashMap::new(),
+            shadow: HashMap::new(),
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        CheckedMap {
+            map: ChainingHashMap::with_capacity(capacity),
+            shadow: HashMap::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        check("len", self.map.len(), self.shadow.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        check("is_empty", self.map.is_empty(), self.shadow.is_empty())
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.shadow.clear();
+        self.len();
+    }
+
+    /// Gives back the checked map, dropping the shadow copy
+    pub fn into_inner(self) -> ChainingHashMap<K, V> {
+        self.map
+    }
+}
+
+impl<K, V> Default for CheckedMap<K, V> {
+    fn default() -> Self {
+        CheckedMap::new()
+    }
+}
+
+impl<K, V> CheckedMap<K, V>
+where
+    K: Eq + hash::Hash + Clone + fmt::Debug,
+    V: PartialEq + Clone + fmt::Debug,
+{
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let theirs = self.shadow.insert(key.clone(), value.clone());
+        let ours = self.map.insert(key, value);
+        let result = check("insert", ours, theirs);
+        self.len();
+        result
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + hash::Hash,
+    {
+        check("get", self.map.get(key), self.shadow.get(key))
+    }
+
+    /// Gets mutable access to a value; the shadow copy is updated when the guard is dropped
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<CheckedMut<'_, V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + hash::Hash,
+    {
+        let ours = self.map.get_mut(key);
+        let theirs = self.shadow.get_mut(key);
+        check("get_mut", ours.is_some(), theirs.is_some());
+
+        Some(CheckedMut {
+            value: ours?,
+            shadow: theirs?,
+        })
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + hash::Hash,
+    {
+        self.get(key).is_some()
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + hash::Hash,
+    {
+        let result = check(
+            "remove_entry",
+            self.map.remove_entry(key),
+            self.shadow.remove_entry(key),
+        );
+        self.len();
+        result
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Eq + hash::Hash,
+    {
+        self.remove_entry(key).map(|entry| entry.1)
+    }
+
+    /// Checks every entry of the shadow map against the checked map
+    pub fn validate(&self) {
+        self.len();
+        for (key, value) in self.shadow.iter() {
+            check("validate", self.map.get(key), Some(value));
+        }
+    }
+}
+
+/// Mutable reference into a `CheckedMap`, returned by `CheckedMap::get_mut`.
+///
+/// Copies the (possibly modified) value into the shadow map when dropped.
+pub struct CheckedMut<'a, V: Clone> {
+    value: &'a mut V,
+    shadow: &'a mut V,
+}
+
+impl<V: Clone> Deref for CheckedMut<'_, V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.value
+    }
+}
+
+impl<V: Clone> DerefMut for CheckedMut<'_, V> {
+    fn deref_mut(&mut self) -> &mut V {
+        self.value
+    }
+}
+
+impl<V: Clone> Drop for CheckedMut<'_, V> {
+    fn drop(&mut self) {
+        self.shadow.clone_from(self.value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirrors_operations() {
+        let mut map = CheckedMap::with_capacity(2);
+
+        for i in 0..100 {
+            assert_eq!(map.insert(i.to_string(), i), None);
+        }
+
+        assert_eq!(map.insert("5".to_string(), 500), Some(5));
+        assert_eq!(map.get("5"), Some(&500));
+        assert_eq!(map.remove("6"), Some(6));
+        assert_eq!(map.remove("6"), None);
+        assert!(!map.contains_key("6"));
+        assert_eq!(map.len(), 99);
+
+        map.validate();
+        map.clear();
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn get_mut_updates_shadow() {
+        let mut map = CheckedMap::new();
+
+        map.insert("a", vec![1]);
+
+        if let Some(mut value) = map.get_mut("a") {
+            value.push(2);
+        }
+
+        assert!(map.get_mut("b").is_none());
+        assert_eq!(map.get("a"), Some(&vec![1, 2]));
+        map.validate();
+    }
+
+    #[test]
+    #[should_panic(expected = "CheckedMap divergence in `get`")]
+    fn panics_on_divergence() {
+        let mut map = CheckedMap::new();
+
+        map.insert(1, 1);
+
+        // sneak a change past the shadow map
+        *map.map.get_mut(&1).unwrap() = 2;
+
+        map.get(&1);
+    }
+}
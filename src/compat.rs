@@ -0,0 +1,783 @@
+//! A drop-in stand-in for `std::collections::HashMap`.
+//!
+//! `compat::HashMap` has the same methods, trait impls and generic parameters as the standard
+//! map, stored in a `ChainingHashMap`, so switching a project over takes one changed `use`:
+//!
+//! ```
+//! use salt_map::compat::HashMap;
+//!
+//! let mut scores = HashMap::new();
+//! *scores.entry("salt").or_insert(0) += 1;
+//! assert_eq!(scores["salt"], 1);
+//! ```
+//!
+//! The one difference in behaviour is that `drain` and `extract_if` remove every matching entry
+//! as soon as they're called rather than as the iterator is advanced.
+
+use crate::chaining_map::{self, ChainingHashMap};
+use crate::error::Error;
+use std::borrow::Borrow;
+use std::collections::TryReserveError;
+use std::fmt;
+use std::hash::{self, BuildHasher, RandomState};
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::ops::Index;
+use std::vec;
+
+/// A hash map with the API of `std::collections::HashMap`.
+pub struct HashMap<K, V, S = RandomState> {
+    map: ChainingHashMap<K, V, S>,
+}
+
+impl<K, V> HashMap<K, V, RandomState> {
+    pub fn new() -> Self {
+        HashMap::with_hasher(RandomState::new())
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        HashMap::with_capacity_and_hasher(capacity, RandomState::new())
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        HashMap {
+            map: ChainingHashMap::with_hasher(hash_builder),
+        }
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        HashMap {
+            map: ChainingHashMap::with_capacity_and_hasher(capacity, hash_builder),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.map.capacity()
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.iter() }
+    }
+
+    pub fn into_keys(self) -> IntoKeys<K, V> {
+        IntoKeys {
+            inner: self.into_iter(),
+        }
+    }
+
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { inner: self.iter() }
+    }
+
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut {
+            inner: self.iter_mut(),
+        }
+    }
+
+    pub fn into_values(self) -> IntoValues<K, V> {
+        IntoValues {
+            inner: self.into_iter(),
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: self.map.iter(),
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            inner: self.map.iter_mut(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Removes every entry, handing them back as an iterator; the table keeps its buckets
+    pub fn drain(&mut self) -> Drain<'_, K, V> {
+        Drain {
+            inner: self.map.extract_where(|_, _| true).into_iter(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Removes every entry `pred` accepts, handing them back as an iterator
+    ///
+    /// Unlike the standard map's, this removes all of them before returning, even if the
+    /// iterator is dropped unused.
+    pub fn extract_if<F>(&mut self, pred: F) -> ExtractIf<'_, K, V>
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        ExtractIf {
+            inner: self.map.extract_where(pred).into_iter(),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.map.retain(f);
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    pub fn hasher(&self) -> &S {
+        self.map.hasher()
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: BuildHasher,
+{
+    pub fn reserve(&mut self, additional: usize) {
+        self.map.reserve(additional);
+    }
+
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.map.try_reserve(additional)
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.map.shrink_to(0);
+    }
+
+    pub fn shrink_to(&mut self, min_capacity: usize) {
+        self.map.shrink_to(min_capacity);
+    }
+
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        match self.map.entry(key) {
+            chaining_map::Entry::Occupied(inner) => Entry::Occupied(OccupiedEntry { inner }),
+            chaining_map::Entry::Vacant(inner) => Entry::Vacant(VacantEntry { inner }),
+        }
+    }
+
+    pub fn get<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + hash::Hash + Eq,
+    {
+        self.map.get(k)
+    }
+
+    pub fn get_key_value<Q>(&self, k: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + hash::Hash + Eq,
+    {
+        self.map.get_key_value(k)
+    }
+
+    /// Like indexing with `map[k]`, but fails with `Error::KeyNotFound` instead of panicking
+    pub fn try_index<Q>(&self, k: &Q) -> Result<&V, Error>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + hash::Hash + Eq,
+    {
+        self.map.get(k).ok_or(Error::KeyNotFound)
+    }
+
+    pub fn get_disjoint_mut<Q, const N: usize>(&mut self, ks: [&Q; N]) -> [Option<&mut V>; N]
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + hash::Hash + Eq,
+    {
+        self.map.get_disjoint_mut(ks)
+    }
+
+    pub fn contains_key<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + hash::Hash + Eq,
+    {
+        self.map.get(k).is_some()
+    }
+
+    pub fn get_mut<Q>(&mut self, k: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + hash::Hash + Eq,
+    {
+        self.map.get_mut(k)
+    }
+
+    pub fn insert(&mut self, k: K, v: V) -> Option<V> {
+        self.map.insert(k, v)
+    }
+
+    pub fn remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + hash::Hash + Eq,
+    {
+        self.map.remove(k)
+    }
+
+    pub fn remove_entry<Q>(&mut self, k: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + hash::Hash + Eq,
+    {
+        self.map.remove_entry(k)
+    }
+}
+
+impl<K, V, S> Clone for HashMap<K, V, S>
+where
+    K: Clone + Eq + hash::Hash,
+    V: Clone,
+    S: Clone + BuildHasher,
+{
+    fn clone(&self) -> Self {
+        HashMap {
+            map: self.map.clone_into_with_hasher(self.hasher().clone()),
+        }
+    }
+}
+
+impl<K, V, S> fmt::Debug for HashMap<K, V, S>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V, S: Default> Default for HashMap<K, V, S> {
+    fn default() -> Self {
+        HashMap::with_hasher(S::default())
+    }
+}
+
+impl<K, V, S> PartialEq for HashMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    V: PartialEq,
+    S: BuildHasher,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+impl<K, V, S> Eq for HashMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    V: Eq,
+    S: BuildHasher,
+{
+}
+
+impl<K, Q, V, S> Index<&Q> for HashMap<K, V, S>
+where
+    K: Eq + hash::Hash + Borrow<Q>,
+    Q: ?Sized + Eq + hash::Hash,
+    S: BuildHasher,
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.try_index(key).unwrap_or_else(|err| panic!("{err}"))
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for HashMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: BuildHasher + Default,
+{
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let mut map = HashMap::default();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K, V, const N: usize> From<[(K, V); N]> for HashMap<K, V, RandomState>
+where
+    K: Eq + hash::Hash,
+{
+    fn from(entries: [(K, V); N]) -> Self {
+        HashMap::from_iter(entries)
+    }
+}
+
+impl<K, V, S> Extend<(K, V)> for HashMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: BuildHasher,
+{
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<'a, K, V, S> Extend<(&'a K, &'a V)> for HashMap<K, V, S>
+where
+    K: Eq + hash::Hash + Copy,
+    V: Copy,
+    S: BuildHasher,
+{
+    fn extend<T: IntoIterator<Item = (&'a K, &'a V)>>(&mut self, iter: T) {
+        self.extend(iter.into_iter().map(|(key, value)| (*key, *value)));
+    }
+}
+
+impl<K, V, S> IntoIterator for HashMap<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        IntoIter {
+            inner: self.map.into_iter(),
+        }
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a HashMap<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a mut HashMap<K, V, S> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+/// A view into one key's place in a `HashMap`, from `HashMap::entry`.
+pub enum Entry<'a, K, V, S = RandomState> {
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    Vacant(VacantEntry<'a, K, V, S>),
+}
+
+/// An entry for a key that is in the map.
+pub struct OccupiedEntry<'a, K, V, S = RandomState> {
+    inner: chaining_map::OccupiedEntry<'a, K, V, S>,
+}
+
+/// An entry for a key that isn't in the map.
+pub struct VacantEntry<'a, K, V, S = RandomState> {
+    inner: chaining_map::VacantEntry<'a, K, V, S>,
+}
+
+impl<'a, K, V, S> Entry<'a, K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: BuildHasher,
+{
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = default(entry.key());
+                entry.insert(value)
+            }
+        }
+    }
+
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+
+    pub fn insert_entry(self, value: V) -> OccupiedEntry<'a, K, V, S> {
+        match self {
+            Entry::Occupied(mut entry) => {
+                entry.insert(value);
+                entry
+            }
+            Entry::Vacant(entry) => entry.insert_entry(value),
+        }
+    }
+
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K, V, S> OccupiedEntry<'a, K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: BuildHasher,
+{
+    pub fn key(&self) -> &K {
+        self.inner.key()
+    }
+
+    pub fn remove_entry(self) -> (K, V) {
+        self.inner.remove_entry()
+    }
+
+    pub fn get(&self) -> &V {
+        self.inner.get()
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        self.inner.get_mut()
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        self.inner.into_mut()
+    }
+
+    pub fn insert(&mut self, value: V) -> V {
+        self.inner.insert(value)
+    }
+
+    pub fn remove(self) -> V {
+        self.inner.remove()
+    }
+}
+
+impl<'a, K, V, S> VacantEntry<'a, K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: BuildHasher,
+{
+    pub fn key(&self) -> &K {
+        self.inner.key()
+    }
+
+    pub fn into_key(self) -> K {
+        self.inner.into_key()
+    }
+
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.inner.insert(value)
+    }
+
+    pub fn insert_entry(self, value: V) -> OccupiedEntry<'a, K, V, S> {
+        OccupiedEntry {
+            inner: self.inner.insert_entry(value),
+        }
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, S> fmt::Debug for Entry<'_, K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Entry::Occupied(entry) => f.debug_tuple("Entry").field(entry).finish(),
+            Entry::Vacant(entry) => f.debug_tuple("Entry").field(entry).finish(),
+        }
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, S> fmt::Debug for OccupiedEntry<'_, K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OccupiedEntry")
+            .field("key", self.inner.key())
+            .field("value", self.inner.get())
+            .finish()
+    }
+}
+
+impl<K: fmt::Debug, V, S> fmt::Debug for VacantEntry<'_, K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("VacantEntry")
+            .field(self.inner.key())
+            .finish()
+    }
+}
+
+/// An iterator over a map's entries.
+#[derive(Debug)]
+pub struct Iter<'a, K, V> {
+    inner: chaining_map::Iter<'a, K, V>,
+}
+
+/// An iterator over a map's entries, with mutable values.
+#[derive(Debug)]
+pub struct IterMut<'a, K, V> {
+    inner: chaining_map::IterMut<'a, K, V>,
+}
+
+/// An iterator over a map's keys.
+#[derive(Debug)]
+pub struct Keys<'a, K, V> {
+    inner: Iter<'a, K, V>,
+}
+
+/// An iterator over a map's values.
+#[derive(Debug)]
+pub struct Values<'a, K, V> {
+    inner: Iter<'a, K, V>,
+}
+
+/// An iterator over a map's values, mutably.
+#[derive(Debug)]
+pub struct ValuesMut<'a, K, V> {
+    inner: IterMut<'a, K, V>,
+}
+
+/// An owning iterator over a map's entries.
+#[derive(Debug)]
+pub struct IntoIter<K, V> {
+    inner: chaining_map::IntoIter<K, V>,
+}
+
+/// An owning iterator over a map's keys.
+#[derive(Debug)]
+pub struct IntoKeys<K, V> {
+    inner: IntoIter<K, V>,
+}
+
+/// An owning iterator over a map's values.
+#[derive(Debug)]
+pub struct IntoValues<K, V> {
+    inner: IntoIter<K, V>,
+}
+
+/// The entries removed by `HashMap::drain`.
+#[derive(Debug)]
+pub struct Drain<'a, K, V> {
+    inner: vec::IntoIter<(K, V)>,
+    marker: PhantomData<&'a mut HashMap<K, V>>,
+}
+
+/// The entries removed by `HashMap::extract_if`.
+#[derive(Debug)]
+pub struct ExtractIf<'a, K, V> {
+    inner: vec::IntoIter<(K, V)>,
+    marker: PhantomData<&'a mut HashMap<K, V>>,
+}
+
+// the borrowed iterators can be cloned whatever the key and value types, like the standard ones
+macro_rules! cloneable_iterator {
+    ($name:ident) => {
+        impl<K, V> Clone for $name<'_, K, V> {
+            fn clone(&self) -> Self {
+                $name {
+                    inner: self.inner.clone(),
+                }
+            }
+        }
+    };
+}
+
+cloneable_iterator!(Iter);
+cloneable_iterator!(Keys);
+cloneable_iterator!(Values);
+
+// all of them are adapters over an iterator that already knows its length
+macro_rules! mapped_iterator {
+    ($name:ident$(<$lt:lifetime>)?, |$entry:pat_param| $map:expr => $item:ty) => {
+        impl<$($lt,)? K, V> Iterator for $name<$($lt,)? K, V> {
+            type Item = $item;
+
+            fn next(&mut self) -> Option<$item> {
+                let $entry = self.inner.next()?;
+                Some($map)
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                let len = self.len();
+                (len, Some(len))
+            }
+        }
+
+        impl<$($lt,)? K, V> ExactSizeIterator for $name<$($lt,)? K, V> {
+            fn len(&self) -> usize {
+                self.inner.len()
+            }
+        }
+
+        impl<$($lt,)? K, V> FusedIterator for $name<$($lt,)? K, V> {}
+    };
+}
+
+mapped_iterator!(Iter<'a>, |entry| entry => (&'a K, &'a V));
+mapped_iterator!(IterMut<'a>, |entry| entry => (&'a K, &'a mut V));
+mapped_iterator!(Keys<'a>, |(key, _)| key => &'a K);
+mapped_iterator!(Values<'a>, |(_, value)| value => &'a V);
+mapped_iterator!(ValuesMut<'a>, |(_, value)| value => &'a mut V);
+mapped_iterator!(IntoIter, |entry| entry => (K, V));
+mapped_iterator!(IntoKeys, |(key, _)| key => K);
+mapped_iterator!(IntoValues, |(_, value)| value => V);
+mapped_iterator!(Drain<'a>, |entry| entry => (K, V));
+mapped_iterator!(ExtractIf<'a>, |entry| entry => (K, V));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::testkit::map_conformance_tests!(HashMap::<usize, usize>::new());
+
+    #[test]
+    fn entry() {
+        let mut map = HashMap::new();
+
+        for word in "a b a c a b".split(' ') {
+            *map.entry(word).or_insert(0) += 1;
+        }
+        assert_eq!(map, HashMap::from([("a", 3), ("b", 2), ("c", 1)]));
+
+        map.entry("b").and_modify(|count| *count *= 10).or_default();
+        map.entry("d").and_modify(|count| *count *= 10).or_default();
+        assert_eq!(map["b"], 20);
+        assert_eq!(map["d"], 0);
+
+        match map.entry("a") {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.insert(7), 3);
+                assert_eq!(entry.remove_entry(), ("a", 7));
+            }
+            Entry::Vacant(_) => unreachable!(),
+        }
+        match map.entry("a") {
+            Entry::Vacant(entry) => assert_eq!(entry.into_key(), "a"),
+            Entry::Occupied(_) => unreachable!(),
+        }
+        assert_eq!(*map.entry("e").or_insert_with_key(|key| key.len()), 1);
+
+        let entry = map.entry("f").insert_entry(6);
+        assert_eq!((entry.key(), entry.get()), (&"f", &6));
+        assert_eq!(entry.remove_entry(), ("f", 6));
+        match map.entry("f") {
+            Entry::Vacant(entry) => assert_eq!(*entry.insert_entry(7).get(), 7),
+            Entry::Occupied(_) => unreachable!(),
+        }
+        assert_eq!(map["f"], 7);
+    }
+
+    #[test]
+    fn iterators() {
+        let mut map = (0..10).map(|i| (i, i * 10)).collect::<HashMap<_, _>>();
+
+        assert_eq!(map.iter().len(), 10);
+        assert_eq!(map.keys().copied().sum::<i32>(), 45);
+        assert_eq!(map.values().copied().sum::<i32>(), 450);
+
+        for value in map.values_mut() {
+            *value += 1;
+        }
+        for (key, value) in &mut map {
+            *value -= key;
+        }
+        assert!((&map).into_iter().all(|(key, value)| *value == key * 9 + 1));
+
+        let mut evens = map.extract_if(|key, _| key % 2 == 0).collect::<Vec<_>>();
+        evens.sort();
+        assert_eq!(evens.len(), 5);
+        assert_eq!(evens[1], (2, 19));
+
+        map.retain(|key, _| *key < 5);
+        let mut keys = map.clone().into_keys().collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, [1, 3]);
+
+        assert_eq!(map.drain().len(), 2);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn iterators_clone_and_cross_threads() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        // keys and values that aren't `Clone` themselves
+        struct Opaque(i32);
+        let mut map = (0..10).map(|i| (i, Opaque(i))).collect::<HashMap<_, _>>();
+
+        let mut iter = map.iter();
+        iter.next();
+        assert_eq!(iter.clone().len(), 9);
+        assert_eq!(map.keys().clone().count(), 10);
+        assert_eq!(map.values().clone().map(|value| value.0).sum::<i32>(), 45);
+
+        assert_send_sync(&map.iter());
+        assert_send_sync(&map.iter_mut());
+        assert_send_sync(&map.keys());
+        let sum = std::thread::scope(|scope| {
+            scope
+                .spawn(|| map.iter().map(|(_, value)| value.0).sum::<i32>())
+                .join()
+                .unwrap()
+        });
+        assert_eq!(sum, 45);
+
+        let mut into_iter = map.into_iter();
+        assert_eq!(into_iter.len(), 10);
+        into_iter.next();
+        assert_eq!(into_iter.len(), 9);
+    }
+
+    #[test]
+    fn capacity() {
+        let mut map = HashMap::<u32, u32>::with_capacity(100);
+        assert!(map.capacity() >= 100);
+
+        map.extend([(1, 1), (2, 2)]);
+        map.extend([(&3, &3)]);
+        map.shrink_to_fit();
+        assert!(map.capacity() < 100);
+        assert!(map.try_reserve(50).is_ok());
+        assert_eq!(map.len(), 3);
+
+        let [one, three] = map.get_disjoint_mut([&1, &3]);
+        std::mem::swap(one.unwrap(), three.unwrap());
+        assert_eq!(map.get_key_value(&1), Some((&1, &3)));
+        assert_eq!(map.try_index(&1), Ok(&3));
+        assert_eq!(map.try_index(&100), Err(Error::KeyNotFound));
+        assert_eq!(format!("{:?}", HashMap::from([(1, 2)])), "{1: 2}");
+    }
+}
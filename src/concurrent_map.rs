@@ -0,0 +1,412 @@
+use crate::equivalent::Equivalent;
+use std::cell::UnsafeCell;
+use std::hash;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{PoisonError, RwLock};
+
+// average entries per bucket above which the table doubles
+const MAX_LOAD: usize = 1;
+
+const INITIAL_BUCKETS: usize = 64;
+
+// a one-word lock for a single chain; held only for a scan of that chain, so spinning beats
+// parking the thread
+struct SpinLock<T> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: the lock hands out access to the value to one thread at a time
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+
+struct SpinGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+}
+
+impl<T> SpinLock<T> {
+    fn new(value: T) -> Self {
+        SpinLock {
+            locked: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    fn lock(&self) -> SpinGuard<'_, T> {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // wait on a plain load, so waiting threads don't keep stealing the cache line
+            while self.locked.load(Ordering::Relaxed) {
+                std::hint::spin_loop();
+            }
+        }
+
+        SpinGuard { lock: self }
+    }
+
+    fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T> Deref for SpinGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: the guard holds the lock
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for SpinGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: the guard holds the lock
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for SpinGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
+
+type Chain<K, V> = SpinLock<Vec<(K, V)>>;
+
+/// A chaining map that can be shared between threads, with a lock per bucket.
+///
+/// Operations on different buckets never wait on each other, so writers contend only when they
+/// hit the same chain, which is much rarer than hitting the same shard of a sharded map. The
+/// bucket array itself sits behind a read-write lock that every operation takes shared and only
+/// a resize takes exclusively. Since values live behind locks, `get` hands back a clone, and
+/// `update` runs a closure on the value in place, while its bucket is locked.
+pub struct ConcurrentMap<K, V, S = hash::RandomState> {
+    buckets: RwLock<Vec<Chain<K, V>>>,
+    load: AtomicUsize,
+    hash_builder: S,
+}
+
+impl<K, V> ConcurrentMap<K, V, hash::RandomState> {
+    pub fn new() -> Self {
+        ConcurrentMap::with_hasher(hash::RandomState::new())
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        ConcurrentMap::with_capacity_and_hasher(capacity, hash::RandomState::new())
+    }
+}
+
+impl<K, V> Default for ConcurrentMap<K, V, hash::RandomState> {
+    fn default() -> Self {
+        ConcurrentMap::new()
+    }
+}
+
+impl<K, V, S> ConcurrentMap<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        ConcurrentMap::with_capacity_and_hasher(0, hash_builder)
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        let buckets = (capacity / MAX_LOAD).max(INITIAL_BUCKETS);
+
+        ConcurrentMap {
+            buckets: RwLock::new((0..buckets).map(|_| SpinLock::new(Vec::new())).collect()),
+            load: AtomicUsize::new(0),
+            hash_builder,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.load.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    pub fn clear(&self) {
+        let buckets = self.buckets.read().unwrap_or_else(PoisonError::into_inner);
+
+        for bucket in buckets.iter() {
+            let mut chain = bucket.lock();
+            self.load.fetch_sub(chain.len(), Ordering::Relaxed);
+            chain.clear();
+        }
+    }
+}
+
+impl<K, V, S> ConcurrentMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    // runs `f` on the locked chain `hash` belongs to
+    fn with_chain<R>(&self, hash: u64, f: impl FnOnce(&mut Vec<(K, V)>) -> R) -> R {
+        // nothing panics while the lock is held for writing except user `Hash` impls, before
+        // anything moves, so a poisoned table is still consistent
+        let buckets = self.buckets.read().unwrap_or_else(PoisonError::into_inner);
+        let idx = (hash % buckets.len() as u64) as usize;
+
+        let mut chain = buckets[idx].lock();
+        f(&mut chain)
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let hash = self.hash_builder.hash_one(&key);
+
+        // the count changes under the chain's lock, so a removal of the same key can't get its
+        // decrement in first and wrap the count below zero
+        let (previous, load) = self.with_chain(hash, |chain| {
+            match chain.iter_mut().find(|item| item.0 == key) {
+                Some(item) => (Some(std::mem::replace(&mut item.1, value)), None),
+                None => {
+                    chain.push((key, value));
+                    (None, Some(self.load.fetch_add(1, Ordering::Relaxed) + 1))
+                }
+            }
+        });
+
+        if let Some(load) = load {
+            self.grow_if_needed(load);
+        }
+
+        previous
+    }
+
+    fn grow_if_needed(&self, load: usize) {
+        if load <= self.bucket_count() * MAX_LOAD {
+            return;
+        }
+
+        let mut buckets = self.buckets.write().unwrap_or_else(PoisonError::into_inner);
+        // another thread may have grown the table while this one waited for the lock
+        let new_len = buckets.len() * 2;
+        if self.len() <= buckets.len() * MAX_LOAD {
+            return;
+        }
+
+        // hash every key before moving anything, so a panicking `Hash` leaves the table as it
+        // was
+        let indices = buckets
+            .iter_mut()
+            .flat_map(|bucket| bucket.get_mut().iter())
+            .map(|(key, _)| (self.hash_builder.hash_one(key) % new_len as u64) as usize)
+            .collect::<Vec<_>>();
+
+        let old = std::mem::replace(
+            &mut *buckets,
+            (0..new_len).map(|_| SpinLock::new(Vec::new())).collect(),
+        );
+        let entries = old.into_iter().flat_map(SpinLock::into_inner);
+        for (entry, idx) in entries.zip(indices) {
+            buckets[idx].get_mut().push(entry);
+        }
+    }
+
+    fn bucket_count(&self) -> usize {
+        self.buckets
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    /// Gets a clone of the value for `key`
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+        V: Clone,
+    {
+        self.update(key, |value| value.clone())
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.update(key, |_| ()).is_some()
+    }
+
+    /// Runs `f` on the value for `key`, returning what it returns, or `None` if the key is
+    /// missing
+    ///
+    /// Every other operation on the same bucket waits for `f`, so keep it short, and don't touch
+    /// the map from inside it: that can deadlock.
+    pub fn update<Q, F, R>(&self, key: &Q, f: F) -> Option<R>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+        F: FnOnce(&mut V) -> R,
+    {
+        self.with_chain(self.hash_builder.hash_one(key), |chain| {
+            let item = chain.iter_mut().find(|item| key.equivalent(&item.0))?;
+            Some(f(&mut item.1))
+        })
+    }
+
+    pub fn remove_entry<Q>(&self, key: &Q) -> Option<(K, V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.with_chain(self.hash_builder.hash_one(key), |chain| {
+            let idx = chain.iter().position(|item| key.equivalent(&item.0))?;
+            self.load.fetch_sub(1, Ordering::Relaxed);
+            Some(chain.swap_remove(idx))
+        })
+    }
+
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.remove_entry(key).map(|entry| entry.1)
+    }
+}
+
+impl<K, V, S> std::fmt::Debug for ConcurrentMap<K, V, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConcurrentMap")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashers::ConstantState;
+
+    #[test]
+    fn single_threaded() {
+        let map = ConcurrentMap::new();
+
+        for i in 0..500 {
+            assert_eq!(map.insert(i, i), None);
+        }
+        assert_eq!(map.insert(7, 70), Some(7));
+
+        assert_eq!(map.len(), 500);
+        assert_eq!(map.get(&7), Some(70));
+        assert_eq!(map.update(&8, |value| std::mem::replace(value, 0)), Some(8));
+        assert_eq!(map.get(&8), Some(0));
+        assert!(!map.contains_key(&500));
+
+        assert_eq!(map.remove(&7), Some(70));
+        assert_eq!(map.remove(&7), None);
+        assert_eq!(map.len(), 499);
+
+        map.clear();
+        assert!(map.is_empty());
+        assert_eq!(map.get(&1), None);
+    }
+
+    // small enough for Miri to get through, which checks the lock's unsafe code
+    const PER_THREAD: usize = if cfg!(miri) { 20 } else { 1000 };
+
+    #[test]
+    fn spin_lock() {
+        let lock = SpinLock::new(0);
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..PER_THREAD {
+                        *lock.lock() += 1;
+                    }
+                });
+            }
+        });
+
+        let guard = lock.lock();
+        assert_eq!(*guard, 4 * PER_THREAD);
+        drop(guard);
+        // dropping the guard released the lock
+        *lock.lock() += 1;
+
+        let mut lock = lock;
+        *lock.get_mut() += 1;
+        assert_eq!(lock.into_inner(), 4 * PER_THREAD + 2);
+    }
+
+    #[test]
+    fn len_never_wraps() {
+        let map = ConcurrentMap::new();
+
+        // a remover racing each insert of the same key must never see the count go below zero
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for i in 0..PER_THREAD {
+                    map.insert(i % 4, i);
+                }
+            });
+            scope.spawn(|| {
+                for i in 0..PER_THREAD {
+                    map.remove(&(i % 4));
+                    assert!(map.len() <= 4);
+                }
+            });
+        });
+
+        assert!(map.len() <= 4);
+    }
+
+    #[test]
+    fn concurrent_writers() {
+        let map = ConcurrentMap::new();
+
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let map = &map;
+                scope.spawn(move || {
+                    for i in 0..PER_THREAD {
+                        map.insert(thread * PER_THREAD + i, i);
+                        // every thread also bumps one shared key
+                        if map.update(&usize::MAX, |count| *count += 1).is_none() {
+                            map.insert(usize::MAX, 0);
+                        }
+                    }
+                    for i in (0..PER_THREAD).step_by(2) {
+                        assert_eq!(map.remove(&(thread * PER_THREAD + i)), Some(i));
+                    }
+                });
+            }
+        });
+
+        assert_eq!(map.len(), 2 * PER_THREAD + 1);
+        for key in 0..4 * PER_THREAD {
+            assert_eq!(map.get(&key), (key % 2 == 1).then_some(key % PER_THREAD));
+        }
+        if !cfg!(miri) {
+            assert!(map.bucket_count() > INITIAL_BUCKETS);
+        }
+    }
+
+    #[test]
+    fn total_collision() {
+        let map = ConcurrentMap::with_hasher(ConstantState::default());
+
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let map = &map;
+                scope.spawn(move || {
+                    for i in 0..50 {
+                        map.insert(thread * 50 + i, thread);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(map.len(), 200);
+        assert_eq!(map.get(&199), Some(3));
+    }
+}
@@ -0,0 +1,209 @@
+use crate::priority_map::PriorityMap;
+use std::hash;
+use std::iter::FusedIterator;
+
+/// A keyed set of entries that each expire at a deadline.
+///
+/// Deadlines can be anything ordered (`std::time::Instant`, a tick count, ...); the map never
+/// reads a clock itself, the caller passes the current time to `poll_expired`. Built on
+/// `PriorityMap`, so rescheduling or cancelling an entry by key is O(log n).
+#[derive(Debug)]
+pub struct DelayQueueMap<K, D, V> {
+    queue: PriorityMap<K, D, V>,
+}
+
+impl<K, D, V> DelayQueueMap<K, D, V> {
+    pub fn new() -> Self {
+        DelayQueueMap {
+            queue: PriorityMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+
+    /// The earliest deadline currently scheduled
+    pub fn next_deadline(&self) -> Option<&D> {
+        self.queue.peek_min().map(|entry| entry.1)
+    }
+}
+
+impl<K, D, V> Default for DelayQueueMap<K, D, V> {
+    fn default() -> Self {
+        DelayQueueMap::new()
+    }
+}
+
+impl<K, D, V> DelayQueueMap<K, D, V>
+where
+    K: Eq + hash::Hash + Clone,
+    D: Ord,
+{
+    /// Schedules an entry, returning the previous deadline and value if the key was scheduled
+    pub fn insert(&mut self, key: K, deadline: D, value: V) -> Option<(D, V)> {
+        self.queue.push(key, deadline, value)
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.queue.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.queue.get_mut(key)
+    }
+
+    pub fn deadline(&self, key: &K) -> Option<&D> {
+        self.queue.priority(key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.queue.contains_key(key)
+    }
+
+    /// Moves the entry to a new deadline, returning the old deadline if the key is scheduled
+    pub fn reset(&mut self, key: &K, deadline: D) -> Option<D> {
+        self.queue.change_priority(key, deadline)
+    }
+
+    /// Cancels the entry, returning its deadline and value
+    pub fn remove(&mut self, key: &K) -> Option<(D, V)> {
+        self.queue.remove(key)
+    }
+
+    /// Removes and yields, earliest first, every entry whose deadline is at or before `now`
+    pub fn poll_expired(&mut self, now: D) -> PollExpired<'_, K, D, V> {
+        PollExpired { map: self, now }
+    }
+}
+
+/// Iterator returned by `DelayQueueMap::poll_expired`.
+///
+/// Entries are only removed as the iterator is advanced; dropping it early leaves the rest
+/// scheduled.
+#[derive(Debug)]
+pub struct PollExpired<'a, K, D, V> {
+    map: &'a mut DelayQueueMap<K, D, V>,
+    now: D,
+}
+
+impl<K, D, V> Iterator for PollExpired<'_, K, D, V>
+where
+    K: Eq + hash::Hash + Clone,
+    D: Ord,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if *self.map.next_deadline()? > self.now {
+            return None;
+        }
+
+        self.map.queue.pop_min().map(|(key, _, value)| (key, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.map.len()))
+    }
+}
+
+// `now` is fixed and the map is borrowed for the iterator's lifetime, so once nothing has
+// expired nothing ever will
+impl<K, D, V> FusedIterator for PollExpired<'_, K, D, V>
+where
+    K: Eq + hash::Hash + Clone,
+    D: Ord,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn poll_expired_yields_in_deadline_order() {
+        let mut map = DelayQueueMap::new();
+
+        map.insert("c", 30, 3);
+        map.insert("a", 10, 1);
+        map.insert("b", 20, 2);
+        map.insert("d", 40, 4);
+
+        assert_eq!(map.poll_expired(5).count(), 0);
+
+        let expired = map.poll_expired(30).collect::<Vec<_>>();
+        assert_eq!(expired, vec![("a", 1), ("b", 2), ("c", 3)]);
+
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.next_deadline(), Some(&40));
+    }
+
+    #[test]
+    fn reset_reschedules() {
+        let mut map = DelayQueueMap::new();
+
+        map.insert("session-1", 10, ());
+        map.insert("session-2", 20, ());
+
+        assert_eq!(map.reset(&"session-1", 100), Some(10));
+        assert_eq!(map.reset(&"missing", 100), None);
+
+        let expired = map
+            .poll_expired(50)
+            .map(|entry| entry.0)
+            .collect::<Vec<_>>();
+        assert_eq!(expired, vec!["session-2"]);
+        assert_eq!(map.deadline(&"session-1"), Some(&100));
+    }
+
+    #[test]
+    fn remove_cancels() {
+        let mut map = DelayQueueMap::new();
+
+        map.insert(1, 10, "one");
+        map.insert(2, 10, "two");
+
+        assert_eq!(map.remove(&1), Some((10, "one")));
+        assert_eq!(map.remove(&1), None);
+
+        assert_eq!(map.poll_expired(10).collect::<Vec<_>>(), vec![(2, "two")]);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn dropping_poll_early_keeps_entries() {
+        let mut map = DelayQueueMap::new();
+
+        for i in 0..5 {
+            map.insert(i, i, i);
+        }
+
+        assert_eq!(map.poll_expired(10).next(), Some((0, 0)));
+        assert_eq!(map.len(), 4);
+    }
+
+    #[test]
+    fn instant_deadlines() {
+        let start = Instant::now();
+        let mut map = DelayQueueMap::new();
+
+        map.insert("short", start + Duration::from_secs(1), ());
+        map.insert("long", start + Duration::from_secs(60), ());
+
+        let expired = map
+            .poll_expired(start + Duration::from_secs(5))
+            .map(|entry| entry.0)
+            .collect::<Vec<_>>();
+
+        assert_eq!(expired, vec!["short"]);
+    }
+}
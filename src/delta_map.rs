@@ -0,0 +1,417 @@
+use crate::chaining_map::{self, ChainingHashMap};
+use crate::equivalent::Equivalent;
+use std::hash;
+
+/// One change to a key, as carried by a `Delta`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change<K, V> {
+    /// The key now maps to this value
+    Insert(K, V),
+    /// The key is no longer in the map
+    Remove(K),
+}
+
+/// The changes to a `DeltaMap` between two of its versions, with at most one change per key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delta<K, V> {
+    /// The version the changes start from
+    pub since: u64,
+    /// The version the map was at when the delta was exported
+    pub version: u64,
+    pub changes: Vec<Change<K, V>>,
+}
+
+#[derive(Debug)]
+struct Versioned<V> {
+    // `None` for a key that was removed, kept so the removal can be exported
+    value: Option<V>,
+    // the version of the last change to the key
+    version: u64,
+}
+
+/// A map that remembers which keys changed when, so a replica can be kept up to date by
+/// shipping only what changed since it last synced.
+///
+/// Every change bumps the map's `version`. `export_changes_since` collects the current state of
+/// every key changed after a given version, and `apply_changes` replays it on another map.
+/// Removed keys are kept as tombstones until `forget_changes_up_to` drops them, after which
+/// deltas can no longer be exported from before that point.
+#[derive(Debug)]
+pub struct DeltaMap<K, V, S = hash::RandomState> {
+    entries: ChainingHashMap<K, Versioned<V>, S>,
+    load: usize,
+    version: u64,
+    // changes up to and including this version may have been forgotten
+    horizon: u64,
+}
+
+impl<K, V> DeltaMap<K, V, hash::RandomState> {
+    pub fn new() -> Self {
+        DeltaMap::with_hasher(hash::RandomState::new())
+    }
+}
+
+impl<K, V> Default for DeltaMap<K, V, hash::RandomState> {
+    fn default() -> Self {
+        DeltaMap::new()
+    }
+}
+
+impl<K, V, S> DeltaMap<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        DeltaMap {
+            entries: ChainingHashMap::with_hasher(hash_builder),
+            load: 0,
+            version: 0,
+            horizon: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.load
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.load == 0
+    }
+
+    /// The version of the latest change, or 0 if nothing has changed yet
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Iterates over the entries, in no particular order
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            entries: self.entries.iter(),
+            remaining: self.load,
+        }
+    }
+
+    /// Iterates over the entries with mutable references to the values, in no particular order
+    ///
+    /// Like `get_mut`, every value handed out counts as changed, whether or not it's written.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        let version = self.bump();
+
+        IterMut {
+            entries: self.entries.iter_mut(),
+            version,
+            remaining: self.load,
+        }
+    }
+
+    /// Iterates over the keys, in no particular order
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.iter() }
+    }
+
+    /// Iterates over the values, in no particular order
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { inner: self.iter() }
+    }
+
+    /// Exports the changes made after `since`, ready for `apply_changes`
+    ///
+    /// `None` if `since` is ahead of this map's version, or if changes after it have been
+    /// forgotten, since the delta would then be incomplete; the replica needs a full copy.
+    pub fn export_changes_since(&self, since: u64) -> Option<Delta<K, V>>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        if since > self.version || since < self.horizon {
+            return None;
+        }
+
+        let changes = self
+            .entries
+            .iter()
+            .filter(|(_, slot)| slot.version > since)
+            .map(|(key, slot)| match &slot.value {
+                Some(value) => Change::Insert(key.clone(), value.clone()),
+                None => Change::Remove(key.clone()),
+            })
+            .collect();
+
+        Some(Delta {
+            since,
+            version: self.version,
+            changes,
+        })
+    }
+
+    fn bump(&mut self) -> u64 {
+        self.version += 1;
+        self.version
+    }
+}
+
+impl<K, V, S> DeltaMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let version = self.bump();
+        let previous = self.entries.insert(
+            key,
+            Versioned {
+                value: Some(value),
+                version,
+            },
+        );
+
+        let previous = previous.and_then(|slot| slot.value);
+        if previous.is_none() {
+            self.load += 1;
+        }
+        previous
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.entries.get(key)?.value.as_ref()
+    }
+
+    /// Gets mutable access to a value, which counts as changing it whether or not it's written
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.get(key)?;
+
+        let version = self.bump();
+        let slot = self.entries.get_mut(key)?;
+        slot.version = version;
+        slot.value.as_mut()
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Removes a key, leaving a tombstone behind so the removal shows up in later deltas
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.get(key)?;
+
+        let version = self.bump();
+        let slot = self.entries.get_mut(key)?;
+        slot.version = version;
+        self.load -= 1;
+        slot.value.take()
+    }
+
+    /// Replays a delta exported from another map, as if its changes were made here
+    pub fn apply_changes(&mut self, delta: Delta<K, V>) {
+        for change in delta.changes {
+            match change {
+                Change::Insert(key, value) => {
+                    self.insert(key, value);
+                }
+                Change::Remove(key) => {
+                    self.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Drops the tombstones of keys removed at or before `version`, after which
+    /// `export_changes_since` refuses to export from before it
+    pub fn forget_changes_up_to(&mut self, version: u64) {
+        let version = version.min(self.version);
+        self.horizon = self.horizon.max(version);
+
+        self.entries
+            .retain(|_, slot| slot.value.is_some() || slot.version > version);
+    }
+}
+
+/// An iterator over a `DeltaMap`'s entries, from `DeltaMap::iter`.
+///
+/// Skips the tombstones of removed keys.
+#[derive(Debug)]
+pub struct Iter<'a, K, V> {
+    entries: chaining_map::Iter<'a, K, Versioned<V>>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        let entry = self
+            .entries
+            .find_map(|(key, slot)| Some((key, slot.value.as_ref()?)))?;
+        self.remaining -= 1;
+        Some(entry)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> std::iter::FusedIterator for Iter<'_, K, V> {}
+
+impl<K, V> Clone for Iter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Iter {
+            entries: self.entries.clone(),
+            remaining: self.remaining,
+        }
+    }
+}
+
+/// A mutable iterator over a `DeltaMap`'s entries, from `DeltaMap::iter_mut`.
+///
+/// Stamps each entry it hands out with the version `iter_mut` bumped the map to.
+#[derive(Debug)]
+pub struct IterMut<'a, K, V> {
+    entries: chaining_map::IterMut<'a, K, Versioned<V>>,
+    version: u64,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        let version = self.version;
+        let entry = self.entries.find_map(|(key, slot)| {
+            let value = slot.value.as_mut()?;
+            slot.version = version;
+            Some((key, value))
+        })?;
+        self.remaining -= 1;
+        Some(entry)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+
+impl<K, V> std::iter::FusedIterator for IterMut<'_, K, V> {}
+
+crate::iter::keys_and_values!(DeltaMap, Iter<K, V>);
+crate::iter::borrowed_into_iterator!(DeltaMap<K, V, S>, Iter<K, V>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::testkit::map_conformance_tests!(DeltaMap::<usize, usize>::new());
+
+    #[test]
+    fn export_and_apply() {
+        let mut primary = DeltaMap::new();
+        let mut replica = DeltaMap::new();
+
+        primary.insert("a", 1);
+        primary.insert("b", 2);
+        replica.apply_changes(primary.export_changes_since(0).unwrap());
+        let synced = primary.version();
+
+        primary.insert("a", 10);
+        primary.remove("b");
+        primary.insert("c", 3);
+        primary.insert("c", 4);
+
+        let delta = primary.export_changes_since(synced).unwrap();
+        assert_eq!(delta.since, synced);
+        assert_eq!(delta.version, primary.version());
+        assert_eq!(delta.changes.len(), 3);
+        assert!(delta.changes.contains(&Change::Remove("b")));
+        assert!(delta.changes.contains(&Change::Insert("c", 4)));
+
+        replica.apply_changes(delta);
+        assert_eq!(replica.len(), 2);
+        assert_eq!(replica.get("a"), Some(&10));
+        assert_eq!(replica.get("b"), None);
+        assert_eq!(replica.get("c"), Some(&4));
+
+        let delta = primary.export_changes_since(primary.version()).unwrap();
+        assert!(delta.changes.is_empty());
+        assert!(primary
+            .export_changes_since(primary.version() + 1)
+            .is_none());
+    }
+
+    #[test]
+    fn get_mut_counts_as_a_change() {
+        let mut map = DeltaMap::new();
+        map.insert(1, 1);
+        let version = map.version();
+
+        *map.get_mut(&1).unwrap() += 1;
+        assert_eq!(
+            map.export_changes_since(version).unwrap().changes,
+            vec![Change::Insert(1, 2)]
+        );
+    }
+
+    #[test]
+    fn iterators_skip_tombstones() {
+        let mut map = DeltaMap::new();
+        for i in 0..10 {
+            map.insert(i, i);
+        }
+        map.remove(&3);
+
+        let iter = map.iter();
+        assert_eq!(iter.len(), 9);
+        assert_eq!(iter.clone().count(), 9);
+        assert_eq!(map.keys().sum::<i32>(), 42);
+        assert_eq!(map.values().sum::<i32>(), 42);
+        assert!((&map).into_iter().all(|(key, value)| key == value));
+    }
+
+    #[test]
+    fn iter_mut_counts_as_a_change() {
+        let mut map = DeltaMap::new();
+        map.insert(1, 1);
+        map.insert(2, 2);
+        map.remove(&2);
+        let version = map.version();
+
+        assert_eq!(map.iter_mut().len(), 1);
+        for (_, value) in &mut map {
+            *value += 10;
+        }
+        assert_eq!(
+            map.export_changes_since(version).unwrap().changes,
+            vec![Change::Insert(1, 11)]
+        );
+    }
+
+    #[test]
+    fn forget_changes_up_to() {
+        let mut map = DeltaMap::new();
+        map.insert(1, 1);
+        map.insert(2, 2);
+        map.remove(&1);
+        let version = map.version();
+        map.remove(&2);
+
+        map.forget_changes_up_to(version);
+        assert!(map.export_changes_since(0).is_none());
+        assert_eq!(
+            map.export_changes_since(version).unwrap().changes,
+            vec![Change::Remove(2)]
+        );
+        assert!(map.is_empty());
+    }
+}
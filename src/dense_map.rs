@@ -0,0 +1,612 @@
+use crate::equivalent::Equivalent;
+use crate::error::Error;
+use std::hash;
+use std::mem;
+
+// the index table doubles before more than 3/4 of its slots are taken, to keep probes short
+const MAX_LOAD_QUARTERS: usize = 3;
+
+// slots in a new map's index table
+const INITIAL_SLOTS: usize = 16;
+
+/// The integer type `DenseMap`'s index table stores entry positions as.
+///
+/// `usize` by default; on 64-bit targets, `u32` halves the index table for maps that are sure
+/// to stay under `u32::MAX` entries.
+pub trait EntryIndex: Copy + Eq + std::fmt::Debug {
+    /// `None` if `idx` doesn't fit
+    fn from_usize(idx: usize) -> Option<Self>;
+
+    fn to_usize(self) -> usize;
+}
+
+impl EntryIndex for usize {
+    fn from_usize(idx: usize) -> Option<Self> {
+        Some(idx)
+    }
+
+    fn to_usize(self) -> usize {
+        self
+    }
+}
+
+impl EntryIndex for u32 {
+    fn from_usize(idx: usize) -> Option<Self> {
+        u32::try_from(idx).ok()
+    }
+
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+}
+
+// the position of an entry already in the map, which fit the index type when it went in
+fn index<I: EntryIndex>(idx: usize) -> I {
+    I::from_usize(idx).expect("every entry's position fits the index type")
+}
+
+// how an entry is stored: its full hash, then the key and value
+pub(crate) type StoredEntry<K, V> = (u64, K, V);
+
+/// A map whose entries live in a single dense `Vec`.
+///
+/// The hash table is a flat, linearly probed array of positions in the entry array, with a bit
+/// per slot marking which are taken. Iterating the whole map is a
+/// linear scan over contiguous memory, however sparse the table is. Removal swaps the last entry
+/// into the hole, which keeps the array dense but means removing reorders the remaining entries.
+/// Worth it for workloads that iterate far more often than they mutate.
+///
+/// The index table stores positions as `I`; see `DenseMap::compact` for a map storing them as
+/// `u32`, which panics on inserting its `u32::MAX + 1`th entry, or fails with `try_insert`.
+#[derive(Debug)]
+pub struct DenseMap<K, V, S = hash::RandomState, I = usize> {
+    // each entry keeps its full hash, so the index table can be rebuilt without rehashing
+    entries: Vec<StoredEntry<K, V>>,
+    // a power of two long; slots not marked in `occupied` hold stale positions
+    indices: Vec<I>,
+    occupied: Vec<u64>,
+    hash_builder: S,
+}
+
+impl<K, V> DenseMap<K, V, hash::RandomState> {
+    pub fn new() -> Self {
+        DenseMap::with_hasher(hash::RandomState::new())
+    }
+}
+
+impl<K, V> DenseMap<K, V, hash::RandomState, u32> {
+    /// Creates a map whose index table stores `u32` positions, half the size of the default's on
+    /// 64-bit targets
+    pub fn compact() -> Self {
+        DenseMap::compact_with_hasher(hash::RandomState::new())
+    }
+}
+
+impl<K, V> Default for DenseMap<K, V, hash::RandomState> {
+    fn default() -> Self {
+        DenseMap::new()
+    }
+}
+
+impl<K, V, S> DenseMap<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        DenseMap::with_index_and_hasher(hash_builder)
+    }
+}
+
+impl<K, V, S> DenseMap<K, V, S, u32> {
+    pub fn compact_with_hasher(hash_builder: S) -> Self {
+        DenseMap::with_index_and_hasher(hash_builder)
+    }
+}
+
+impl<K, V, S, I: EntryIndex> DenseMap<K, V, S, I> {
+    /// Creates a map storing positions as any `EntryIndex` type
+    pub fn with_index_and_hasher(hash_builder: S) -> Self {
+        DenseMap {
+            entries: Vec::new(),
+            indices: vec![index(0); INITIAL_SLOTS],
+            occupied: vec![0; INITIAL_SLOTS.div_ceil(64)],
+            hash_builder,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.occupied.fill(0);
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    fn home(&self, hash: u64) -> usize {
+        hash as usize & (self.indices.len() - 1)
+    }
+
+    fn next_slot(&self, slot: usize) -> usize {
+        (slot + 1) & (self.indices.len() - 1)
+    }
+
+    fn is_occupied(&self, slot: usize) -> bool {
+        self.occupied[slot / 64] & (1 << (slot % 64)) != 0
+    }
+
+    fn set_occupied(&mut self, slot: usize, occupied: bool) {
+        if occupied {
+            self.occupied[slot / 64] |= 1 << (slot % 64);
+        } else {
+            self.occupied[slot / 64] &= !(1 << (slot % 64));
+        }
+    }
+
+    fn resize(&mut self) {
+        let slots = self.indices.len() * 2;
+        self.indices = vec![index(0); slots];
+        self.occupied = vec![0; slots.div_ceil(64)];
+
+        for idx in 0..self.entries.len() {
+            self.place(idx);
+        }
+    }
+
+    // indexes entry `idx` in the first free slot from its home
+    fn place(&mut self, idx: usize) {
+        let mut slot = self.home(self.entries[idx].0);
+        while self.is_occupied(slot) {
+            slot = self.next_slot(slot);
+        }
+
+        self.indices[slot] = index(idx);
+        self.set_occupied(slot, true);
+    }
+
+    // the slot holding the index of entry `idx`
+    fn slot_of(&self, idx: usize) -> usize {
+        let mut slot = self.home(self.entries[idx].0);
+        while self.indices[slot].to_usize() != idx {
+            debug_assert!(self.is_occupied(slot), "every entry is indexed");
+            slot = self.next_slot(slot);
+        }
+
+        slot
+    }
+
+    // frees a slot, moving back any later index in its probe run that may take its place, so
+    // runs never have gaps and lookups can stop at the first free slot
+    fn unindex(&mut self, mut hole: usize) {
+        let mask = self.indices.len() - 1;
+        let mut slot = self.next_slot(hole);
+
+        while self.is_occupied(slot) {
+            let home = self.home(self.entries[self.indices[slot].to_usize()].0);
+            // it can move unless its home lies after the hole
+            if slot.wrapping_sub(home) & mask >= slot.wrapping_sub(hole) & mask {
+                self.indices[hole] = self.indices[slot];
+                hole = slot;
+            }
+            slot = self.next_slot(slot);
+        }
+
+        self.set_occupied(hole, false);
+    }
+
+    // the index of the entry for `key`, along with the slot holding it
+    fn find<Q>(&self, hash: u64, key: &Q) -> Option<(usize, usize)>
+    where
+        Q: ?Sized + Equivalent<K>,
+    {
+        let mut slot = self.home(hash);
+
+        while self.is_occupied(slot) {
+            let idx = self.indices[slot].to_usize();
+            let item = &self.entries[idx];
+            if item.0 == hash && key.equivalent(&item.1) {
+                return Some((idx, slot));
+            }
+            slot = self.next_slot(slot);
+        }
+
+        None
+    }
+}
+
+impl<K, V, S, I> DenseMap<K, V, S, I> {
+    /// Iterates over the entries in the order they sit in the entry array
+    ///
+    /// That's insertion order until something is removed, which moves the last entry into the
+    /// hole.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            entries: self.entries.iter(),
+        }
+    }
+
+    /// Iterates over the entries with mutable references to the values, in the same order as
+    /// `iter`
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            entries: self.entries.iter_mut(),
+        }
+    }
+
+    /// Iterates over the keys, in the same order as `iter`
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.iter() }
+    }
+
+    /// Iterates over the values, in the same order as `iter`
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { inner: self.iter() }
+    }
+}
+
+impl<K, V, S, I> DenseMap<K, V, S, I>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+    I: EntryIndex,
+{
+    /// Panics if the map already holds as many entries as its index type can address; see
+    /// `try_insert`
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.try_insert(key, value) {
+            Ok(previous) => previous,
+            Err(_) => panic!(
+                "DenseMap is full: {} entries is as many as its index type can address",
+                self.entries.len()
+            ),
+        }
+    }
+
+    /// Like `insert`, but fails with `Error::Full` instead of panicking when a new key doesn't
+    /// fit the index type
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<Option<V>, Error> {
+        let result = self.insert_inner(key, value);
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        result
+    }
+
+    fn insert_inner(&mut self, key: K, value: V) -> Result<Option<V>, Error> {
+        let hash = self.hash_builder.hash_one(&key);
+
+        if let Some((idx, _)) = self.find(hash, &key) {
+            return Ok(Some(mem::replace(&mut self.entries[idx].2, value)));
+        }
+
+        let idx = self.entries.len();
+        I::from_usize(idx).ok_or(Error::Full)?;
+
+        if (idx + 1) * 4 > self.indices.len() * MAX_LOAD_QUARTERS {
+            self.resize();
+        }
+
+        self.entries.push((hash, key, value));
+        self.place(idx);
+
+        Ok(None)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.get_key_value(key).map(|(_, value)| value)
+    }
+
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let (idx, _) = self.find(self.hash_builder.hash_one(key), key)?;
+        let item = &self.entries[idx];
+        Some((&item.1, &item.2))
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let (idx, _) = self.find(self.hash_builder.hash_one(key), key)?;
+        Some(&mut self.entries[idx].2)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let (idx, slot) = self.find(self.hash_builder.hash_one(key), key)?;
+        self.unindex(slot);
+
+        // the last entry is about to move into the hole, so repoint its index first
+        let last = self.entries.len() - 1;
+        if idx != last {
+            let slot = self.slot_of(last);
+            self.indices[slot] = index(idx);
+        }
+        let (_, key, value) = self.entries.swap_remove(idx);
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        Some((key, value))
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.remove_entry(key).map(|entry| entry.1)
+    }
+
+    /// Checks the index table against the entry list: each entry indexed once, within reach of its
+    /// home slot, with a current cached hash and a key of its own
+    #[cfg(any(test, feature = "validate"))]
+    pub fn debug_validate(&self) {
+        let mut indexed = vec![false; self.entries.len()];
+
+        for slot in (0..self.indices.len()).filter(|&slot| self.is_occupied(slot)) {
+            let idx = self.indices[slot].to_usize();
+            let item = self
+                .entries
+                .get(idx)
+                .expect("index points past the entries");
+            let mut probe = self.home(item.0);
+            while probe != slot {
+                assert!(
+                    self.is_occupied(probe),
+                    "index is cut off from its home slot"
+                );
+                probe = self.next_slot(probe);
+            }
+            assert!(!indexed[idx], "entry is indexed more than once");
+            indexed[idx] = true;
+        }
+
+        assert!(
+            indexed.iter().all(|indexed| *indexed),
+            "entry is not indexed"
+        );
+
+        let mut seen = std::collections::HashSet::with_capacity(self.entries.len());
+        for item in self.entries.iter() {
+            assert_eq!(
+                item.0,
+                self.hash_builder.hash_one(&item.1),
+                "cached hash is stale"
+            );
+            assert!(seen.insert(&item.1), "key is stored more than once");
+        }
+    }
+}
+
+/// An iterator over a `DenseMap`'s entries, from `DenseMap::iter`.
+///
+/// A linear scan over the entry array.
+#[derive(Debug)]
+pub struct Iter<'a, K, V> {
+    entries: std::slice::Iter<'a, StoredEntry<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        self.entries.next().map(|item| (&item.1, &item.2))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.entries.next_back().map(|item| (&item.1, &item.2))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> std::iter::FusedIterator for Iter<'_, K, V> {}
+
+impl<K, V> Clone for Iter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Iter {
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+/// A mutable iterator over a `DenseMap`'s entries, from `DenseMap::iter_mut`.
+#[derive(Debug)]
+pub struct IterMut<'a, K, V> {
+    entries: std::slice::IterMut<'a, StoredEntry<K, V>>,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        self.entries.next().map(|item| (&item.1, &mut item.2))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IterMut<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.entries.next_back().map(|item| (&item.1, &mut item.2))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+
+impl<K, V> std::iter::FusedIterator for IterMut<'_, K, V> {}
+
+crate::iter::keys_and_values!(DenseMap, Iter<K, V>);
+crate::iter::borrowed_into_iterator!(DenseMap<K, V, S, I>, Iter<K, V>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashers::ConstantState;
+
+    crate::testkit::map_conformance_tests!(DenseMap::<usize, usize>::new());
+
+    mod compact {
+        use super::*;
+
+        crate::testkit::map_conformance_tests!(DenseMap::<usize, usize, _, u32>::compact());
+    }
+
+    // an index type small enough to fill up in a test
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Tiny(u8);
+
+    impl EntryIndex for Tiny {
+        fn from_usize(idx: usize) -> Option<Self> {
+            u8::try_from(idx).ok().map(Tiny)
+        }
+
+        fn to_usize(self) -> usize {
+            self.0 as usize
+        }
+    }
+
+    #[test]
+    fn iterators() {
+        let mut map = DenseMap::new();
+        for i in 0..10 {
+            map.insert(i, i);
+        }
+        map.remove(&3);
+
+        // the last entry moved into the hole
+        let keys = map.keys().copied().collect::<Vec<_>>();
+        assert_eq!(keys, [0, 1, 2, 9, 4, 5, 6, 7, 8]);
+        assert_eq!(map.iter().len(), 9);
+        assert_eq!(map.iter().next_back(), Some((&8, &8)));
+
+        for (key, value) in &mut map {
+            *value += key;
+        }
+        for (key, value) in map.iter_mut().rev().take(2) {
+            *value -= key;
+        }
+        let values = map.values().copied().collect::<Vec<_>>();
+        assert_eq!(values, [0, 2, 4, 18, 8, 10, 12, 7, 8]);
+    }
+
+    #[test]
+    fn entry_layout() {
+        use std::num::NonZeroUsize;
+
+        // a set-like map's entries hold just the hash and the key
+        assert_eq!(
+            mem::size_of::<StoredEntry<usize, ()>>(),
+            mem::size_of::<(u64, usize)>()
+        );
+        // values with a niche don't pay for their `None`
+        assert_eq!(
+            mem::size_of::<StoredEntry<usize, Option<NonZeroUsize>>>(),
+            mem::size_of::<StoredEntry<usize, usize>>()
+        );
+    }
+
+    #[test]
+    fn compact_index_table() {
+        let mut wide = DenseMap::new();
+        let mut compact = DenseMap::compact();
+        for i in 0..1000 {
+            wide.insert(i, ());
+            compact.insert(i, ());
+        }
+
+        // the slots shrink to the index type's width, and the occupancy bits stay a bit per slot
+        let slots = wide.indices.len();
+        assert_eq!(compact.indices.len(), slots);
+        assert_eq!(
+            mem::size_of_val(&wide.indices[..]),
+            slots * mem::size_of::<usize>()
+        );
+        assert_eq!(
+            mem::size_of_val(&compact.indices[..]),
+            slots * mem::size_of::<u32>()
+        );
+        assert_eq!(mem::size_of_val(&compact.occupied[..]), slots / 8);
+    }
+
+    #[test]
+    fn compact_indices() {
+        let mut map = DenseMap::<_, _, _, Tiny>::with_index_and_hasher(hash::RandomState::new());
+        for i in 0..256 {
+            map.insert(i, i);
+        }
+        assert_eq!(map.try_insert(256, 256), Err(Error::Full));
+        assert_eq!(map.try_insert(0, 0), Ok(Some(0)));
+        for i in (0..256).step_by(3) {
+            assert_eq!(map.remove(&i), Some(i));
+        }
+        assert_eq!(map.get(&254), Some(&254));
+        assert_eq!(map.get(&255), None);
+        assert_eq!(map.len(), 170);
+    }
+
+    #[test]
+    #[should_panic(expected = "DenseMap is full")]
+    fn index_type_overflow() {
+        let mut map = DenseMap::<_, _, _, Tiny>::with_index_and_hasher(hash::RandomState::new());
+        for i in 0..257 {
+            map.insert(i, i);
+        }
+    }
+
+    #[test]
+    fn iterates_in_insertion_order_until_removal() {
+        let mut map = DenseMap::new();
+
+        for i in 0..10 {
+            map.insert(i, i * 10);
+        }
+        assert!(map.iter().map(|(key, _)| *key).eq(0..10));
+
+        // the last entry fills the hole
+        map.remove(&3);
+        assert_eq!(
+            map.iter().map(|(key, _)| *key).collect::<Vec<_>>(),
+            [0, 1, 2, 9, 4, 5, 6, 7, 8]
+        );
+        assert_eq!(map.get(&9), Some(&90));
+    }
+
+    #[test]
+    fn total_collision() {
+        let mut map = DenseMap::with_hasher(ConstantState::default());
+
+        for i in 0..200 {
+            map.insert(i, i);
+        }
+        for i in (0..200).step_by(2) {
+            assert_eq!(map.remove(&i), Some(i));
+        }
+
+        assert_eq!(map.len(), 100);
+        for i in (1..200).step_by(2) {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! Deserializing maps, behind the `serde` feature.
+//!
+//! `ChainingHashMap` deserializes like the standard maps, keeping the last value of a repeated
+//! key. Formats like JSON don't forbid repeated keys, so ingestion code that needs to notice them
+//! can pick another `DuplicateKeys` policy, or keep every value with `grouped`. The free functions
+//! fit `#[serde(deserialize_with = "...")]`.
+
+use crate::chaining_map::ChainingHashMap;
+use serde::de::{self, Deserialize, Deserializer, MapAccess, Visitor};
+use std::fmt;
+use std::hash;
+use std::marker::PhantomData;
+
+// caps the allocation made up front on a size hint, which comes from untrusted input
+const MAX_PREALLOCATED: usize = 4096;
+
+/// What to do when the input repeats a key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicateKeys {
+    /// Later values replace earlier ones, like `std::collections::HashMap`
+    #[default]
+    LastWins,
+    /// Later values are ignored
+    FirstWins,
+    /// A repeated key fails the whole deserialization
+    Error,
+}
+
+impl DuplicateKeys {
+    pub fn deserialize<'de, D, K, V, S>(
+        self,
+        deserializer: D,
+    ) -> Result<ChainingHashMap<K, V, S>, D::Error>
+    where
+        D: Deserializer<'de>,
+        K: Deserialize<'de> + Eq + hash::Hash,
+        V: Deserialize<'de>,
+        S: hash::BuildHasher + Default,
+    {
+        deserializer.deserialize_map(MapVisitor {
+            policy: self,
+            marker: PhantomData,
+        })
+    }
+}
+
+/// Deserializes a map, keeping the first value of a repeated key
+pub fn first_wins<'de, D, K, V, S>(deserializer: D) -> Result<ChainingHashMap<K, V, S>, D::Error>
+where
+    D: Deserializer<'de>,
+    K: Deserialize<'de> + Eq + hash::Hash,
+    V: Deserialize<'de>,
+    S: hash::BuildHasher + Default,
+{
+    DuplicateKeys::FirstWins.deserialize(deserializer)
+}
+
+/// Deserializes a map, failing on a repeated key
+pub fn reject_duplicates<'de, D, K, V, S>(
+    deserializer: D,
+) -> Result<ChainingHashMap<K, V, S>, D::Error>
+where
+    D: Deserializer<'de>,
+    K: Deserialize<'de> + Eq + hash::Hash,
+    V: Deserialize<'de>,
+    S: hash::BuildHasher + Default,
+{
+    DuplicateKeys::Error.deserialize(deserializer)
+}
+
+/// Deserializes a map keeping every value of a repeated key, in input order
+pub fn grouped<'de, D, K, V, S>(deserializer: D) -> Result<ChainingHashMap<K, Vec<V>, S>, D::Error>
+where
+    D: Deserializer<'de>,
+    K: Deserialize<'de> + Eq + hash::Hash,
+    V: Deserialize<'de>,
+    S: hash::BuildHasher + Default,
+{
+    deserializer.deserialize_map(GroupedVisitor(PhantomData))
+}
+
+impl<'de, K, V, S> Deserialize<'de> for ChainingHashMap<K, V, S>
+where
+    K: Deserialize<'de> + Eq + hash::Hash,
+    V: Deserialize<'de>,
+    S: hash::BuildHasher + Default,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        DuplicateKeys::LastWins.deserialize(deserializer)
+    }
+}
+
+fn empty_map<'de, A, K, V, S>(access: &A) -> ChainingHashMap<K, V, S>
+where
+    A: MapAccess<'de>,
+    S: Default,
+{
+    let capacity = access.size_hint().unwrap_or(0).min(MAX_PREALLOCATED);
+    ChainingHashMap::with_capacity_and_hasher(capacity, S::default())
+}
+
+struct MapVisitor<K, V, S> {
+    policy: DuplicateKeys,
+    marker: PhantomData<ChainingHashMap<K, V, S>>,
+}
+
+impl<'de, K, V, S> Visitor<'de> for MapVisitor<K, V, S>
+where
+    K: Deserialize<'de> + Eq + hash::Hash,
+    V: Deserialize<'de>,
+    S: hash::BuildHasher + Default,
+{
+    type Value = ChainingHashMap<K, V, S>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map")
+    }
+
+    fn visit_map<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut map = empty_map(&access);
+
+        while let Some((key, value)) = access.next_entry()? {
+            match self.policy {
+                DuplicateKeys::LastWins => {
+                    map.insert(key, value);
+                }
+                DuplicateKeys::FirstWins => {
+                    map.get_or_insert_with(key, || value);
+                }
+                DuplicateKeys::Error => {
+                    if map.insert(key, value).is_some() {
+                        return Err(de::Error::custom("duplicate map key"));
+                    }
+                }
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+struct GroupedVisitor<K, V, S>(PhantomData<ChainingHashMap<K, Vec<V>, S>>);
+
+impl<'de, K, V, S> Visitor<'de> for GroupedVisitor<K, V, S>
+where
+    K: Deserialize<'de> + Eq + hash::Hash,
+    V: Deserialize<'de>,
+    S: hash::BuildHasher + Default,
+{
+    type Value = ChainingHashMap<K, Vec<V>, S>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map")
+    }
+
+    fn visit_map<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut map = empty_map(&access);
+
+        while let Some((key, value)) = access.next_entry()? {
+            map.get_or_insert_with(key, Vec::new).push(value);
+        }
+
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPEATED: &str = r#"{"a": 1, "b": 2, "a": 3}"#;
+
+    fn with_policy(policy: DuplicateKeys) -> serde_json::Result<ChainingHashMap<String, i32>> {
+        policy.deserialize(&mut serde_json::Deserializer::from_str(REPEATED))
+    }
+
+    #[test]
+    fn policies() {
+        let last = serde_json::from_str::<ChainingHashMap<String, i32>>(REPEATED).unwrap();
+        assert_eq!(last.get("a"), Some(&3));
+        assert_eq!(last.len(), 2);
+
+        assert_eq!(
+            with_policy(DuplicateKeys::LastWins).unwrap().get("a"),
+            Some(&3)
+        );
+        assert_eq!(
+            with_policy(DuplicateKeys::FirstWins).unwrap().get("a"),
+            Some(&1)
+        );
+
+        let error = with_policy(DuplicateKeys::Error).unwrap_err();
+        assert!(error.to_string().contains("duplicate map key"));
+    }
+
+    #[test]
+    fn deserialize_with() {
+        #[derive(serde::Deserialize)]
+        struct Record {
+            #[serde(deserialize_with = "grouped")]
+            headers: ChainingHashMap<String, Vec<String>>,
+            #[serde(deserialize_with = "reject_duplicates")]
+            fields: ChainingHashMap<String, i32>,
+        }
+
+        let record: Record = serde_json::from_str(
+            r#"{"headers": {"set-cookie": "a", "host": "h", "set-cookie": "b"}, "fields": {"x": 1}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            record.headers.get("set-cookie"),
+            Some(&vec!["a".to_string(), "b".to_string()])
+        );
+        assert_eq!(record.headers.get("host"), Some(&vec!["h".to_string()]));
+        assert_eq!(record.fields.get("x"), Some(&1));
+
+        assert!(
+            serde_json::from_str::<Record>(r#"{"headers": {}, "fields": {"x": 1, "x": 2}}"#)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use crate::chaining_map::ChainingHashMap;
+use std::borrow::Borrow;
+use std::hash;
+
+/// Key equivalence for map lookups.
+///
+/// Lookups take any `Q: Equivalent<K>` that hashes the same way as the equivalent `K`. Every
+/// borrowed form of a key (`&str` for `String`, `&[T]` for `Vec<T>`, ...) is equivalent through
+/// the blanket impl; implement it for your own types to look keys up by something that can't be
+/// produced with `Borrow`, like `CompositeKey` does.
+pub trait Equivalent<K: ?Sized> {
+    fn equivalent(&self, key: &K) -> bool;
+}
+
+impl<Q, K> Equivalent<K> for Q
+where
+    Q: ?Sized + Eq,
+    K: ?Sized + Borrow<Q>,
+{
+    fn equivalent(&self, key: &K) -> bool {
+        self == key.borrow()
+    }
+}
+
+/// Borrowed halves of an `(A, B)` key.
+///
+/// Hashes exactly like the owned tuple, so a map keyed by `(String, String)` can be queried
+/// with two `&str`s without building the tuple.
+#[derive(Debug, Clone, Copy)]
+pub struct CompositeKey<'a, A: ?Sized, B: ?Sized>(pub &'a A, pub &'a B);
+
+impl<A, B> hash::Hash for CompositeKey<'_, A, B>
+where
+    A: ?Sized + hash::Hash,
+    B: ?Sized + hash::Hash,
+{
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        // same sequence of writes as `<(A, B) as Hash>::hash`
+        self.0.hash(state);
+        self.1.hash(state);
+    }
+}
+
+impl<A, B, KA, KB> Equivalent<(KA, KB)> for CompositeKey<'_, A, B>
+where
+    A: ?Sized + Eq,
+    B: ?Sized + Eq,
+    KA: Borrow<A>,
+    KB: Borrow<B>,
+{
+    fn equivalent(&self, key: &(KA, KB)) -> bool {
+        self.0 == key.0.borrow() && self.1 == key.1.borrow()
+    }
+}
+
+impl<A, B, V, S> ChainingHashMap<(A, B), V, S>
+where
+    A: Eq + hash::Hash,
+    B: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    /// Looks up a tuple key by borrowed parts
+    pub fn get_parts<QA, QB>(&self, a: &QA, b: &QB) -> Option<&V>
+    where
+        QA: ?Sized + Eq + hash::Hash,
+        QB: ?Sized + Eq + hash::Hash,
+        A: Borrow<QA>,
+        B: Borrow<QB>,
+    {
+        self.get(&CompositeKey(a, b))
+    }
+
+    pub fn get_parts_mut<QA, QB>(&mut self, a: &QA, b: &QB) -> Option<&mut V>
+    where
+        QA: ?Sized + Eq + hash::Hash,
+        QB: ?Sized + Eq + hash::Hash,
+        A: Borrow<QA>,
+        B: Borrow<QB>,
+    {
+        self.get_mut(&CompositeKey(a, b))
+    }
+
+    pub fn remove_parts<QA, QB>(&mut self, a: &QA, b: &QB) -> Option<V>
+    where
+        QA: ?Sized + Eq + hash::Hash,
+        QB: ?Sized + Eq + hash::Hash,
+        A: Borrow<QA>,
+        B: Borrow<QB>,
+    {
+        self.remove(&CompositeKey(a, b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hash::BuildHasher;
+
+    #[test]
+    fn composite_key_hashes_like_tuple() {
+        let state = hash::RandomState::new();
+        let owned = ("user".to_string(), vec![1u8, 2, 3]);
+
+        assert_eq!(
+            state.hash_one(&owned),
+            state.hash_one(CompositeKey("user", &[1u8, 2, 3][..]))
+        );
+    }
+
+    #[test]
+    fn get_parts() {
+        let mut map = ChainingHashMap::new();
+
+        map.insert(("GET".to_string(), "/index".to_string()), 1);
+        map.insert(("POST".to_string(), "/index".to_string()), 2);
+
+        assert_eq!(map.get_parts("GET", "/index"), Some(&1));
+        assert_eq!(map.get_parts("POST", "/index"), Some(&2));
+        assert_eq!(map.get_parts("GET", "/missing"), None);
+
+        *map.get_parts_mut("GET", "/index").unwrap() += 10;
+        assert_eq!(map.get(&CompositeKey("GET", "/index")), Some(&11));
+
+        assert_eq!(map.remove_parts("POST", "/index"), Some(2));
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn custom_equivalent() {
+        // a case-insensitive view over upper case keys
+        #[derive(Hash)]
+        struct Upper<'a>(&'a str);
+
+        impl Equivalent<String> for Upper<'_> {
+            fn equivalent(&self, key: &String) -> bool {
+                key.eq_ignore_ascii_case(self.0)
+            }
+        }
+
+        let mut map = ChainingHashMap::new();
+        map.insert("KEY".to_string(), 1);
+
+        // only works because both sides hash the same bytes
+        assert_eq!(map.get(&Upper("KEY")), Some(&1));
+    }
+}
@@ -0,0 +1,48 @@
+use std::collections::TryReserveError;
+use std::fmt;
+
+/// Why one of the crate's `try_*` operations failed, where its counterpart would panic or abort.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// There's no entry for the key, where indexing would panic
+    KeyNotFound,
+    /// The same key was passed twice to an operation that needs distinct keys
+    DuplicateKey,
+    /// The map can't address another entry, e.g. a `DenseMap` whose index type has run out
+    Full,
+    /// A shared memory region too small to hold even one entry
+    RegionTooSmall,
+    /// A shared map's writer stopped partway through writing a slot, most likely by dying
+    WriterStalled,
+    /// The table's memory couldn't be allocated, or its size doesn't fit in a `usize`
+    Allocation(TryReserveError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::KeyNotFound => f.write_str("no entry found for key"),
+            Error::DuplicateKey => f.write_str("the same key was passed twice"),
+            Error::Full => f.write_str("the map can't address any more entries"),
+            Error::RegionTooSmall => f.write_str("region is too small for a shared map"),
+            Error::WriterStalled => f.write_str("the shared map's writer stalled mid-write"),
+            Error::Allocation(err) => write!(f, "allocating the table failed: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Allocation(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<TryReserveError> for Error {
+    fn from(err: TryReserveError) -> Self {
+        Error::Allocation(err)
+    }
+}
@@ -0,0 +1,496 @@
+use crate::equivalent::Equivalent;
+use std::hash;
+use std::mem;
+
+// entries per bucket before it has to split
+const BUCKET_SIZE: usize = 16;
+
+// buckets whose entries all agree on this many low hash bits overflow instead of splitting,
+// which keeps the directory bounded under heavy collisions
+const MAX_DEPTH: u32 = 24;
+
+#[derive(Debug)]
+struct Bucket<K, V> {
+    local_depth: u32,
+    // each entry keeps its full hash, so splits never rehash
+    entries: Vec<(u64, K, V)>,
+}
+
+impl<K, V> Bucket<K, V> {
+    fn new(local_depth: u32) -> Self {
+        Bucket {
+            local_depth,
+            entries: Vec::new(),
+        }
+    }
+}
+
+/// A map using extendible hashing.
+///
+/// A directory of `2^global_depth` slots, indexed by the low bits of the hash, points at
+/// buckets of bounded size. A full bucket splits in two on the next bit of the hash and only the
+/// directory slots that pointed at it are updated, so growth never rehashes the whole table; the
+/// directory itself doubles when a bucket that is already as deep as the directory splits.
+/// Buckets are never merged back on removal.
+#[derive(Debug)]
+pub struct ExtendibleHashMap<K, V, S = hash::RandomState> {
+    directory: Vec<usize>,
+    buckets: Vec<Bucket<K, V>>,
+    global_depth: u32,
+    load: usize,
+    hash_builder: S,
+}
+
+impl<K, V> ExtendibleHashMap<K, V, hash::RandomState> {
+    pub fn new() -> Self {
+        ExtendibleHashMap::with_hasher(hash::RandomState::new())
+    }
+}
+
+impl<K, V> Default for ExtendibleHashMap<K, V, hash::RandomState> {
+    fn default() -> Self {
+        ExtendibleHashMap::new()
+    }
+}
+
+impl<K, V, S> ExtendibleHashMap<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        ExtendibleHashMap {
+            directory: vec![0],
+            buckets: vec![Bucket::new(0)],
+            global_depth: 0,
+            load: 0,
+            hash_builder,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.load
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.load == 0
+    }
+
+    /// Removes every entry, shrinking back to a single bucket
+    pub fn clear(&mut self) {
+        self.directory = vec![0];
+        self.buckets = vec![Bucket::new(0)];
+        self.global_depth = 0;
+        self.load = 0;
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    /// The number of hash bits the directory is indexed by
+    pub fn global_depth(&self) -> u32 {
+        self.global_depth
+    }
+
+    pub fn bucket_count(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Iterates over the entries, in no particular order
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            buckets: self.buckets.iter(),
+            chain: [].iter(),
+            remaining: self.load,
+        }
+    }
+
+    /// Iterates over the entries with mutable references to the values, in the same order as
+    /// `iter`
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            buckets: self.buckets.iter_mut(),
+            chain: [].iter_mut(),
+            remaining: self.load,
+        }
+    }
+
+    /// Iterates over the keys, in the same order as `iter`
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.iter() }
+    }
+
+    /// Iterates over the values, in the same order as `iter`
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { inner: self.iter() }
+    }
+
+    fn bucket_for(&self, hash: u64) -> usize {
+        let mask = (1u64 << self.global_depth) - 1;
+        self.directory[(hash & mask) as usize]
+    }
+
+    // whether splitting `bucket` could separate anything from a new entry with `hash`
+    fn can_split(&self, bucket: usize, hash: u64) -> bool {
+        let mask = (1u64 << MAX_DEPTH) - 1;
+        let bucket = &self.buckets[bucket];
+
+        bucket.local_depth < MAX_DEPTH
+            && bucket
+                .entries
+                .iter()
+                .any(|item| (item.0 ^ hash) & mask != 0)
+    }
+
+    fn split(&mut self, bucket: usize) {
+        let depth = self.buckets[bucket].local_depth;
+
+        if depth == self.global_depth {
+            self.directory.extend_from_within(..);
+            self.global_depth += 1;
+        }
+
+        // entries with the next hash bit set move to the new bucket
+        let bit = 1u64 << depth;
+        let (stay, moved) = mem::take(&mut self.buckets[bucket].entries)
+            .into_iter()
+            .partition(|item| item.0 & bit == 0);
+
+        self.buckets[bucket] = Bucket {
+            local_depth: depth + 1,
+            entries: stay,
+        };
+
+        let new_bucket = self.buckets.len();
+        self.buckets.push(Bucket {
+            local_depth: depth + 1,
+            entries: moved,
+        });
+
+        for (slot, target) in self.directory.iter_mut().enumerate() {
+            if *target == bucket && slot as u64 & bit != 0 {
+                *target = new_bucket;
+            }
+        }
+    }
+}
+
+impl<K, V, S> ExtendibleHashMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let result = self.insert_inner(key, value);
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        result
+    }
+
+    fn insert_inner(&mut self, key: K, value: V) -> Option<V> {
+        let hash = self.hash_builder.hash_one(&key);
+
+        loop {
+            let bucket = self.bucket_for(hash);
+
+            if let Some(item) = self.buckets[bucket]
+                .entries
+                .iter_mut()
+                .find(|item| item.0 == hash && key == item.1)
+            {
+                return Some(mem::replace(&mut item.2, value));
+            }
+
+            if self.buckets[bucket].entries.len() < BUCKET_SIZE || !self.can_split(bucket, hash) {
+                self.buckets[bucket].entries.push((hash, key, value));
+                self.load += 1;
+                return None;
+            }
+
+            self.split(bucket);
+        }
+    }
+
+    fn find<Q>(&self, key: &Q) -> Option<&(u64, K, V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let hash = self.hash_builder.hash_one(key);
+        self.buckets[self.bucket_for(hash)]
+            .entries
+            .iter()
+            .find(|item| item.0 == hash && key.equivalent(&item.1))
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.find(key).map(|item| &item.2)
+    }
+
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.find(key).map(|item| (&item.1, &item.2))
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let hash = self.hash_builder.hash_one(key);
+        let bucket = self.bucket_for(hash);
+        self.buckets[bucket]
+            .entries
+            .iter_mut()
+            .find(|item| item.0 == hash && key.equivalent(&item.1))
+            .map(|item| &mut item.2)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let hash = self.hash_builder.hash_one(key);
+        let bucket = self.bucket_for(hash);
+        let bucket = &mut self.buckets[bucket];
+
+        let idx = bucket
+            .entries
+            .iter()
+            .position(|item| item.0 == hash && key.equivalent(&item.1))?;
+        let (_, key, value) = bucket.entries.swap_remove(idx);
+        self.load -= 1;
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        Some((key, value))
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.remove_entry(key).map(|entry| entry.1)
+    }
+
+    /// Panics if the directory's size or its slot-to-bucket links don't match the depths, or a bucket
+    /// holds a misplaced, stale or repeated entry
+    #[cfg(any(test, feature = "validate"))]
+    pub fn debug_validate(&self) {
+        assert_eq!(
+            self.directory.len(),
+            1 << self.global_depth,
+            "directory size does not match the global depth"
+        );
+
+        let mut seen = std::collections::HashSet::with_capacity(self.load);
+
+        for (idx, bucket) in self.buckets.iter().enumerate() {
+            assert!(
+                bucket.local_depth <= self.global_depth,
+                "bucket is deeper than the directory"
+            );
+
+            let slots = self
+                .directory
+                .iter()
+                .filter(|target| **target == idx)
+                .count();
+            assert_eq!(
+                slots,
+                1 << (self.global_depth - bucket.local_depth),
+                "bucket is not referenced by the expected number of directory slots"
+            );
+
+            for item in bucket.entries.iter() {
+                assert_eq!(
+                    item.0,
+                    self.hash_builder.hash_one(&item.1),
+                    "cached hash is stale"
+                );
+                assert_eq!(
+                    self.bucket_for(item.0),
+                    idx,
+                    "entry is stored in the wrong bucket"
+                );
+                assert!(seen.insert(&item.1), "key is stored more than once");
+            }
+        }
+
+        assert_eq!(
+            seen.len(),
+            self.load,
+            "load does not match the number of stored entries"
+        );
+    }
+}
+
+/// An iterator over an `ExtendibleHashMap`'s entries, from `ExtendibleHashMap::iter`.
+///
+/// Walks the buckets in the order they were created, not in directory order, so a bucket
+/// several directory slots point at is visited once.
+#[derive(Debug)]
+pub struct Iter<'a, K, V> {
+    buckets: std::slice::Iter<'a, Bucket<K, V>>,
+    chain: std::slice::Iter<'a, (u64, K, V)>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            if let Some(item) = self.chain.next() {
+                self.remaining -= 1;
+                return Some((&item.1, &item.2));
+            }
+            self.chain = self.buckets.next()?.entries.iter();
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> std::iter::FusedIterator for Iter<'_, K, V> {}
+
+impl<K, V> Clone for Iter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Iter {
+            buckets: self.buckets.clone(),
+            chain: self.chain.clone(),
+            remaining: self.remaining,
+        }
+    }
+}
+
+/// A mutable iterator over an `ExtendibleHashMap`'s entries, from `ExtendibleHashMap::iter_mut`.
+#[derive(Debug)]
+pub struct IterMut<'a, K, V> {
+    buckets: std::slice::IterMut<'a, Bucket<K, V>>,
+    chain: std::slice::IterMut<'a, (u64, K, V)>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        loop {
+            if let Some(item) = self.chain.next() {
+                self.remaining -= 1;
+                return Some((&item.1, &mut item.2));
+            }
+            self.chain = self.buckets.next()?.entries.iter_mut();
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+
+impl<K, V> std::iter::FusedIterator for IterMut<'_, K, V> {}
+
+crate::iter::keys_and_values!(ExtendibleHashMap, Iter<K, V>);
+crate::iter::borrowed_into_iterator!(ExtendibleHashMap<K, V, S>, Iter<K, V>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashers::{ConstantState, LowBitsState};
+
+    crate::testkit::map_conformance_tests!(ExtendibleHashMap::<usize, usize>::new());
+
+    #[test]
+    fn iterators() {
+        let mut map = ExtendibleHashMap::new();
+        for i in 0..100 {
+            map.insert(i, i);
+        }
+
+        let iter = map.iter();
+        assert_eq!(iter.len(), 100);
+        assert_eq!(iter.clone().count(), 100);
+        assert_eq!(map.keys().sum::<i32>(), 4950);
+
+        for (key, value) in &mut map {
+            *value += key;
+        }
+        map.iter_mut().take(10).for_each(|(_, value)| *value = -1);
+        assert_eq!(map.iter_mut().len(), 100);
+        assert_eq!(map.values().filter(|value| **value == -1).count(), 10);
+        assert!((&map)
+            .into_iter()
+            .all(|(key, value)| *value == -1 || *value == key * 2));
+    }
+
+    #[test]
+    fn grows_by_splitting() {
+        let mut map = ExtendibleHashMap::new();
+
+        for i in 0..1000 {
+            map.insert(i, i);
+        }
+
+        assert!(map.global_depth() >= 6);
+        assert!(map.bucket_count() >= 1000 / BUCKET_SIZE);
+        assert!(map
+            .buckets
+            .iter()
+            .all(|bucket| bucket.entries.len() <= BUCKET_SIZE));
+
+        for i in 0..1000 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn identical_hashes_overflow() {
+        let mut map = ExtendibleHashMap::with_hasher(ConstantState::default());
+
+        for i in 0..100 {
+            map.insert(i, i);
+        }
+
+        // nothing to split on, so the single bucket just grows
+        assert_eq!(map.global_depth(), 0);
+        assert_eq!(map.get(&50), Some(&50));
+    }
+
+    #[test]
+    fn deep_collisions_are_bounded() {
+        // multiples of 2^30 agree on every bit below `MAX_DEPTH`
+        let mut map = ExtendibleHashMap::with_hasher(LowBitsState::new(64));
+
+        for i in 0..(BUCKET_SIZE as u64 * 2) {
+            map.insert(i << 30, i);
+        }
+
+        assert_eq!(map.global_depth(), 0);
+        assert_eq!(map.len(), BUCKET_SIZE * 2);
+    }
+
+    #[test]
+    fn clear() {
+        let mut map = ExtendibleHashMap::new();
+
+        for i in 0..100 {
+            map.insert(i, i);
+        }
+        map.clear();
+
+        assert!(map.is_empty());
+        assert_eq!(map.bucket_count(), 1);
+        assert_eq!(map.get(&1), None);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::chaining_map::ChainingHashMap;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash;
+
+mod private {
+    pub trait Sealed {}
+
+    impl Sealed for f32 {}
+    impl Sealed for f64 {}
+}
+
+/// Floating point types usable inside a `FloatKey`.
+pub trait Float: Copy + private::Sealed {
+    #[doc(hidden)]
+    type Bits: Eq + Ord + hash::Hash;
+
+    #[doc(hidden)]
+    fn canonicalize(self) -> Self;
+
+    #[doc(hidden)]
+    fn to_key_bits(self) -> Self::Bits;
+
+    #[doc(hidden)]
+    fn cmp_total(&self, other: &Self) -> Ordering;
+}
+
+macro_rules! impl_float {
+    ($float:ty, $bits:ty) => {
+        impl Float for $float {
+            type Bits = $bits;
+
+            fn canonicalize(self) -> Self {
+                if self.is_nan() {
+                    <$float>::NAN
+                } else if self == 0.0 {
+                    // folds -0.0 into 0.0
+                    0.0
+                } else {
+                    self
+                }
+            }
+
+            fn to_key_bits(self) -> $bits {
+                self.to_bits()
+            }
+
+            fn cmp_total(&self, other: &Self) -> Ordering {
+                self.total_cmp(other)
+            }
+        }
+    };
+}
+
+impl_float!(f32, u32);
+impl_float!(f64, u64);
+
+/// A float usable as a map key.
+///
+/// Floats aren't `Eq` or `Hash` because `NaN != NaN` and `0.0 == -0.0` with different bits.
+/// `FloatKey` canonicalizes on construction, folding every NaN into one NaN and `-0.0` into
+/// `0.0`, then compares and hashes the bit pattern. Keys are totally ordered, with NaN above
+/// positive infinity.
+#[derive(Clone, Copy)]
+pub struct FloatKey<F: Float = f64>(F);
+
+impl<F: Float> FloatKey<F> {
+    pub fn new(value: F) -> Self {
+        FloatKey(value.canonicalize())
+    }
+
+    /// The canonicalized value
+    pub fn value(self) -> F {
+        self.0
+    }
+}
+
+impl<F: Float> From<F> for FloatKey<F> {
+    fn from(value: F) -> Self {
+        FloatKey::new(value)
+    }
+}
+
+impl<F: Float> PartialEq for FloatKey<F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_key_bits() == other.0.to_key_bits()
+    }
+}
+
+impl<F: Float> Eq for FloatKey<F> {}
+
+impl<F: Float> hash::Hash for FloatKey<F> {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.0.to_key_bits().hash(state);
+    }
+}
+
+impl<F: Float> PartialOrd for FloatKey<F> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<F: Float> Ord for FloatKey<F> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.cmp_total(&other.0)
+    }
+}
+
+impl<F: Float + fmt::Debug> fmt::Debug for FloatKey<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<F: Float + fmt::Display> fmt::Display for FloatKey<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A map keyed by `f64`s
+pub type FloatMap<V, S = hash::RandomState> = ChainingHashMap<FloatKey<f64>, V, S>;
+
+/// A map keyed by `f32`s
+pub type F32Map<V, S = hash::RandomState> = ChainingHashMap<FloatKey<f32>, V, S>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nan_and_zero_are_canonicalized() {
+        let quiet = f64::NAN;
+        let other_nan = f64::from_bits(f64::NAN.to_bits() ^ 1);
+        assert!(other_nan.is_nan());
+
+        assert_eq!(FloatKey::new(quiet), FloatKey::new(other_nan));
+        assert_eq!(FloatKey::new(-f64::NAN), FloatKey::new(quiet));
+        assert_eq!(FloatKey::new(-0.0f64), FloatKey::new(0.0));
+        assert!(FloatKey::new(-0.0f64).value().is_sign_positive());
+        assert_ne!(FloatKey::new(1.0f64), FloatKey::new(1.0 + f64::EPSILON));
+    }
+
+    #[test]
+    fn total_order() {
+        let mut keys = [f64::NAN, 1.0, f64::NEG_INFINITY, -2.5, f64::INFINITY, 0.0]
+            .into_iter()
+            .map(FloatKey::new)
+            .collect::<Vec<_>>();
+        keys.sort();
+
+        let sorted = keys.iter().map(|key| key.value()).collect::<Vec<_>>();
+        assert_eq!(
+            &sorted[..5],
+            &[f64::NEG_INFINITY, -2.5, 0.0, 1.0, f64::INFINITY]
+        );
+        assert!(sorted[5].is_nan());
+    }
+
+    #[test]
+    fn float_map() {
+        let mut map = FloatMap::new();
+
+        map.insert(FloatKey::new(0.5), "half");
+        map.insert(FloatKey::new(f64::NAN), "nan");
+        map.insert(FloatKey::new(0.0), "zero");
+
+        assert_eq!(map.get(&FloatKey::new(0.5)), Some(&"half"));
+        assert_eq!(map.get(&FloatKey::new(f64::NAN)), Some(&"nan"));
+        assert_eq!(map.get(&FloatKey::new(-0.0)), Some(&"zero"));
+        assert_eq!(
+            map.insert(FloatKey::new(-0.0), "negative zero"),
+            Some("zero")
+        );
+        assert_eq!(map.len(), 3);
+    }
+
+    #[test]
+    fn f32_map() {
+        let mut map = F32Map::new();
+
+        map.insert(1.5f32.into(), 1);
+
+        assert_eq!(map.get(&FloatKey::new(1.5f32)), Some(&1));
+    }
+}
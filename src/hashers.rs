@@ -0,0 +1,227 @@
+//! Deliberately bad hashers for testing and benchmarking.
+//!
+//! These exist to push a map into its worst cases (every key in one chain, keys that only
+//! differ in bits the index ignores) so correctness under collision can be tested directly.
+//! Never use them for a real map.
+
+use std::hash;
+use std::iter::FusedIterator;
+
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Hashes every key to 0, so every key collides.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ConstantHasher;
+
+impl hash::Hasher for ConstantHasher {
+    fn finish(&self) -> u64 {
+        0
+    }
+
+    fn write(&mut self, _bytes: &[u8]) {}
+}
+
+pub type ConstantState = hash::BuildHasherDefault<ConstantHasher>;
+
+/// Keeps only the low `bits` bits of the hash.
+///
+/// A single integer key hashes to itself (masked), so keys that are equal modulo `2^bits`
+/// collide, which is exactly the pattern that breaks maps that index by the low bits alone.
+#[derive(Debug, Clone, Copy)]
+pub struct LowBitsHasher {
+    state: u64,
+    mask: u64,
+}
+
+impl hash::Hasher for LowBitsHasher {
+    fn finish(&self) -> u64 {
+        self.state & self.mask
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write_u64(*byte as u64);
+        }
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.state = self.state.wrapping_mul(FNV_PRIME) ^ i;
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+}
+
+/// Builds `LowBitsHasher`s keeping the configured number of bits.
+#[derive(Debug, Clone, Copy)]
+pub struct LowBitsState {
+    bits: u32,
+}
+
+impl LowBitsState {
+    pub fn new(bits: u32) -> Self {
+        assert!(bits <= 64, "cannot keep more than 64 bits of a u64 hash");
+        LowBitsState { bits }
+    }
+}
+
+impl hash::BuildHasher for LowBitsState {
+    type Hasher = LowBitsHasher;
+
+    fn build_hasher(&self) -> LowBitsHasher {
+        let mask = if self.bits == 64 {
+            u64::MAX
+        } else {
+            (1 << self.bits) - 1
+        };
+
+        LowBitsHasher { state: 0, mask }
+    }
+}
+
+/// Yields pairs of distinct `u64` keys that land in the same bucket of a table with the given
+/// number of buckets under the given hasher.
+///
+/// Found by brute force over `0, 1, 2, ...`, so this works against any `BuildHasher`,
+/// including randomly seeded ones (as long as the same instance is used for the map).
+#[derive(Debug)]
+pub struct CollidingPairs<'a, S> {
+    hash_builder: &'a S,
+    buckets: u64,
+    seen: Vec<Option<u64>>,
+    next_candidate: u64,
+}
+
+impl<'a, S: hash::BuildHasher> CollidingPairs<'a, S> {
+    pub fn new(hash_builder: &'a S, buckets: usize) -> Self {
+        assert!(buckets > 0, "a table needs at least one bucket");
+        CollidingPairs {
+            hash_builder,
+            buckets: buckets as u64,
+            seen: vec![None; buckets],
+            next_candidate: 0,
+        }
+    }
+}
+
+impl<S: hash::BuildHasher> Iterator for CollidingPairs<'_, S> {
+    type Item = (u64, u64);
+
+    fn next(&mut self) -> Option<(u64, u64)> {
+        loop {
+            let candidate = self.next_candidate;
+            self.next_candidate = self.next_candidate.checked_add(1)?;
+
+            let bucket = (self.hash_builder.hash_one(candidate) % self.buckets) as usize;
+            match self.seen[bucket].take() {
+                Some(first) => return Some((first, candidate)),
+                None => self.seen[bucket] = Some(candidate),
+            }
+        }
+    }
+}
+
+// the candidate counter saturates at `u64::MAX`, after which `next` keeps returning `None`
+impl<S: hash::BuildHasher> FusedIterator for CollidingPairs<'_, S> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chaining_map::ChainingHashMap;
+    use std::hash::BuildHasher;
+
+    #[test]
+    fn constant_hasher_collides_everything() {
+        let state = ConstantState::default();
+
+        assert_eq!(state.hash_one("a"), state.hash_one(12345u64));
+    }
+
+    #[test]
+    fn low_bits_hasher_masks() {
+        let state = LowBitsState::new(4);
+
+        assert_eq!(state.hash_one(3u64), 3);
+        assert_eq!(state.hash_one(3u64), state.hash_one(19u64));
+        assert_ne!(state.hash_one(3u64), state.hash_one(4u64));
+        assert!(LowBitsState::new(64).hash_one(u64::MAX) == u64::MAX);
+    }
+
+    #[test]
+    fn colliding_pairs_collide() {
+        let state = std::hash::RandomState::new();
+
+        for (a, b) in CollidingPairs::new(&state, 32).take(100) {
+            assert_ne!(a, b);
+            assert_eq!(state.hash_one(a) % 32, state.hash_one(b) % 32);
+        }
+    }
+
+    #[test]
+    fn map_survives_total_collision() {
+        let mut map = ChainingHashMap::with_capacity_and_hasher(4, ConstantState::default());
+
+        for i in 0..500 {
+            assert_eq!(map.insert(i, i), None);
+        }
+        for i in (0..500).step_by(2) {
+            assert_eq!(map.remove(&i), Some(i));
+        }
+
+        assert_eq!(map.len(), 250);
+        for i in 0..500 {
+            let expected = if i % 2 == 0 { None } else { Some(&i) };
+            assert_eq!(map.get(&i), expected);
+        }
+    }
+
+    #[test]
+    fn map_survives_low_bit_collisions() {
+        let mut map = ChainingHashMap::with_capacity_and_hasher(4, LowBitsState::new(3));
+
+        // multiples of 8 all share the same low bits
+        for i in 0..300u64 {
+            assert_eq!(map.insert(i * 8, i), None);
+        }
+        for i in 0..300u64 {
+            assert_eq!(map.get(&(i * 8)), Some(&i));
+        }
+    }
+
+    #[test]
+    fn map_survives_colliding_pairs() {
+        let state = std::hash::RandomState::new();
+        let pairs = CollidingPairs::new(&state, 28).take(50).collect::<Vec<_>>();
+        let mut map = ChainingHashMap::with_capacity_and_hasher(20, state);
+
+        for (a, b) in pairs.iter() {
+            map.insert(*a, "a");
+            map.insert(*b, "b");
+        }
+        for (a, b) in pairs.iter() {
+            assert_eq!(map.remove(a), Some("a"));
+            assert_eq!(map.get(b), Some(&"b"));
+        }
+    }
+
+    mod constant {
+        use super::*;
+
+        crate::testkit::map_conformance_tests!(ChainingHashMap::<usize, usize, _>::with_hasher(
+            ConstantState::default()
+        ));
+    }
+}
@@ -0,0 +1,413 @@
+use crate::chaining_map::ChainingHashMap;
+use crate::equivalent::Equivalent;
+use std::fmt;
+use std::hash;
+
+// one secondary index: the primary keys of every entry, grouped by the index key extracted from
+// its value
+struct SecondaryIndex<K, V, I, S> {
+    name: &'static str,
+    extract: Box<dyn Fn(&V) -> I + Send + Sync>,
+    entries: ChainingHashMap<I, Vec<K>, S>,
+}
+
+/// A map with secondary indexes over its values, kept up to date on every write; a tiny
+/// in-memory table.
+///
+/// Each index is declared with a name and a closure extracting an index key from a value, and
+/// maps every index key to the primary keys of the entries it was extracted from, so
+/// `find_by_index` answers "every entry whose value has this property" without a scan. All
+/// indexes share the index key type `I`; an enum covers indexes over differently typed fields.
+/// Values are only reachable mutably through `update`, which re-indexes the entry afterwards.
+pub struct IndexedMap<K, V, I, S = hash::RandomState> {
+    map: ChainingHashMap<K, V, S>,
+    indexes: Vec<SecondaryIndex<K, V, I, S>>,
+}
+
+impl<K, V, I> IndexedMap<K, V, I, hash::RandomState> {
+    pub fn new() -> Self {
+        IndexedMap::with_hasher(hash::RandomState::new())
+    }
+}
+
+impl<K, V, I> Default for IndexedMap<K, V, I, hash::RandomState> {
+    fn default() -> Self {
+        IndexedMap::new()
+    }
+}
+
+impl<K, V, I, S> IndexedMap<K, V, I, S> {
+    /// Creates a map whose primary table and indexes all hash with clones of `hash_builder`
+    pub fn with_hasher(hash_builder: S) -> Self {
+        IndexedMap {
+            map: ChainingHashMap::with_hasher(hash_builder),
+            indexes: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.map.iter()
+    }
+
+    /// The names of the declared indexes, in the order they were added
+    pub fn index_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.indexes.iter().map(|index| index.name)
+    }
+
+    fn index_named(&self, name: &str) -> &SecondaryIndex<K, V, I, S> {
+        self.indexes
+            .iter()
+            .find(|index| index.name == name)
+            .unwrap_or_else(|| panic!("no index named {name:?}"))
+    }
+}
+
+impl<K, V, I, S> IndexedMap<K, V, I, S>
+where
+    K: Eq + hash::Hash + Clone,
+    I: Eq + hash::Hash,
+    S: hash::BuildHasher + Clone,
+{
+    /// Declares an index, filling it from the entries already stored
+    ///
+    /// Panics if an index with the same name already exists.
+    pub fn add_index<F>(&mut self, name: &'static str, extract: F)
+    where
+        F: Fn(&V) -> I + Send + Sync + 'static,
+    {
+        assert!(
+            self.indexes.iter().all(|index| index.name != name),
+            "an index named {name:?} already exists"
+        );
+
+        let mut index = SecondaryIndex {
+            name,
+            extract: Box::new(extract),
+            entries: ChainingHashMap::with_hasher(self.map.hasher().clone()),
+        };
+        for (key, value) in self.map.iter() {
+            index.add(key, value);
+        }
+        self.indexes.push(index);
+    }
+
+    /// Inserts an entry, moving it between index keys if it replaces an existing value
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let previous = self.map.insert(key.clone(), value);
+        if let Some(previous) = &previous {
+            for index in &mut self.indexes {
+                index.remove(&key, previous);
+            }
+        }
+
+        let value = self.map.get(&key).expect("key was just inserted");
+        for index in &mut self.indexes {
+            index.add(&key, value);
+        }
+
+        previous
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.map.get(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.map.get(key).is_some()
+    }
+
+    /// Runs `f` on the value for `key`, then re-indexes the entry; `None` if the key is missing
+    pub fn update<Q, F, R>(&mut self, key: &Q, f: F) -> Option<R>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+        F: FnOnce(&mut V) -> R,
+    {
+        let (key, value) = self.map.get_key_value(key)?;
+        for index in &mut self.indexes {
+            index.remove(key, value);
+        }
+        let key = key.clone();
+
+        // re-adds the entry on the way out, even if `f` panics
+        let reindex = Reindex {
+            map: &mut self.map,
+            indexes: &mut self.indexes,
+            key,
+        };
+        let value = reindex
+            .map
+            .get_mut(&reindex.key)
+            .expect("key was just found");
+        Some(f(value))
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let (key, value) = self.map.remove_entry(key)?;
+        for index in &mut self.indexes {
+            index.remove(&key, &value);
+        }
+
+        Some((key, value))
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.remove_entry(key).map(|entry| entry.1)
+    }
+
+    /// The entries whose value the index called `name` extracts `index_key` from, in no
+    /// particular order
+    ///
+    /// Panics if there is no index called `name`.
+    pub fn find_by_index<Q>(&self, name: &str, index_key: &Q) -> impl Iterator<Item = (&K, &V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<I>,
+    {
+        let keys = self
+            .index_named(name)
+            .entries
+            .get(index_key)
+            .map_or(&[][..], Vec::as_slice);
+
+        keys.iter().map(|key| {
+            let value = self.map.get(key).expect("indexed keys are in the map");
+            (key, value)
+        })
+    }
+
+    /// The number of entries the index called `name` files under `index_key`
+    ///
+    /// Panics if there is no index called `name`.
+    pub fn count_by_index<Q>(&self, name: &str, index_key: &Q) -> usize
+    where
+        Q: ?Sized + hash::Hash + Equivalent<I>,
+    {
+        self.index_named(name)
+            .entries
+            .get(index_key)
+            .map_or(0, Vec::len)
+    }
+}
+
+impl<K, V, I, S> SecondaryIndex<K, V, I, S>
+where
+    K: Eq + hash::Hash + Clone,
+    I: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    // every write removes an entry's old value from the index before adding the new one, so a
+    // key is never filed twice
+    fn add(&mut self, key: &K, value: &V) {
+        self.entries
+            .get_or_insert_with((self.extract)(value), Vec::new)
+            .push(key.clone());
+    }
+
+    fn remove(&mut self, key: &K, value: &V) {
+        let index_key = (self.extract)(value);
+        let Some(keys) = self.entries.get_mut(&index_key) else {
+            return;
+        };
+
+        if let Some(pos) = keys.iter().position(|indexed| indexed == key) {
+            keys.swap_remove(pos);
+        }
+        if keys.is_empty() {
+            self.entries.remove(&index_key);
+        }
+    }
+}
+
+// files an entry whose value `update` is changing back into every index when dropped
+struct Reindex<'a, K, V, I, S>
+where
+    K: Eq + hash::Hash + Clone,
+    I: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    map: &'a mut ChainingHashMap<K, V, S>,
+    indexes: &'a mut Vec<SecondaryIndex<K, V, I, S>>,
+    key: K,
+}
+
+impl<K, V, I, S> Drop for Reindex<'_, K, V, I, S>
+where
+    K: Eq + hash::Hash + Clone,
+    I: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    fn drop(&mut self) {
+        let value = self
+            .map
+            .get(&self.key)
+            .expect("updated keys stay in the map");
+        for index in self.indexes.iter_mut() {
+            index.add(&self.key, value);
+        }
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug, I, S> fmt::Debug for IndexedMap<K, V, I, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IndexedMap")
+            .field("entries", &self.map.iter().collect::<Vec<_>>())
+            .field("indexes", &self.index_names().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct User {
+        city: &'static str,
+        age: u32,
+    }
+
+    fn users() -> IndexedMap<u32, User, String> {
+        let mut map = IndexedMap::new();
+        map.add_index("city", |user: &User| user.city.to_string());
+
+        map.insert(
+            1,
+            User {
+                city: "Oslo",
+                age: 30,
+            },
+        );
+        map.insert(
+            2,
+            User {
+                city: "Lima",
+                age: 41,
+            },
+        );
+        map.insert(
+            3,
+            User {
+                city: "Oslo",
+                age: 25,
+            },
+        );
+        map
+    }
+
+    fn ids_by(map: &IndexedMap<u32, User, String>, name: &str, key: &str) -> Vec<u32> {
+        let mut ids = map
+            .find_by_index(name, key)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn indexes_follow_writes() {
+        let mut map = users();
+        assert_eq!(ids_by(&map, "city", "Oslo"), [1, 3]);
+        assert_eq!(ids_by(&map, "city", "Lima"), [2]);
+        assert_eq!(map.count_by_index("city", "Rome"), 0);
+
+        // replacing a value moves the entry between index keys
+        map.insert(
+            1,
+            User {
+                city: "Rome",
+                age: 30,
+            },
+        );
+        assert_eq!(ids_by(&map, "city", "Oslo"), [3]);
+        assert_eq!(ids_by(&map, "city", "Rome"), [1]);
+
+        // and so does updating one in place
+        assert_eq!(map.update(&2, |user| user.city = "Oslo"), Some(()));
+        assert_eq!(ids_by(&map, "city", "Oslo"), [2, 3]);
+        assert_eq!(map.count_by_index("city", "Lima"), 0);
+        assert_eq!(map.update(&9, |user| user.age += 1), None);
+
+        assert_eq!(map.remove(&3).map(|user| user.age), Some(25));
+        assert_eq!(ids_by(&map, "city", "Oslo"), [2]);
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn late_indexes_cover_existing_entries() {
+        let mut map = users();
+        map.add_index("decade", |user: &User| (user.age / 10 * 10).to_string());
+
+        assert_eq!(ids_by(&map, "decade", "20"), [3]);
+        assert_eq!(ids_by(&map, "decade", "30"), [1]);
+        assert_eq!(map.index_names().collect::<Vec<_>>(), ["city", "decade"]);
+
+        // an overwrite that keeps the index key keeps the entry filed under it, once
+        map.insert(
+            1,
+            User {
+                city: "Oslo",
+                age: 31,
+            },
+        );
+        assert_eq!(ids_by(&map, "decade", "30"), [1]);
+        assert_eq!(ids_by(&map, "city", "Oslo"), [1, 3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "no index named \"age\"")]
+    fn unknown_index() {
+        users().find_by_index("age", "30").for_each(drop);
+    }
+
+    #[test]
+    #[should_panic(expected = "already exists")]
+    fn duplicate_index() {
+        users().add_index("city", |user: &User| user.city.to_string());
+    }
+
+    #[test]
+    fn panicking_update_keeps_the_entry_indexed() {
+        let mut map = users();
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            map.update(&1, |user| {
+                user.city = "Rome";
+                panic!("update failed part way");
+            })
+        }));
+        assert!(result.is_err());
+
+        // filed under whatever the value was left as
+        assert_eq!(ids_by(&map, "city", "Rome"), [1]);
+        assert_eq!(ids_by(&map, "city", "Oslo"), [3]);
+    }
+
+    #[test]
+    fn send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let map = users();
+        assert_send_sync(&map);
+        let map = std::thread::spawn(move || map).join().unwrap();
+        assert_eq!(map.len(), 3);
+    }
+}
@@ -0,0 +1,238 @@
+//! Sharing one allocation between equal values.
+//!
+//! For maps where many keys map to identical large values: `dedup_values` collapses the equal
+//! values of a map of `Arc`s after the fact, and `InterningMap` keeps them collapsed as they are
+//! inserted.
+
+use crate::chaining_map::ChainingHashMap;
+use crate::equivalent::Equivalent;
+use std::collections::HashSet;
+use std::hash;
+use std::mem;
+use std::sync::Arc;
+
+/// What a `dedup_values` pass reclaimed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DedupReport {
+    /// Values whose allocation was released because an equal value took their place
+    pub freed: usize,
+    /// `freed` times `size_of::<V>()`; doesn't count memory the values own indirectly
+    pub bytes: usize,
+}
+
+impl<K, V, S, M> ChainingHashMap<K, Arc<V>, S, M>
+where
+    V: Eq + hash::Hash,
+{
+    /// Points every value at a single shared allocation per distinct value
+    ///
+    /// An allocation is only freed once nothing outside the map holds it either, so the report
+    /// counts what was actually released.
+    pub fn dedup_values(&mut self) -> DedupReport {
+        let mut pool = HashSet::<Arc<V>>::new();
+        let mut freed = 0;
+
+        for value in self.values_mut() {
+            match pool.get(&**value) {
+                Some(shared) if !Arc::ptr_eq(shared, value) => {
+                    let old = mem::replace(value, shared.clone());
+                    if Arc::into_inner(old).is_some() {
+                        freed += 1;
+                    }
+                }
+                Some(_) => {}
+                None => {
+                    pool.insert(value.clone());
+                }
+            }
+        }
+
+        DedupReport {
+            freed,
+            bytes: freed * mem::size_of::<V>(),
+        }
+    }
+}
+
+/// A map that stores each distinct value once.
+///
+/// Values are kept as `Arc<V>` and run through a side interner on insert, so every key mapped
+/// to an equal value shares one allocation. Values drop out of the interner once no key in the
+/// map refers to them, unless an `Arc` handed out by `get_shared` is still alive elsewhere at
+/// that point.
+///
+/// Doesn't implement `Map`: a value may be shared by many keys, so there is no `&mut V` to hand
+/// out for just one of them. Replace the value with `insert` instead.
+#[derive(Debug)]
+pub struct InterningMap<K, V, S = hash::RandomState> {
+    map: ChainingHashMap<K, Arc<V>, S>,
+    interner: HashSet<Arc<V>>,
+}
+
+impl<K, V> InterningMap<K, V, hash::RandomState> {
+    pub fn new() -> Self {
+        InterningMap::with_hasher(hash::RandomState::new())
+    }
+}
+
+impl<K, V> Default for InterningMap<K, V, hash::RandomState> {
+    fn default() -> Self {
+        InterningMap::new()
+    }
+}
+
+impl<K, V, S> InterningMap<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        InterningMap {
+            map: ChainingHashMap::with_hasher(hash_builder),
+            interner: HashSet::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// The number of distinct values currently stored
+    pub fn distinct_values(&self) -> usize {
+        self.interner.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.interner.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.map.iter().map(|(key, value)| (key, &**value))
+    }
+}
+
+impl<K, V, S> InterningMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    V: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    pub fn insert(&mut self, key: K, value: V) -> Option<Arc<V>> {
+        let shared = match self.interner.get(&value) {
+            Some(shared) => shared.clone(),
+            None => {
+                let shared = Arc::new(value);
+                self.interner.insert(shared.clone());
+                shared
+            }
+        };
+
+        let old = self.map.insert(key, shared);
+        old.inspect(|old| self.release(old))
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.map.get(key).map(|value| &**value)
+    }
+
+    /// Gets the shared allocation itself, e.g. to hold on to it past the map's lifetime
+    pub fn get_shared<Q>(&self, key: &Q) -> Option<&Arc<V>>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.map.get(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.map.get(key).is_some()
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<Arc<V>>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let old = self.map.remove(key);
+        old.inspect(|old| self.release(old))
+    }
+
+    // drops a value from the interner once the interner and `removed` are its only owners
+    fn release(&mut self, removed: &Arc<V>) {
+        let in_use = self
+            .interner
+            .get(&**removed)
+            .is_some_and(|shared| Arc::ptr_eq(shared, removed) && Arc::strong_count(shared) > 2);
+
+        if !in_use {
+            self.interner.remove(&**removed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dedup_values() {
+        let mut map = ChainingHashMap::new();
+
+        for i in 0..100 {
+            map.insert(i, Arc::new(vec![i % 3; 64]));
+        }
+
+        let report = map.dedup_values();
+
+        assert_eq!(report.freed, 97);
+        assert_eq!(report.bytes, 97 * mem::size_of::<Vec<i32>>());
+        assert_eq!(map.dedup_values(), DedupReport::default());
+        assert!(Arc::ptr_eq(map.get(&0).unwrap(), map.get(&99).unwrap()));
+    }
+
+    #[test]
+    fn dedup_values_held_elsewhere() {
+        let mut map = ChainingHashMap::new();
+        let held = [Arc::new("value".to_string()), Arc::new("value".to_string())];
+
+        map.insert("a", held[0].clone());
+        map.insert("b", held[1].clone());
+
+        // shared from now on, but both allocations are still alive
+        assert_eq!(map.dedup_values().freed, 0);
+        assert!(Arc::ptr_eq(map.get("a").unwrap(), map.get("b").unwrap()));
+    }
+
+    #[test]
+    fn interning() {
+        let mut map = InterningMap::new();
+
+        map.insert("a", "large".repeat(100));
+        map.insert("b", "large".repeat(100));
+        map.insert("c", "other".to_string());
+
+        assert_eq!(map.distinct_values(), 2);
+        assert!(Arc::ptr_eq(
+            map.get_shared(&"a").unwrap(),
+            map.get_shared(&"b").unwrap()
+        ));
+
+        // still used by "b"
+        map.remove(&"a");
+        assert_eq!(map.distinct_values(), 2);
+
+        map.insert("b", "other".to_string());
+        assert_eq!(map.distinct_values(), 1);
+        assert_eq!(map.get(&"b"), Some(&"other".to_string()));
+
+        map.remove(&"b");
+        map.remove(&"c");
+        assert_eq!(map.distinct_values(), 0);
+        assert!(map.is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+// adapters every map module with its own `Iter` and `IterMut` defines the same way
+
+// `Keys` and `Values` over the module's `Iter`, which must be `Clone`, exact-size and fused; a
+// trailing `where` clause carries over to the adapters, for an `Iter` that is only an iterator
+// under some bounds
+macro_rules! keys_and_values {
+    ($map:ident, Iter<K, V $(, $param:ident $(= $default:ty)?)*> $(where $($bound:tt)+)?) => {
+        #[doc = concat!("An iterator over the keys, from `", stringify!($map), "::keys`.")]
+        #[derive(Debug)]
+        pub struct Keys<'a, K, V $(, $param $(= $default)?)*> $(where $($bound)+)? {
+            inner: Iter<'a, K, V $(, $param)*>,
+        }
+
+        #[doc = concat!("An iterator over the values, from `", stringify!($map), "::values`.")]
+        #[derive(Debug)]
+        pub struct Values<'a, K, V $(, $param $(= $default)?)*> $(where $($bound)+)? {
+            inner: Iter<'a, K, V $(, $param)*>,
+        }
+
+        impl<'a, K, V $(, $param)*> Iterator for Keys<'a, K, V $(, $param)*> $(where $($bound)+)? {
+            type Item = &'a K;
+
+            fn next(&mut self) -> Option<&'a K> {
+                self.inner.next().map(|(key, _)| key)
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.inner.size_hint()
+            }
+        }
+
+        impl<'a, K, V $(, $param)*> Iterator for Values<'a, K, V $(, $param)*> $(where $($bound)+)? {
+            type Item = &'a V;
+
+            fn next(&mut self) -> Option<&'a V> {
+                self.inner.next().map(|(_, value)| value)
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.inner.size_hint()
+            }
+        }
+
+        impl<K, V $(, $param)*> ExactSizeIterator for Keys<'_, K, V $(, $param)*> $(where $($bound)+)? {}
+
+        impl<K, V $(, $param)*> ExactSizeIterator for Values<'_, K, V $(, $param)*> $(where $($bound)+)? {}
+
+        impl<K, V $(, $param)*> std::iter::FusedIterator for Keys<'_, K, V $(, $param)*> $(where $($bound)+)? {}
+
+        impl<K, V $(, $param)*> std::iter::FusedIterator for Values<'_, K, V $(, $param)*> $(where $($bound)+)? {}
+
+        impl<K, V $(, $param)*> Clone for Keys<'_, K, V $(, $param)*> $(where $($bound)+)? {
+            fn clone(&self) -> Self {
+                Keys {
+                    inner: self.inner.clone(),
+                }
+            }
+        }
+
+        impl<K, V $(, $param)*> Clone for Values<'_, K, V $(, $param)*> $(where $($bound)+)? {
+            fn clone(&self) -> Self {
+                Values {
+                    inner: self.inner.clone(),
+                }
+            }
+        }
+    };
+}
+
+// `IntoIterator` for shared and mutable references to the map, through its `iter` and `iter_mut`
+macro_rules! borrowed_into_iterator {
+    ($map:ident<K, V $(, $param:ident)*>, Iter<K, V $(, $iter_param:ident)*>) => {
+        impl<'a, K, V $(, $param)*> IntoIterator for &'a $map<K, V $(, $param)*> {
+            type Item = (&'a K, &'a V);
+            type IntoIter = Iter<'a, K, V $(, $iter_param)*>;
+
+            fn into_iter(self) -> Iter<'a, K, V $(, $iter_param)*> {
+                self.iter()
+            }
+        }
+
+        impl<'a, K, V $(, $param)*> IntoIterator for &'a mut $map<K, V $(, $param)*> {
+            type Item = (&'a K, &'a mut V);
+            type IntoIter = IterMut<'a, K, V $(, $iter_param)*>;
+
+            fn into_iter(self) -> IterMut<'a, K, V $(, $iter_param)*> {
+                self.iter_mut()
+            }
+        }
+    };
+}
+
+pub(crate) use borrowed_into_iterator;
+pub(crate) use keys_and_values;
@@ -0,0 +1,111 @@
+//! Conversions between maps and JSON objects, behind the `json` feature.
+
+use crate::chaining_map::ChainingHashMap;
+use crate::deserialize::DuplicateKeys;
+use serde_json::{Map, Value};
+use std::hash;
+
+impl<S> ChainingHashMap<String, Value, S>
+where
+    S: hash::BuildHasher + Default,
+{
+    /// Parses a JSON object into a map of its top-level members
+    ///
+    /// Fails if the text isn't valid JSON or isn't an object. Nested objects stay as `Value`s,
+    /// and a repeated member keeps its last value.
+    pub fn from_json_str(json: &str) -> serde_json::Result<Self> {
+        ChainingHashMap::from_json_str_with(json, DuplicateKeys::LastWins)
+    }
+
+    /// Like `from_json_str`, with a choice of what to do about repeated top-level members
+    pub fn from_json_str_with(json: &str, duplicates: DuplicateKeys) -> serde_json::Result<Self> {
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        let map = duplicates.deserialize(&mut deserializer)?;
+        deserializer.end()?;
+
+        Ok(map)
+    }
+}
+
+impl<S> From<Map<String, Value>> for ChainingHashMap<String, Value, S>
+where
+    S: hash::BuildHasher + Default,
+{
+    fn from(object: Map<String, Value>) -> Self {
+        let mut map = ChainingHashMap::with_capacity_and_hasher(object.len(), S::default());
+
+        for (key, value) in object {
+            map.insert(key, value);
+        }
+
+        map
+    }
+}
+
+impl<S, M> From<ChainingHashMap<String, Value, S, M>> for Map<String, Value> {
+    fn from(map: ChainingHashMap<String, Value, S, M>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl<S, M> From<ChainingHashMap<String, Value, S, M>> for Value {
+    fn from(map: ChainingHashMap<String, Value, S, M>) -> Self {
+        Value::Object(map.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn from_json_str() {
+        let mut map: ChainingHashMap<String, Value> =
+            ChainingHashMap::from_json_str(r#"{"name": "salt", "tags": {"a": 1}}"#).unwrap();
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get("name"), Some(&json!("salt")));
+        assert_eq!(map.get("tags"), Some(&json!({"a": 1})));
+
+        map.insert("count".to_string(), json!(3));
+        assert_eq!(
+            Value::from(map),
+            json!({"name": "salt", "tags": {"a": 1}, "count": 3})
+        );
+    }
+
+    #[test]
+    fn rejects_non_objects() {
+        assert!(ChainingHashMap::<String, Value>::from_json_str("[1, 2]").is_err());
+        assert!(ChainingHashMap::<String, Value>::from_json_str("{").is_err());
+        assert!(ChainingHashMap::<String, Value>::from_json_str("{} {}").is_err());
+    }
+
+    #[test]
+    fn duplicate_members() {
+        let json = r#"{"a": 1, "a": 2}"#;
+
+        let map = ChainingHashMap::<String, Value>::from_json_str(json).unwrap();
+        assert_eq!(map.get("a"), Some(&json!(2)));
+
+        let map: ChainingHashMap<String, Value> =
+            ChainingHashMap::from_json_str_with(json, DuplicateKeys::FirstWins).unwrap();
+        assert_eq!(map.get("a"), Some(&json!(1)));
+
+        assert!(
+            ChainingHashMap::<String, Value>::from_json_str_with(json, DuplicateKeys::Error)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn round_trips_through_map() {
+        let Value::Object(object) = json!({"x": null, "y": [true]}) else {
+            unreachable!()
+        };
+
+        let map: ChainingHashMap<String, Value> = object.clone().into();
+        assert_eq!(Map::from(map), object);
+    }
+}
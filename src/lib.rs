@@ -1 +1,44 @@
+pub mod bounded_concurrent_map;
+pub mod bounded_map;
+pub mod bytes_map;
+pub mod canonical_map;
 pub mod chaining_map;
+pub mod checked_map;
+pub mod compat;
+pub mod concurrent_map;
+pub mod delay_queue_map;
+pub mod delta_map;
+pub mod dense_map;
+#[cfg(feature = "serde")]
+pub mod deserialize;
+pub mod equivalent;
+pub mod error;
+pub mod extendible_map;
+pub mod float_key;
+pub mod hashers;
+pub mod indexed_map;
+pub mod interning;
+mod iter;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod linear_map;
+pub mod map;
+pub mod nested_map;
+pub mod partitioned_map;
+pub mod path;
+pub mod priority_map;
+pub mod seeded;
+#[cfg(feature = "serde")]
+pub mod serialize;
+pub mod shared_map;
+pub mod soa_map;
+pub mod sorted_chain_map;
+pub mod static_map;
+pub mod str_keys;
+#[cfg(test)]
+mod testkit;
+pub mod two_choice_map;
+
+pub use equivalent::{CompositeKey, Equivalent};
+pub use error::Error;
+pub use map::Map;
@@ -0,0 +1,426 @@
+use crate::equivalent::Equivalent;
+use std::hash;
+use std::mem;
+
+// buckets at the start of the first round; must be a power of two
+const INITIAL_BUCKETS: usize = 8;
+
+// average entries per bucket above which the next bucket is split
+const MAX_LOAD: usize = 2;
+
+/// A map using linear hashing.
+///
+/// Instead of rehashing everything when the load gets too high, the table splits one bucket at
+/// a time, in order, on the insert that crosses the load threshold. A round ends when every
+/// bucket of the round has been split, which doubles the number of buckets over the course of
+/// the round. Each insert moves at most one bucket's worth of entries, so there is never a pause
+/// for a full rehash; the bucket array itself still grows by doubling, but that only moves
+/// the chain headers. Buckets are never merged back on removal.
+#[derive(Debug)]
+pub struct LinearHashMap<K, V, S = hash::RandomState> {
+    // each entry keeps its full hash, so splits never rehash
+    buckets: Vec<Vec<(u64, K, V)>>,
+    // buckets at the start of the current round, always a power of two
+    round: usize,
+    // the next bucket to split; buckets before it are already addressed with one more bit
+    next_split: usize,
+    load: usize,
+    hash_builder: S,
+}
+
+impl<K, V> LinearHashMap<K, V, hash::RandomState> {
+    pub fn new() -> Self {
+        LinearHashMap::with_hasher(hash::RandomState::new())
+    }
+}
+
+impl<K, V> Default for LinearHashMap<K, V, hash::RandomState> {
+    fn default() -> Self {
+        LinearHashMap::new()
+    }
+}
+
+impl<K, V, S> LinearHashMap<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        LinearHashMap {
+            buckets: (0..INITIAL_BUCKETS).map(|_| Vec::new()).collect(),
+            round: INITIAL_BUCKETS,
+            next_split: 0,
+            load: 0,
+            hash_builder,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.load
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.load == 0
+    }
+
+    /// Removes every entry, shrinking back to the initial buckets
+    pub fn clear(&mut self) {
+        self.buckets = (0..INITIAL_BUCKETS).map(|_| Vec::new()).collect();
+        self.round = INITIAL_BUCKETS;
+        self.next_split = 0;
+        self.load = 0;
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    pub fn bucket_count(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Iterates over the entries, in no particular order
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            buckets: self.buckets.iter(),
+            chain: [].iter(),
+            remaining: self.load,
+        }
+    }
+
+    /// Iterates over the entries with mutable references to the values, in the same order as
+    /// `iter`
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            buckets: self.buckets.iter_mut(),
+            chain: [].iter_mut(),
+            remaining: self.load,
+        }
+    }
+
+    /// Iterates over the keys, in the same order as `iter`
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.iter() }
+    }
+
+    /// Iterates over the values, in the same order as `iter`
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { inner: self.iter() }
+    }
+
+    fn bucket_for(&self, hash: u64) -> usize {
+        let idx = hash as usize & (self.round - 1);
+
+        if idx < self.next_split {
+            // already split this round, so it's addressed with the next bit too
+            hash as usize & (self.round * 2 - 1)
+        } else {
+            idx
+        }
+    }
+
+    fn split_next(&mut self) {
+        let split = self.next_split;
+        let high_bit = self.round;
+
+        self.buckets.push(Vec::new());
+        let (stay, moved) = mem::take(&mut self.buckets[split])
+            .into_iter()
+            .partition(|item| item.0 as usize & high_bit == 0);
+        self.buckets[split] = stay;
+        self.buckets[split + high_bit] = moved;
+
+        self.next_split += 1;
+        if self.next_split == self.round {
+            self.round *= 2;
+            self.next_split = 0;
+        }
+    }
+}
+
+impl<K, V, S> LinearHashMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let result = self.insert_inner(key, value);
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        result
+    }
+
+    fn insert_inner(&mut self, key: K, value: V) -> Option<V> {
+        let hash = self.hash_builder.hash_one(&key);
+        let bucket = self.bucket_for(hash);
+
+        if let Some(item) = self.buckets[bucket]
+            .iter_mut()
+            .find(|item| item.0 == hash && key == item.1)
+        {
+            return Some(mem::replace(&mut item.2, value));
+        }
+
+        self.buckets[bucket].push((hash, key, value));
+        self.load += 1;
+
+        if self.load > self.buckets.len() * MAX_LOAD {
+            self.split_next();
+        }
+
+        None
+    }
+
+    fn find<Q>(&self, key: &Q) -> Option<&(u64, K, V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let hash = self.hash_builder.hash_one(key);
+        self.buckets[self.bucket_for(hash)]
+            .iter()
+            .find(|item| item.0 == hash && key.equivalent(&item.1))
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.find(key).map(|item| &item.2)
+    }
+
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.find(key).map(|item| (&item.1, &item.2))
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let hash = self.hash_builder.hash_one(key);
+        let bucket = self.bucket_for(hash);
+        self.buckets[bucket]
+            .iter_mut()
+            .find(|item| item.0 == hash && key.equivalent(&item.1))
+            .map(|item| &mut item.2)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let hash = self.hash_builder.hash_one(key);
+        let bucket = self.bucket_for(hash);
+
+        let idx = self.buckets[bucket]
+            .iter()
+            .position(|item| item.0 == hash && key.equivalent(&item.1))?;
+        let (_, key, value) = self.buckets[bucket].swap_remove(idx);
+        self.load -= 1;
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        Some((key, value))
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.remove_entry(key).map(|entry| entry.1)
+    }
+
+    /// Panics if the bucket count has drifted from the round and split pointer, an entry is
+    /// misplaced, a cached hash is stale, a key repeats, or `len()` is off
+    #[cfg(any(test, feature = "validate"))]
+    pub fn debug_validate(&self) {
+        assert!(
+            self.round.is_power_of_two(),
+            "round size is not a power of two"
+        );
+        assert_eq!(
+            self.buckets.len(),
+            self.round + self.next_split,
+            "bucket count does not match the split pointer"
+        );
+
+        let mut seen = std::collections::HashSet::with_capacity(self.load);
+
+        for (idx, bucket) in self.buckets.iter().enumerate() {
+            for item in bucket.iter() {
+                assert_eq!(
+                    item.0,
+                    self.hash_builder.hash_one(&item.1),
+                    "cached hash is stale"
+                );
+                assert_eq!(
+                    self.bucket_for(item.0),
+                    idx,
+                    "entry is stored in the wrong bucket"
+                );
+                assert!(seen.insert(&item.1), "key is stored more than once");
+            }
+        }
+
+        assert_eq!(
+            seen.len(),
+            self.load,
+            "load does not match the number of stored entries"
+        );
+    }
+}
+
+/// An iterator over a `LinearHashMap`'s entries, from `LinearHashMap::iter`.
+///
+/// Walks the buckets in order, each one's chain in turn.
+#[derive(Debug)]
+pub struct Iter<'a, K, V> {
+    buckets: std::slice::Iter<'a, Vec<(u64, K, V)>>,
+    chain: std::slice::Iter<'a, (u64, K, V)>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            if let Some(item) = self.chain.next() {
+                self.remaining -= 1;
+                return Some((&item.1, &item.2));
+            }
+            self.chain = self.buckets.next()?.iter();
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> std::iter::FusedIterator for Iter<'_, K, V> {}
+
+impl<K, V> Clone for Iter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Iter {
+            buckets: self.buckets.clone(),
+            chain: self.chain.clone(),
+            remaining: self.remaining,
+        }
+    }
+}
+
+/// A mutable iterator over a `LinearHashMap`'s entries, from `LinearHashMap::iter_mut`.
+#[derive(Debug)]
+pub struct IterMut<'a, K, V> {
+    buckets: std::slice::IterMut<'a, Vec<(u64, K, V)>>,
+    chain: std::slice::IterMut<'a, (u64, K, V)>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        loop {
+            if let Some(item) = self.chain.next() {
+                self.remaining -= 1;
+                return Some((&item.1, &mut item.2));
+            }
+            self.chain = self.buckets.next()?.iter_mut();
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+
+impl<K, V> std::iter::FusedIterator for IterMut<'_, K, V> {}
+
+crate::iter::keys_and_values!(LinearHashMap, Iter<K, V>);
+crate::iter::borrowed_into_iterator!(LinearHashMap<K, V, S>, Iter<K, V>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashers::ConstantState;
+
+    crate::testkit::map_conformance_tests!(LinearHashMap::<usize, usize>::new());
+
+    #[test]
+    fn iterators() {
+        let mut map = LinearHashMap::new();
+        for i in 0..100 {
+            map.insert(i, i);
+        }
+
+        let iter = map.iter();
+        assert_eq!(iter.len(), 100);
+        assert_eq!(iter.clone().count(), 100);
+        assert_eq!(map.keys().sum::<i32>(), 4950);
+
+        for (key, value) in &mut map {
+            *value += key;
+        }
+        map.iter_mut().take(10).for_each(|(_, value)| *value = -1);
+        assert_eq!(map.iter_mut().len(), 100);
+        assert_eq!(map.values().filter(|value| **value == -1).count(), 10);
+        assert!((&map)
+            .into_iter()
+            .all(|(key, value)| *value == -1 || *value == key * 2));
+    }
+
+    #[test]
+    fn splits_one_bucket_at_a_time() {
+        let mut map = LinearHashMap::new();
+        let mut buckets = map.bucket_count();
+
+        for i in 0..1000 {
+            map.insert(i, i);
+
+            let now = map.bucket_count();
+            assert!(now == buckets || now == buckets + 1);
+            buckets = now;
+        }
+
+        assert!(map.len() <= map.bucket_count() * MAX_LOAD);
+        for i in 0..1000 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn total_collision() {
+        let mut map = LinearHashMap::with_hasher(ConstantState::default());
+
+        for i in 0..200 {
+            map.insert(i, i);
+        }
+        for i in (0..200).step_by(2) {
+            assert_eq!(map.remove(&i), Some(i));
+        }
+
+        assert_eq!(map.len(), 100);
+        assert_eq!(map.get(&99), Some(&99));
+    }
+
+    #[test]
+    fn clear() {
+        let mut map = LinearHashMap::new();
+
+        for i in 0..100 {
+            map.insert(i, i);
+        }
+        map.clear();
+
+        assert!(map.is_empty());
+        assert_eq!(map.bucket_count(), INITIAL_BUCKETS);
+        assert_eq!(map.get(&1), None);
+    }
+}
@@ -0,0 +1,527 @@
+use crate::bytes_map::BytesMap;
+use crate::canonical_map::CanonicalMap;
+use crate::chaining_map::{Bucket, ChainingHashMap};
+use crate::compat;
+use crate::delta_map::DeltaMap;
+use crate::dense_map::{DenseMap, EntryIndex};
+use crate::extendible_map::ExtendibleHashMap;
+use crate::linear_map::LinearHashMap;
+use crate::partitioned_map::PartitionedMap;
+use crate::soa_map::SoaMap;
+use crate::sorted_chain_map::SortedChainMap;
+use crate::two_choice_map::TwoChoiceMap;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash;
+
+/// The operations every map in this crate supports.
+///
+/// Lets benchmarks, tests, and downstream code be written once and run against any
+/// implementation. Also implemented for `std::collections::HashMap`, which makes it easy to
+/// compare against the standard library.
+pub trait Map<K, V> {
+    fn insert(&mut self, key: K, value: V) -> Option<V>;
+
+    fn get(&self, key: &K) -> Option<&V>;
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V>;
+
+    fn remove(&mut self, key: &K) -> Option<V>;
+
+    fn len(&self) -> usize;
+
+    /// Iterates over every key/value pair, in no particular order
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a K, &'a V)> + 'a>;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+}
+
+impl<K, V, S, B> Map<K, V> for ChainingHashMap<K, V, S, (), B>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+    B: Bucket<(K, V, ())>,
+{
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        ChainingHashMap::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        ChainingHashMap::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        ChainingHashMap::get_mut(self, key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        ChainingHashMap::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        ChainingHashMap::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a K, &'a V)> + 'a> {
+        Box::new(ChainingHashMap::iter(self))
+    }
+}
+
+impl<K, V, S> Map<K, V> for ExtendibleHashMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        ExtendibleHashMap::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        ExtendibleHashMap::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        ExtendibleHashMap::get_mut(self, key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        ExtendibleHashMap::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        ExtendibleHashMap::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a K, &'a V)> + 'a> {
+        Box::new(ExtendibleHashMap::iter(self))
+    }
+}
+
+impl<K, V, S> Map<K, V> for LinearHashMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        LinearHashMap::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        LinearHashMap::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        LinearHashMap::get_mut(self, key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        LinearHashMap::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        LinearHashMap::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a K, &'a V)> + 'a> {
+        Box::new(LinearHashMap::iter(self))
+    }
+}
+
+impl<K, V, S> Map<K, V> for TwoChoiceMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        TwoChoiceMap::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        TwoChoiceMap::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        TwoChoiceMap::get_mut(self, key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        TwoChoiceMap::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        TwoChoiceMap::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a K, &'a V)> + 'a> {
+        Box::new(TwoChoiceMap::iter(self))
+    }
+}
+
+impl<K, V, S> Map<K, V> for SortedChainMap<K, V, S>
+where
+    K: Ord + hash::Hash,
+    S: hash::BuildHasher,
+{
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        SortedChainMap::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        SortedChainMap::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        SortedChainMap::get_mut(self, key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        SortedChainMap::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        SortedChainMap::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a K, &'a V)> + 'a> {
+        Box::new(SortedChainMap::iter(self))
+    }
+}
+
+impl<K, V, S, I> Map<K, V> for DenseMap<K, V, S, I>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+    I: EntryIndex,
+{
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        DenseMap::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        DenseMap::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        DenseMap::get_mut(self, key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        DenseMap::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        DenseMap::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a K, &'a V)> + 'a> {
+        Box::new(DenseMap::iter(self))
+    }
+}
+
+impl<K, V, S> Map<K, V> for PartitionedMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher + Clone,
+{
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        PartitionedMap::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        PartitionedMap::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        PartitionedMap::get_mut(self, key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        PartitionedMap::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        PartitionedMap::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a K, &'a V)> + 'a> {
+        Box::new(PartitionedMap::iter(self))
+    }
+}
+
+impl<K, V, S> Map<K, V> for DeltaMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        DeltaMap::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        DeltaMap::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        DeltaMap::get_mut(self, key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        DeltaMap::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        DeltaMap::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a K, &'a V)> + 'a> {
+        Box::new(DeltaMap::iter(self))
+    }
+}
+
+impl<K, V, S> Map<K, V> for compat::HashMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        compat::HashMap::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        compat::HashMap::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        compat::HashMap::get_mut(self, key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        compat::HashMap::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        compat::HashMap::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a K, &'a V)> + 'a> {
+        Box::new(compat::HashMap::iter(self))
+    }
+}
+
+impl<K, V, S> Map<K, V> for SoaMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        SoaMap::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        SoaMap::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        SoaMap::get_mut(self, key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        SoaMap::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        SoaMap::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a K, &'a V)> + 'a> {
+        Box::new(SoaMap::iter(self))
+    }
+}
+
+// keys given by value are normalized like any other, so two keys that canonicalize alike are
+// the same key through this trait too
+impl<Q, V, S> Map<Q::Owned, V> for CanonicalMap<Q, V, S>
+where
+    Q: ?Sized + ToOwned,
+    Q::Owned: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    fn insert(&mut self, key: Q::Owned, value: V) -> Option<V> {
+        CanonicalMap::insert(self, key.borrow(), value)
+    }
+
+    fn get(&self, key: &Q::Owned) -> Option<&V> {
+        CanonicalMap::get(self, key.borrow())
+    }
+
+    fn get_mut(&mut self, key: &Q::Owned) -> Option<&mut V> {
+        CanonicalMap::get_mut(self, key.borrow())
+    }
+
+    fn remove(&mut self, key: &Q::Owned) -> Option<V> {
+        CanonicalMap::remove(self, key.borrow())
+    }
+
+    fn len(&self) -> usize {
+        CanonicalMap::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a Q::Owned, &'a V)> + 'a> {
+        Box::new(CanonicalMap::iter(self))
+    }
+}
+
+impl<V, S> Map<Box<[u8]>, V> for BytesMap<V, S>
+where
+    S: hash::BuildHasher,
+{
+    fn insert(&mut self, key: Box<[u8]>, value: V) -> Option<V> {
+        BytesMap::insert_owned(self, key.into_vec(), value)
+    }
+
+    fn get(&self, key: &Box<[u8]>) -> Option<&V> {
+        BytesMap::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &Box<[u8]>) -> Option<&mut V> {
+        BytesMap::get_mut(self, key)
+    }
+
+    fn remove(&mut self, key: &Box<[u8]>) -> Option<V> {
+        BytesMap::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        BytesMap::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a Box<[u8]>, &'a V)> + 'a> {
+        Box::new(BytesMap::entries(self))
+    }
+}
+
+impl<K, V, S> Map<K, V> for HashMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        HashMap::insert(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        HashMap::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        HashMap::get_mut(self, key)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        HashMap::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (&'a K, &'a V)> + 'a> {
+        Box::new(HashMap::iter(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // keeps the suite itself honest
+    crate::testkit::map_conformance_tests!(HashMap::<usize, usize>::new());
+
+    fn count_words<M: Map<String, usize>>(map: &mut M, text: &str) {
+        for word in text.split_whitespace() {
+            match map.get_mut(&word.to_string()) {
+                Some(count) => *count += 1,
+                None => {
+                    map.insert(word.to_string(), 1);
+                }
+            }
+        }
+    }
+
+    fn check_word_counts<M: Map<String, usize>>(mut map: M) {
+        count_words(
+            &mut map,
+            "the quick brown fox jumps over the lazy dog the end",
+        );
+
+        assert_eq!(map.len(), 9);
+        assert_eq!(map.get(&"the".to_string()), Some(&3));
+        assert!(map.contains_key(&"fox".to_string()));
+        assert_eq!(map.iter().map(|(_, count)| count).sum::<usize>(), 11);
+
+        assert_eq!(map.remove(&"the".to_string()), Some(3));
+        assert!(!map.contains_key(&"the".to_string()));
+        assert!(!map.is_empty());
+    }
+
+    #[test]
+    fn chaining_map() {
+        check_word_counts(ChainingHashMap::new());
+    }
+
+    #[test]
+    fn extendible_map() {
+        check_word_counts(ExtendibleHashMap::new());
+    }
+
+    #[test]
+    fn linear_map() {
+        check_word_counts(LinearHashMap::new());
+    }
+
+    #[test]
+    fn two_choice_map() {
+        check_word_counts(TwoChoiceMap::new());
+    }
+
+    #[test]
+    fn sorted_chain_map() {
+        check_word_counts(SortedChainMap::new());
+    }
+
+    #[test]
+    fn dense_map() {
+        check_word_counts(DenseMap::new());
+    }
+
+    #[test]
+    fn partitioned_map() {
+        check_word_counts(PartitionedMap::new());
+    }
+
+    #[test]
+    fn delta_map() {
+        check_word_counts(DeltaMap::new());
+    }
+
+    #[test]
+    fn compat_map() {
+        check_word_counts(compat::HashMap::new());
+    }
+
+    #[test]
+    fn soa_map() {
+        check_word_counts(SoaMap::new());
+    }
+
+    #[test]
+    fn std_map() {
+        check_word_counts(HashMap::new());
+    }
+}
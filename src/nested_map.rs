@@ -0,0 +1,214 @@
+use crate::chaining_map::ChainingHashMap;
+use std::hash;
+
+/// A two-level map, keyed by an outer key and then an inner key.
+///
+/// Wraps a `ChainingHashMap<K1, ChainingHashMap<K2, V>>` and keeps it tidy: inner maps are
+/// created on demand and removed as soon as they become empty.
+#[derive(Debug)]
+pub struct NestedMap<K1, K2, V> {
+    outer: ChainingHashMap<K1, ChainingHashMap<K2, V>>,
+    load: usize,
+}
+
+impl<K1, K2, V> NestedMap<K1, K2, V> {
+    pub fn new() -> Self {
+        NestedMap {
+            outer: ChainingHashMap::new(),
+            load: 0,
+        }
+    }
+
+    /// Number of `(k1, k2)` pairs stored, across all inner maps
+    pub fn len(&self) -> usize {
+        self.load
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.load == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.load = 0;
+        self.outer.clear();
+    }
+
+    /// Iterates over every `(k1, k2, value)` triple in the map
+    pub fn iter(&self) -> impl Iterator<Item = (&K1, &K2, &V)> {
+        self.outer
+            .iter()
+            .flat_map(|(k1, inner)| inner.iter().map(move |(k2, value)| (k1, k2, value)))
+    }
+}
+
+impl<K1, K2, V> Default for NestedMap<K1, K2, V> {
+    fn default() -> Self {
+        NestedMap::new()
+    }
+}
+
+impl<K1, K2, V> NestedMap<K1, K2, V>
+where
+    K1: Eq + hash::Hash,
+    K2: Eq + hash::Hash,
+{
+    pub fn insert(&mut self, key: (K1, K2), value: V) -> Option<V> {
+        let (k1, k2) = key;
+
+        if let Some(inner) = self.outer.get_mut(&k1) {
+            let result = inner.insert(k2, value);
+            if result.is_none() {
+                self.load += 1;
+            }
+            return result;
+        }
+
+        let mut inner = ChainingHashMap::new();
+        inner.insert(k2, value);
+        self.outer.insert(k1, inner);
+        self.load += 1;
+
+        None
+    }
+
+    pub fn get(&self, k1: &K1, k2: &K2) -> Option<&V> {
+        self.outer.get(k1)?.get(k2)
+    }
+
+    pub fn get_mut(&mut self, k1: &K1, k2: &K2) -> Option<&mut V> {
+        self.outer.get_mut(k1)?.get_mut(k2)
+    }
+
+    pub fn contains_key(&self, k1: &K1, k2: &K2) -> bool {
+        self.get(k1, k2).is_some()
+    }
+
+    /// Gets the whole inner map stored under the outer key
+    pub fn get_inner(&self, k1: &K1) -> Option<&ChainingHashMap<K2, V>> {
+        self.outer.get(k1)
+    }
+
+    /// Removes the value stored under `(k1, k2)`, dropping the inner map if it is left empty
+    pub fn remove(&mut self, k1: &K1, k2: &K2) -> Option<V> {
+        let inner = self.outer.get_mut(k1)?;
+        let result = inner.remove(k2)?;
+        self.load -= 1;
+
+        if inner.is_empty() {
+            self.outer.remove(k1);
+        }
+
+        Some(result)
+    }
+
+    /// Removes every value stored under the outer key, returning them as a map
+    pub fn remove_inner(&mut self, k1: &K1) -> Option<ChainingHashMap<K2, V>> {
+        let inner = self.outer.remove(k1)?;
+        self.load -= inner.len();
+        Some(inner)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a, K1, K2, V> arbitrary::Arbitrary<'a> for NestedMap<K1, K2, V>
+where
+    K1: arbitrary::Arbitrary<'a> + Eq + hash::Hash,
+    K2: arbitrary::Arbitrary<'a> + Eq + hash::Hash,
+    V: arbitrary::Arbitrary<'a>,
+{
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut map = NestedMap::new();
+
+        for entry in u.arbitrary_iter::<((K1, K2), V)>()? {
+            let (key, value) = entry?;
+            map.insert(key, value);
+        }
+
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_and_get() {
+        let mut map = NestedMap::new();
+
+        assert_eq!(map.insert(("a".to_string(), 1), 10), None);
+        assert_eq!(map.insert(("a".to_string(), 2), 20), None);
+        assert_eq!(map.insert(("b".to_string(), 1), 30), None);
+        assert_eq!(map.insert(("a".to_string(), 1), 11), Some(10));
+
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.get(&"a".to_string(), &1), Some(&11));
+        assert_eq!(map.get(&"a".to_string(), &2), Some(&20));
+        assert_eq!(map.get(&"b".to_string(), &1), Some(&30));
+        assert_eq!(map.get(&"b".to_string(), &2), None);
+        assert_eq!(map.get(&"c".to_string(), &1), None);
+    }
+
+    #[test]
+    fn get_mut() {
+        let mut map = NestedMap::new();
+
+        map.insert((1, 2), 3);
+
+        if let Some(value) = map.get_mut(&1, &2) {
+            *value += 1;
+        }
+
+        assert_eq!(map.get(&1, &2), Some(&4));
+    }
+
+    #[test]
+    fn remove_drops_empty_inner_maps() {
+        let mut map = NestedMap::new();
+
+        map.insert((1, 1), "one-one");
+        map.insert((1, 2), "one-two");
+
+        assert_eq!(map.remove(&1, &3), None);
+        assert_eq!(map.remove(&2, &1), None);
+
+        assert_eq!(map.remove(&1, &1), Some("one-one"));
+        assert!(map.get_inner(&1).is_some());
+
+        assert_eq!(map.remove(&1, &2), Some("one-two"));
+        assert!(map.get_inner(&1).is_none());
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn remove_inner() {
+        let mut map = NestedMap::new();
+
+        for i in 0..10 {
+            map.insert((i % 2, i), i);
+        }
+
+        let evens = map.remove_inner(&0).unwrap();
+
+        assert_eq!(evens.len(), 5);
+        assert_eq!(map.len(), 5);
+        assert!(map.remove_inner(&0).is_none());
+    }
+
+    #[test]
+    fn iter() {
+        let mut map = NestedMap::new();
+
+        for i in 0..20 {
+            map.insert((i % 3, i), i * 10);
+        }
+
+        let mut triples = map.iter().map(|(a, b, c)| (*a, *b, *c)).collect::<Vec<_>>();
+        triples.sort();
+
+        let mut expected = (0..20).map(|i| (i % 3, i, i * 10)).collect::<Vec<_>>();
+        expected.sort();
+
+        assert_eq!(triples, expected);
+    }
+}
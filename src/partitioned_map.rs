@@ -0,0 +1,499 @@
+use crate::chaining_map::{self, ChainingHashMap};
+use crate::equivalent::Equivalent;
+use std::hash;
+use std::mem;
+
+// entries a partition may hold before it splits, unless configured otherwise
+const DEFAULT_THRESHOLD: usize = 1 << 16;
+
+// partitions whose entries agree on this many top hash bits grow past the threshold instead of
+// splitting, which keeps the directory bounded under heavy collisions
+const MAX_DEPTH: u32 = 16;
+
+#[derive(Debug)]
+struct Partition<K, V, S> {
+    depth: u32,
+    map: ChainingHashMap<K, V, S>,
+}
+
+/// A map that spreads its entries over several `ChainingHashMap`s once it gets large.
+///
+/// Starts out as a single map. A partition that passes the size threshold splits in two on the
+/// next top bit of the hash, so no table ever holds much more than the threshold, and the pause
+/// for resizing or splitting one is bounded by the threshold rather than by the size of the
+/// whole map. A directory indexed by the top bits of the hash finds a key's partition, which
+/// costs a second hash per operation. Partitions are never merged back on removal.
+#[derive(Debug)]
+pub struct PartitionedMap<K, V, S = hash::RandomState> {
+    // slot `i` holds the partition for hashes whose top `global_depth` bits are `i`
+    directory: Vec<usize>,
+    partitions: Vec<Partition<K, V, S>>,
+    global_depth: u32,
+    threshold: usize,
+    load: usize,
+    hash_builder: S,
+}
+
+impl<K, V> PartitionedMap<K, V, hash::RandomState> {
+    pub fn new() -> Self {
+        PartitionedMap::with_threshold(DEFAULT_THRESHOLD)
+    }
+
+    /// Creates a map whose partitions split once they hold more than `threshold` entries
+    pub fn with_threshold(threshold: usize) -> Self {
+        PartitionedMap::with_threshold_and_hasher(threshold, hash::RandomState::new())
+    }
+}
+
+impl<K, V> Default for PartitionedMap<K, V, hash::RandomState> {
+    fn default() -> Self {
+        PartitionedMap::new()
+    }
+}
+
+impl<K, V, S: Clone> PartitionedMap<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        PartitionedMap::with_threshold_and_hasher(DEFAULT_THRESHOLD, hash_builder)
+    }
+
+    pub fn with_threshold_and_hasher(threshold: usize, hash_builder: S) -> Self {
+        PartitionedMap {
+            directory: vec![0],
+            partitions: vec![Partition {
+                depth: 0,
+                map: ChainingHashMap::with_hasher(hash_builder.clone()),
+            }],
+            global_depth: 0,
+            threshold: threshold.max(1),
+            load: 0,
+            hash_builder,
+        }
+    }
+
+    /// Removes every entry, going back to a single partition
+    pub fn clear(&mut self) {
+        self.directory = vec![0];
+        self.partitions.truncate(1);
+        self.partitions[0].depth = 0;
+        self.partitions[0].map.clear();
+        self.global_depth = 0;
+        self.load = 0;
+    }
+}
+
+impl<K, V, S> PartitionedMap<K, V, S> {
+    pub fn len(&self) -> usize {
+        self.load
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.load == 0
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    pub fn partition_count(&self) -> usize {
+        self.partitions.len()
+    }
+
+    /// Iterates over the entries of every partition in turn, in no particular order
+    pub fn iter(&self) -> Iter<'_, K, V, S> {
+        Iter {
+            partitions: self.partitions.iter(),
+            partition: None,
+            remaining: self.load,
+        }
+    }
+
+    /// Iterates over the entries with mutable references to the values, in no particular order
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V, S> {
+        IterMut {
+            partitions: self.partitions.iter_mut(),
+            partition: None,
+            remaining: self.load,
+        }
+    }
+
+    /// Iterates over the keys, in no particular order
+    pub fn keys(&self) -> Keys<'_, K, V, S> {
+        Keys { inner: self.iter() }
+    }
+
+    /// Iterates over the values, in no particular order
+    pub fn values(&self) -> Values<'_, K, V, S> {
+        Values { inner: self.iter() }
+    }
+
+    // the directory slot for a hash, from its top `depth` bits
+    fn slot(hash: u64, depth: u32) -> usize {
+        hash.checked_shr(64 - depth).unwrap_or(0) as usize
+    }
+}
+
+impl<K, V, S> PartitionedMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher + Clone,
+{
+    fn partition_for<Q>(&self, key: &Q) -> usize
+    where
+        Q: ?Sized + hash::Hash,
+    {
+        let hash = self.hash_builder.hash_one(key);
+        self.directory[Self::slot(hash, self.global_depth)]
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let partition = self.partition_for(&key);
+        let result = self.partitions[partition].map.insert(key, value);
+
+        if result.is_none() {
+            self.load += 1;
+
+            let Partition { depth, map } = &self.partitions[partition];
+            if map.len() > self.threshold && *depth < MAX_DEPTH {
+                self.split(partition);
+            }
+        }
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        result
+    }
+
+    fn split(&mut self, partition: usize) {
+        let depth = self.partitions[partition].depth;
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "split",
+            partition,
+            depth,
+            entries = self.partitions[partition].map.len()
+        )
+        .entered();
+
+        if depth == self.global_depth {
+            // every slot becomes two adjacent slots, one more bit deep
+            self.directory = self
+                .directory
+                .iter()
+                .flat_map(|target| [*target, *target])
+                .collect();
+            self.global_depth += 1;
+        }
+
+        // entries with the next hash bit set move to the new partition, which takes over the
+        // upper half of the old one's directory slots
+        let bit = 1 << (63 - depth);
+        let mut stay = ChainingHashMap::with_hasher(self.hash_builder.clone());
+        let mut moved = ChainingHashMap::with_hasher(self.hash_builder.clone());
+        let old = mem::replace(
+            &mut self.partitions[partition].map,
+            ChainingHashMap::with_hasher(self.hash_builder.clone()),
+        );
+
+        for (key, value) in old {
+            if self.hash_builder.hash_one(&key) & bit == 0 {
+                stay.insert(key, value);
+            } else {
+                moved.insert(key, value);
+            }
+        }
+
+        self.partitions[partition] = Partition {
+            depth: depth + 1,
+            map: stay,
+        };
+        let new_partition = self.partitions.len();
+        self.partitions.push(Partition {
+            depth: depth + 1,
+            map: moved,
+        });
+
+        let span = 1 << (self.global_depth - depth);
+        let start = self
+            .directory
+            .iter()
+            .position(|target| *target == partition)
+            .expect("every partition is in the directory");
+        for target in &mut self.directory[start + span / 2..start + span] {
+            *target = new_partition;
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(partitions = self.partitions.len(), "split");
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.partitions[self.partition_for(key)].map.get(key)
+    }
+
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.partitions[self.partition_for(key)]
+            .map
+            .get_key_value(key)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let partition = self.partition_for(key);
+        self.partitions[partition].map.get_mut(key)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let partition = self.partition_for(key);
+        let entry = self.partitions[partition].map.remove_entry(key)?;
+        self.load -= 1;
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        Some(entry)
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.remove_entry(key).map(|entry| entry.1)
+    }
+
+    /// Checks the directory against the partition depths, including that each partition owns one
+    /// contiguous run of slots, and that every entry was routed to the right partition
+    #[cfg(any(test, feature = "validate"))]
+    pub fn debug_validate(&self) {
+        assert_eq!(
+            self.directory.len(),
+            1 << self.global_depth,
+            "directory size does not match the global depth"
+        );
+
+        let mut entries = 0;
+
+        for (idx, partition) in self.partitions.iter().enumerate() {
+            assert!(
+                partition.depth <= self.global_depth,
+                "partition is deeper than the directory"
+            );
+
+            let slots = self
+                .directory
+                .iter()
+                .enumerate()
+                .filter(|(_, target)| **target == idx)
+                .map(|(slot, _)| slot)
+                .collect::<Vec<_>>();
+            assert_eq!(
+                slots.len(),
+                1 << (self.global_depth - partition.depth),
+                "partition is not referenced by the expected number of directory slots"
+            );
+            assert!(
+                slots.windows(2).all(|pair| pair[0] + 1 == pair[1]),
+                "partition's directory slots are not contiguous"
+            );
+
+            for (key, _) in partition.map.iter() {
+                assert_eq!(
+                    self.partition_for(key),
+                    idx,
+                    "entry is stored in the wrong partition"
+                );
+            }
+            entries += partition.map.len();
+        }
+
+        assert_eq!(
+            entries, self.load,
+            "load does not match the number of stored entries"
+        );
+    }
+}
+
+/// An iterator over a `PartitionedMap`'s entries, from `PartitionedMap::iter`.
+///
+/// Finishes each partition before starting on the next.
+#[derive(Debug)]
+pub struct Iter<'a, K, V, S = hash::RandomState> {
+    partitions: std::slice::Iter<'a, Partition<K, V, S>>,
+    partition: Option<chaining_map::Iter<'a, K, V>>,
+    remaining: usize,
+}
+
+impl<'a, K, V, S> Iterator for Iter<'a, K, V, S> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            if let Some(entry) = self.partition.as_mut().and_then(Iterator::next) {
+                self.remaining -= 1;
+                return Some(entry);
+            }
+            self.partition = Some(self.partitions.next()?.map.iter());
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V, S> ExactSizeIterator for Iter<'_, K, V, S> {}
+
+impl<K, V, S> std::iter::FusedIterator for Iter<'_, K, V, S> {}
+
+impl<K, V, S> Clone for Iter<'_, K, V, S> {
+    fn clone(&self) -> Self {
+        Iter {
+            partitions: self.partitions.clone(),
+            partition: self.partition.clone(),
+            remaining: self.remaining,
+        }
+    }
+}
+
+/// A mutable iterator over a `PartitionedMap`'s entries, from `PartitionedMap::iter_mut`.
+#[derive(Debug)]
+pub struct IterMut<'a, K, V, S = hash::RandomState> {
+    partitions: std::slice::IterMut<'a, Partition<K, V, S>>,
+    partition: Option<chaining_map::IterMut<'a, K, V>>,
+    remaining: usize,
+}
+
+impl<'a, K, V, S> Iterator for IterMut<'a, K, V, S> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        loop {
+            if let Some(entry) = self.partition.as_mut().and_then(Iterator::next) {
+                self.remaining -= 1;
+                return Some(entry);
+            }
+            self.partition = Some(self.partitions.next()?.map.iter_mut());
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V, S> ExactSizeIterator for IterMut<'_, K, V, S> {}
+
+impl<K, V, S> std::iter::FusedIterator for IterMut<'_, K, V, S> {}
+
+crate::iter::keys_and_values!(PartitionedMap, Iter<K, V, S = hash::RandomState>);
+crate::iter::borrowed_into_iterator!(PartitionedMap<K, V, S>, Iter<K, V, S>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashers::ConstantState;
+
+    crate::testkit::map_conformance_tests!(PartitionedMap::<usize, usize>::with_threshold(16));
+
+    #[test]
+    fn iterates_across_partitions() {
+        let mut map = PartitionedMap::with_threshold(16);
+        for i in 0..200 {
+            map.insert(i, i);
+        }
+        assert!(map.partition_count() > 1);
+
+        let iter = map.iter();
+        assert_eq!(iter.len(), 200);
+        assert_eq!(iter.clone().count(), 200);
+        assert_eq!(map.keys().sum::<i32>(), 19900);
+
+        for (key, value) in &mut map {
+            *value += key;
+        }
+        assert_eq!(map.iter_mut().len(), 200);
+        assert_eq!(map.values().sum::<i32>(), 2 * 19900);
+        assert!((&map).into_iter().all(|(key, value)| *value == key * 2));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn traces_splits() {
+        let mut map = PartitionedMap::with_threshold(50);
+        let traces = crate::testkit::record_traces(|| {
+            for i in 0..1000 {
+                map.insert(i, i);
+            }
+        });
+
+        // a span and a closing event for every split
+        let splits = traces.iter().filter(|trace| *trace == "split").count();
+        assert_eq!(splits, 2 * (map.partition_count() - 1));
+    }
+
+    #[test]
+    fn splits_past_the_threshold() {
+        let mut map = PartitionedMap::with_threshold(50);
+
+        for i in 0..1000 {
+            map.insert(i, i);
+        }
+
+        assert!(map.partition_count() >= 1000 / 50);
+        assert!(map
+            .partitions
+            .iter()
+            .all(|partition| partition.map.len() <= 50));
+        for i in 0..1000 {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+        assert_eq!(map.iter().count(), 1000);
+    }
+
+    #[test]
+    fn identical_hashes_stop_splitting() {
+        let mut map = PartitionedMap::with_threshold_and_hasher(4, ConstantState::default());
+
+        for i in 0..20 {
+            map.insert(i, i);
+        }
+
+        // nothing ever moves, but the depth cap bounds the directory
+        assert_eq!(map.len(), 20);
+        assert_eq!(map.get(&7), Some(&7));
+        assert!(map.global_depth <= MAX_DEPTH);
+    }
+
+    #[test]
+    fn clear() {
+        let mut map = PartitionedMap::with_threshold(8);
+
+        for i in 0..100 {
+            map.insert(i, i);
+        }
+        map.clear();
+
+        assert!(map.is_empty());
+        assert_eq!(map.partition_count(), 1);
+        assert_eq!(map.get(&1), None);
+
+        map.insert(1, 1);
+        assert_eq!(map.get(&1), Some(&1));
+    }
+}
@@ -0,0 +1,121 @@
+//! Following a path of keys through nested maps.
+//!
+//! Tree-shaped data, like parsed configuration, is usually a map whose values are either leaves
+//! or further maps of the same kind. Implementing `Branch` for the value type lets
+//! `get_path(["a", "b", "c"])` walk such a tree in one call.
+
+use crate::chaining_map::ChainingHashMap;
+use crate::equivalent::Equivalent;
+use std::hash;
+
+/// A map value that may itself be a map of the same shape.
+pub trait Branch<K, S = hash::RandomState>: Sized {
+    /// The map held by this value, or `None` if it's a leaf
+    fn branch(&self) -> Option<&ChainingHashMap<K, Self, S>>;
+
+    fn branch_mut(&mut self) -> Option<&mut ChainingHashMap<K, Self, S>>;
+}
+
+impl<K, V, S> ChainingHashMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    V: Branch<K, S>,
+    S: hash::BuildHasher,
+{
+    /// Looks up each key of `path` in the map the previous one led to, returning the value at
+    /// the end of the path
+    ///
+    /// `None` if any key is missing, if the path runs into a leaf before its end, or if it's
+    /// empty.
+    pub fn get_path<'q, Q, I>(&self, path: I) -> Option<&V>
+    where
+        I: IntoIterator<Item = &'q Q>,
+        Q: ?Sized + hash::Hash + Equivalent<K> + 'q,
+    {
+        let mut path = path.into_iter();
+        let mut value = self.get(path.next()?)?;
+
+        for key in path {
+            value = value.branch()?.get(key)?;
+        }
+
+        Some(value)
+    }
+
+    pub fn get_path_mut<'q, Q, I>(&mut self, path: I) -> Option<&mut V>
+    where
+        I: IntoIterator<Item = &'q Q>,
+        Q: ?Sized + hash::Hash + Equivalent<K> + 'q,
+    {
+        let mut path = path.into_iter();
+        let mut value = self.get_mut(path.next()?)?;
+
+        for key in path {
+            value = value.branch_mut()?.get_mut(key)?;
+        }
+
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    enum Config {
+        Leaf(i32),
+        Table(ChainingHashMap<String, Config>),
+    }
+
+    impl Branch<String> for Config {
+        fn branch(&self) -> Option<&ChainingHashMap<String, Config>> {
+            match self {
+                Config::Table(table) => Some(table),
+                Config::Leaf(_) => None,
+            }
+        }
+
+        fn branch_mut(&mut self) -> Option<&mut ChainingHashMap<String, Config>> {
+            match self {
+                Config::Table(table) => Some(table),
+                Config::Leaf(_) => None,
+            }
+        }
+    }
+
+    fn table(entries: impl IntoIterator<Item = (&'static str, Config)>) -> Config {
+        let mut table = ChainingHashMap::new();
+        for (key, value) in entries {
+            table.insert(key.to_string(), value);
+        }
+        Config::Table(table)
+    }
+
+    #[test]
+    fn get_path() {
+        let Config::Table(mut config) = table([
+            ("port", Config::Leaf(80)),
+            ("db", table([("pool", table([("size", Config::Leaf(4))]))])),
+        ]) else {
+            unreachable!()
+        };
+
+        assert!(matches!(
+            config.get_path(["db", "pool", "size"]),
+            Some(Config::Leaf(4))
+        ));
+        assert!(matches!(config.get_path(["port"]), Some(Config::Leaf(80))));
+        assert!(matches!(config.get_path(["db"]), Some(Config::Table(_))));
+
+        assert!(config.get_path(["db", "missing"]).is_none());
+        assert!(config.get_path(["port", "nested"]).is_none());
+        assert!(config.get_path::<str, _>([]).is_none());
+
+        *config.get_path_mut(["db", "pool", "size"]).unwrap() = Config::Leaf(8);
+        assert!(matches!(
+            config.get_path(["db", "pool", "size"]),
+            Some(Config::Leaf(8))
+        ));
+    }
+}
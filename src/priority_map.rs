@@ -0,0 +1,570 @@
+use crate::chaining_map::ChainingHashMap;
+use crate::equivalent::Equivalent;
+use std::hash;
+use std::iter::FusedIterator;
+
+#[derive(Debug)]
+struct HeapEntry<K, P, V> {
+    key: K,
+    priority: P,
+    value: V,
+}
+
+/// A double-ended priority queue whose entries can also be looked up, reprioritized, and removed
+/// by key.
+///
+/// The entries form a min-max heap in a `Vec`: levels alternate between holding the minimum and
+/// the maximum of their subtrees, so both ends are reachable in O(1) and poppable in O(log n). A
+/// `ChainingHashMap` tracks where each key currently sits in the heap, so `change_priority` and
+/// `remove` run in O(log n) instead of O(n).
+#[derive(Debug)]
+pub struct PriorityMap<K, P, V> {
+    heap: Vec<HeapEntry<K, P, V>>,
+    positions: ChainingHashMap<K, usize>,
+}
+
+impl<K, P, V> PriorityMap<K, P, V> {
+    pub fn new() -> Self {
+        PriorityMap {
+            heap: Vec::new(),
+            positions: ChainingHashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.heap.clear();
+        self.positions.clear();
+    }
+
+    /// Gets the entry with the lowest priority without removing it
+    pub fn peek_min(&self) -> Option<(&K, &P, &V)> {
+        self.heap
+            .first()
+            .map(|entry| (&entry.key, &entry.priority, &entry.value))
+    }
+
+    /// Gets the entry with the highest priority without removing it
+    pub fn peek_max(&self) -> Option<(&K, &P, &V)>
+    where
+        P: Ord,
+    {
+        self.max_index()
+            .map(|idx| &self.heap[idx])
+            .map(|entry| (&entry.key, &entry.priority, &entry.value))
+    }
+
+    // the maximum is the larger of the root's children, or the root itself if it has none
+    fn max_index(&self) -> Option<usize>
+    where
+        P: Ord,
+    {
+        match self.heap.len() {
+            0 => None,
+            1 => Some(0),
+            2 => Some(1),
+            _ if self.heap[2].priority > self.heap[1].priority => Some(2),
+            _ => Some(1),
+        }
+    }
+
+    /// Iterates over the entries in heap order, which is not sorted order
+    pub fn iter(
+        &self,
+    ) -> impl ExactSizeIterator<Item = (&K, &P, &V)> + DoubleEndedIterator + FusedIterator {
+        self.heap
+            .iter()
+            .map(|entry| (&entry.key, &entry.priority, &entry.value))
+    }
+}
+
+impl<K, P, V> Default for PriorityMap<K, P, V> {
+    fn default() -> Self {
+        PriorityMap::new()
+    }
+}
+
+impl<K, P, V> PriorityMap<K, P, V>
+where
+    K: Eq + hash::Hash + Clone,
+    P: Ord,
+{
+    /// Adds an entry, returning the previous priority and value if the key was already queued
+    pub fn push(&mut self, key: K, priority: P, value: V) -> Option<(P, V)> {
+        let previous = self.remove(&key);
+
+        let idx = self.heap.len();
+        self.positions.insert(key.clone(), idx);
+        self.heap.push(HeapEntry {
+            key,
+            priority,
+            value,
+        });
+        self.sift_up(idx);
+
+        previous
+    }
+
+    /// Removes and returns the entry with the lowest priority
+    pub fn pop_min(&mut self) -> Option<(K, P, V)> {
+        if self.heap.is_empty() {
+            return None;
+        }
+
+        let entry = self.remove_at(0);
+        Some((entry.key, entry.priority, entry.value))
+    }
+
+    /// Removes and returns the entry with the highest priority
+    pub fn pop_max(&mut self) -> Option<(K, P, V)> {
+        let idx = self.max_index()?;
+
+        let entry = self.remove_at(idx);
+        Some((entry.key, entry.priority, entry.value))
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.positions.get(key).map(|&idx| &self.heap[idx].value)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let idx = *self.positions.get(key)?;
+        Some(&mut self.heap[idx].value)
+    }
+
+    pub fn priority<Q>(&self, key: &Q) -> Option<&P>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.positions.get(key).map(|&idx| &self.heap[idx].priority)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.positions.get(key).is_some()
+    }
+
+    /// Moves the entry for the key to a new priority, returning the old one if the key is queued
+    pub fn change_priority<Q>(&mut self, key: &Q, priority: P) -> Option<P>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let idx = *self.positions.get(key)?;
+        let old = std::mem::replace(&mut self.heap[idx].priority, priority);
+        self.restore(idx);
+
+        Some(old)
+    }
+
+    /// Removes the entry for the key wherever it sits in the heap
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<(P, V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let idx = *self.positions.get(key)?;
+        let entry = self.remove_at(idx);
+        Some((entry.priority, entry.value))
+    }
+
+    fn remove_at(&mut self, idx: usize) -> HeapEntry<K, P, V> {
+        // move the last entry into the hole, then restore the heap property around it
+        let last = self.heap.len() - 1;
+        self.swap(idx, last);
+
+        let entry = self.heap.pop().expect("heap is not empty");
+        self.positions.remove(&entry.key);
+
+        if idx < self.heap.len() {
+            self.restore(idx);
+        }
+
+        entry
+    }
+
+    // moves an entry whose priority may be wrong for its position to where it belongs
+    fn restore(&mut self, idx: usize) {
+        let idx = self.sift_down(idx);
+        self.sift_up(idx);
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        if a == b {
+            return;
+        }
+
+        self.heap.swap(a, b);
+        if let Some(position) = self.positions.get_mut(&self.heap[a].key) {
+            *position = a;
+        }
+        if let Some(position) = self.positions.get_mut(&self.heap[b].key) {
+            *position = b;
+        }
+    }
+
+    // whether `a` belongs above `b` on the given kind of level
+    fn precedes(&self, a: usize, b: usize, min_level: bool) -> bool {
+        if min_level {
+            self.heap[a].priority < self.heap[b].priority
+        } else {
+            self.heap[a].priority > self.heap[b].priority
+        }
+    }
+
+    fn sift_up(&mut self, idx: usize) {
+        if idx == 0 {
+            return;
+        }
+
+        // an entry on the wrong side of its parent belongs on the parent's kind of level
+        let parent = (idx - 1) / 2;
+        let mut min_level = is_min_level(idx);
+        let mut idx = idx;
+        if self.precedes(parent, idx, min_level) {
+            self.swap(idx, parent);
+            idx = parent;
+            min_level = !min_level;
+        }
+
+        // from there it only climbs through levels of its own kind
+        while idx > 2 {
+            let grandparent = ((idx - 1) / 2 - 1) / 2;
+            if !self.precedes(idx, grandparent, min_level) {
+                break;
+            }
+            self.swap(idx, grandparent);
+            idx = grandparent;
+        }
+    }
+
+    // returns where the entry starting at `idx` ends up
+    fn sift_down(&mut self, mut idx: usize) -> usize {
+        let min_level = is_min_level(idx);
+        let mut sifted = idx;
+
+        loop {
+            // the most extreme of the children and grandchildren
+            let first_child = 2 * idx + 1;
+            let descendants = [
+                first_child,
+                first_child + 1,
+                2 * first_child + 1,
+                2 * first_child + 2,
+                2 * first_child + 3,
+                2 * first_child + 4,
+            ];
+            let Some(extreme) = descendants
+                .into_iter()
+                .filter(|&i| i < self.heap.len())
+                .reduce(|a, b| if self.precedes(b, a, min_level) { b } else { a })
+            else {
+                return sifted;
+            };
+
+            if !self.precedes(extreme, idx, min_level) {
+                return sifted;
+            }
+            self.swap(idx, extreme);
+            if idx == sifted {
+                sifted = extreme;
+            }
+            if extreme <= first_child + 1 {
+                return sifted;
+            }
+
+            // a grandchild came up; what went down in its place may belong above its new parent
+            let parent = (extreme - 1) / 2;
+            if self.precedes(parent, extreme, min_level) {
+                self.swap(extreme, parent);
+                if sifted == extreme {
+                    sifted = parent;
+                }
+            }
+            idx = extreme;
+        }
+    }
+}
+
+// the root's level holds minimums, its children's maximums, and so on alternately
+fn is_min_level(idx: usize) -> bool {
+    (idx + 1).ilog2() & 1 == 0
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a, K, P, V> arbitrary::Arbitrary<'a> for PriorityMap<K, P, V>
+where
+    K: arbitrary::Arbitrary<'a> + Eq + hash::Hash + Clone,
+    P: arbitrary::Arbitrary<'a> + Ord,
+    V: arbitrary::Arbitrary<'a>,
+{
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut map = PriorityMap::new();
+
+        for entry in u.arbitrary_iter::<(K, P, V)>()? {
+            let (key, priority, value) = entry?;
+            map.push(key, priority, value);
+        }
+
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Reverse;
+
+    #[test]
+    fn push_and_pop_in_priority_order() {
+        let mut map = PriorityMap::new();
+
+        for (i, priority) in [5, 3, 8, 1, 9, 2, 7].into_iter().enumerate() {
+            assert_eq!(map.push(i, priority, i * 10), None);
+        }
+
+        let mut popped = Vec::new();
+        while let Some((_, priority, _)) = map.pop_min() {
+            popped.push(priority);
+        }
+
+        assert_eq!(popped, vec![1, 2, 3, 5, 7, 8, 9]);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn push_existing_key_replaces() {
+        let mut map = PriorityMap::new();
+
+        map.push("a", 5, 1);
+        assert_eq!(map.push("a", 2, 2), Some((5, 1)));
+
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.peek_min(), Some((&"a", &2, &2)));
+    }
+
+    #[test]
+    fn lookup_by_key() {
+        let mut map = PriorityMap::new();
+
+        map.push("a", 5, "five");
+        map.push("b", 1, "one");
+
+        assert_eq!(map.get(&"a"), Some(&"five"));
+        assert_eq!(map.priority(&"b"), Some(&1));
+        assert!(map.contains_key(&"a"));
+        assert!(!map.contains_key(&"c"));
+
+        if let Some(value) = map.get_mut(&"a") {
+            *value = "FIVE";
+        }
+
+        assert_eq!(map.get(&"a"), Some(&"FIVE"));
+    }
+
+    #[test]
+    fn change_priority() {
+        let mut map = PriorityMap::new();
+
+        for i in 0..10 {
+            map.push(i, i, ());
+        }
+
+        assert_eq!(map.change_priority(&7, -1), Some(7));
+        assert_eq!(map.change_priority(&0, 100), Some(0));
+        assert_eq!(map.change_priority(&42, 0), None);
+
+        assert_eq!(map.pop_min().map(|entry| entry.0), Some(7));
+        assert_eq!(map.pop_min().map(|entry| entry.0), Some(1));
+
+        let mut last = None;
+        while let Some(entry) = map.pop_min() {
+            last = Some(entry.0);
+        }
+        assert_eq!(last, Some(0));
+    }
+
+    #[test]
+    fn remove_from_middle() {
+        let mut map = PriorityMap::new();
+
+        for i in 0..20 {
+            map.push(i, (i * 7) % 20, i);
+        }
+
+        for i in (0..20).step_by(3) {
+            assert_eq!(map.remove(&i), Some(((i * 7) % 20, i)));
+        }
+        assert_eq!(map.remove(&0), None);
+
+        let mut previous = None;
+        while let Some((key, priority, _)) = map.pop_min() {
+            assert!(key % 3 != 0);
+            assert!(previous <= Some(priority));
+            previous = Some(priority);
+        }
+    }
+
+    #[test]
+    fn iter_is_exact_size() {
+        let mut map = PriorityMap::new();
+
+        for i in 0..10 {
+            map.push(i, i, ());
+        }
+
+        let mut iter = map.iter();
+        assert_eq!(iter.len(), 10);
+        iter.next();
+        iter.next_back();
+        assert_eq!(iter.len(), 8);
+    }
+
+    #[test]
+    fn pop_from_both_ends() {
+        let mut map = PriorityMap::new();
+        assert_eq!(map.peek_max(), None);
+        assert_eq!(map.pop_max(), None);
+
+        for (i, priority) in [5, 3, 8, 1, 9, 2, 7].into_iter().enumerate() {
+            map.push(i, priority, ());
+        }
+
+        assert_eq!(map.peek_max(), Some((&4, &9, &())));
+        assert_eq!(map.pop_max().map(|entry| entry.1), Some(9));
+        assert_eq!(map.pop_min().map(|entry| entry.1), Some(1));
+        assert_eq!(map.pop_max().map(|entry| entry.1), Some(8));
+        assert_eq!(map.peek_min(), Some((&5, &2, &())));
+
+        let mut popped = Vec::new();
+        while let Some((_, priority, _)) = map.pop_max() {
+            popped.push(priority);
+        }
+        assert_eq!(popped, vec![7, 5, 3, 2]);
+    }
+
+    #[test]
+    fn lookup_by_borrowed_key() {
+        let mut map = PriorityMap::new();
+
+        map.push("a".to_string(), 5, "five");
+
+        assert_eq!(map.get("a"), Some(&"five"));
+        assert_eq!(map.priority("a"), Some(&5));
+        assert_eq!(map.change_priority("a", 1), Some(5));
+        assert!(map.contains_key("a"));
+        assert_eq!(map.remove("a"), Some((1, "five")));
+    }
+
+    #[test]
+    fn max_ordering_with_reverse() {
+        let mut map = PriorityMap::new();
+
+        for i in 0..5 {
+            map.push(i, Reverse(i), ());
+        }
+
+        assert_eq!(map.pop_min().map(|entry| entry.0), Some(4));
+    }
+}
+
+#[cfg(test)]
+mod proptests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::HashMap;
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Push(u8, i16),
+        PopMin,
+        PopMax,
+        ChangePriority(u8, i16),
+        Remove(u8),
+    }
+
+    fn ops() -> impl Strategy<Value = Vec<Op>> {
+        let op = prop_oneof![
+            3 => (0..64u8, any::<i16>()).prop_map(|(k, p)| Op::Push(k, p)),
+            1 => Just(Op::PopMin),
+            1 => Just(Op::PopMax),
+            1 => (0..64u8, any::<i16>()).prop_map(|(k, p)| Op::ChangePriority(k, p)),
+            1 => (0..64u8).prop_map(Op::Remove),
+        ];
+        prop::collection::vec(op, 0..300)
+    }
+
+    // every entry lies between its min-level and max-level ancestors
+    fn check_heap(map: &PriorityMap<u8, i16, ()>) -> Result<(), TestCaseError> {
+        for idx in 1..map.heap.len() {
+            let mut ancestor = idx;
+            while ancestor > 0 {
+                ancestor = (ancestor - 1) / 2;
+                let (priority, bound) = (map.heap[idx].priority, map.heap[ancestor].priority);
+                if is_min_level(ancestor) {
+                    prop_assert!(bound <= priority);
+                } else {
+                    prop_assert!(bound >= priority);
+                }
+            }
+            prop_assert_eq!(map.positions.get(&map.heap[idx].key), Some(&idx));
+        }
+
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn matches_model(ops in ops()) {
+            let mut map = PriorityMap::new();
+            let mut model = HashMap::new();
+
+            for op in ops {
+                match op {
+                    Op::Push(k, p) => {
+                        prop_assert_eq!(map.push(k, p, ()).map(|entry| entry.0), model.insert(k, p));
+                    }
+                    Op::PopMin => {
+                        let min = model.values().min().copied();
+                        let popped = map.pop_min();
+                        prop_assert_eq!(popped.map(|entry| entry.1), min);
+                        if let Some((k, _, _)) = popped {
+                            model.remove(&k);
+                        }
+                    }
+                    Op::PopMax => {
+                        let max = model.values().max().copied();
+                        let popped = map.pop_max();
+                        prop_assert_eq!(popped.map(|entry| entry.1), max);
+                        if let Some((k, _, _)) = popped {
+                            model.remove(&k);
+                        }
+                    }
+                    Op::ChangePriority(k, p) => {
+                        let old = model.get_mut(&k).map(|old| std::mem::replace(old, p));
+                        prop_assert_eq!(map.change_priority(&k, p), old);
+                    }
+                    Op::Remove(k) => {
+                        prop_assert_eq!(map.remove(&k).map(|entry| entry.0), model.remove(&k));
+                    }
+                }
+
+                prop_assert_eq!(map.len(), model.len());
+                check_heap(&map)?;
+            }
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! An explicitly seeded hasher.
+//!
+//! `std::hash::RandomState` draws its keys from the OS, which is not available everywhere (e.g.
+//! `wasm32-unknown-unknown`) and can't be reproduced between runs. `SeededState` runs the same
+//! algorithm std uses (SipHash-1-3) with keys derived from a caller-supplied seed.
+//!
+//! Where a map still wants an unpredictable seed on such a target, register a hardware RNG or
+//! similar with `set_entropy_source`; `SeededState::random` draws from it.
+
+use std::hash::{self, BuildHasher, Hasher};
+use std::sync::{OnceLock, RwLock};
+
+static ENTROPY_SOURCE: RwLock<Option<fn() -> u64>> = RwLock::new(None);
+
+/// Registers the crate-wide source of random seeds, replacing the OS randomness std provides
+///
+/// Call it once at startup, before creating maps that seed themselves randomly.
+pub fn set_entropy_source(source: fn() -> u64) {
+    // a poisoned lock still holds a plain function pointer, which can't be left half-written
+    *ENTROPY_SOURCE.write().unwrap_or_else(|e| e.into_inner()) = Some(source);
+}
+
+fn entropy() -> u64 {
+    let source = *ENTROPY_SOURCE.read().unwrap_or_else(|e| e.into_inner());
+
+    match source {
+        Some(source) => source(),
+        // every `RandomState` is keyed differently, from the OS
+        None => hash::RandomState::new().build_hasher().finish(),
+    }
+}
+
+/// Builds `SeededHasher`s from a fixed pair of SipHash keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeededState {
+    k0: u64,
+    k1: u64,
+}
+
+impl SeededState {
+    /// Derives both SipHash keys from a single seed
+    pub const fn new(seed: u64) -> Self {
+        SeededState::with_keys(seed, seed.rotate_left(32) ^ 0x9e37_79b9_7f4a_7c15)
+    }
+
+    pub const fn with_keys(k0: u64, k1: u64) -> Self {
+        SeededState { k0, k1 }
+    }
+
+    /// Seeds from the source registered with `set_entropy_source`, or from the OS if there is
+    /// none
+    pub fn random() -> Self {
+        SeededState::new(entropy())
+    }
+}
+
+impl Default for SeededState {
+    fn default() -> Self {
+        SeededState::random()
+    }
+}
+
+impl hash::BuildHasher for SeededState {
+    type Hasher = SeededHasher;
+
+    fn build_hasher(&self) -> SeededHasher {
+        SeededHasher::with_keys(self.k0, self.k1)
+    }
+}
+
+/// A `SeededState` that is only seeded, randomly, when the first hasher is built.
+///
+/// Unlike the other states it can be created in a `const` context, which is what lets a map
+/// live in a plain `static`.
+#[derive(Debug, Clone, Default)]
+pub struct LazySeededState(OnceLock<SeededState>);
+
+impl LazySeededState {
+    pub const fn new() -> Self {
+        LazySeededState(OnceLock::new())
+    }
+}
+
+impl hash::BuildHasher for LazySeededState {
+    type Hasher = SeededHasher;
+
+    fn build_hasher(&self) -> SeededHasher {
+        self.0.get_or_init(SeededState::random).build_hasher()
+    }
+}
+
+/// SipHash-1-3, keyed by a `SeededState`.
+#[derive(Debug, Clone)]
+pub struct SeededHasher {
+    v0: u64,
+    v1: u64,
+    v2: u64,
+    v3: u64,
+    // bytes written so far, plus the trailing partial word that hasn't been compressed yet
+    length: usize,
+    tail: u64,
+    ntail: usize,
+}
+
+// reads up to 8 bytes as a little-endian word
+fn read_le(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .enumerate()
+        .fold(0, |word, (i, byte)| word | (*byte as u64) << (8 * i))
+}
+
+impl SeededHasher {
+    fn with_keys(k0: u64, k1: u64) -> Self {
+        SeededHasher {
+            v0: k0 ^ 0x736f_6d65_7073_6575,
+            v1: k1 ^ 0x646f_7261_6e64_6f6d,
+            v2: k0 ^ 0x6c79_6765_6e65_7261,
+            v3: k1 ^ 0x7465_6462_7974_6573,
+            length: 0,
+            tail: 0,
+            ntail: 0,
+        }
+    }
+
+    fn round(&mut self) {
+        self.v0 = self.v0.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(13) ^ self.v0;
+        self.v0 = self.v0.rotate_left(32);
+        self.v2 = self.v2.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(16) ^ self.v2;
+        self.v0 = self.v0.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(21) ^ self.v0;
+        self.v2 = self.v2.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(17) ^ self.v2;
+        self.v2 = self.v2.rotate_left(32);
+    }
+
+    fn compress(&mut self, word: u64) {
+        self.v3 ^= word;
+        self.round();
+        self.v0 ^= word;
+    }
+}
+
+impl hash::Hasher for SeededHasher {
+    fn write(&mut self, mut bytes: &[u8]) {
+        self.length += bytes.len();
+
+        // top up the partial word left over from the previous write
+        if self.ntail != 0 {
+            let fill = (8 - self.ntail).min(bytes.len());
+            self.tail |= read_le(&bytes[..fill]) << (8 * self.ntail);
+            self.ntail += fill;
+            bytes = &bytes[fill..];
+
+            if self.ntail < 8 {
+                return;
+            }
+
+            self.compress(self.tail);
+            self.tail = 0;
+            self.ntail = 0;
+        }
+
+        let mut words = bytes.chunks_exact(8);
+        for word in &mut words {
+            self.compress(read_le(word));
+        }
+
+        let rest = words.remainder();
+        self.tail = read_le(rest);
+        self.ntail = rest.len();
+    }
+
+    fn finish(&self) -> u64 {
+        let mut state = self.clone();
+
+        state.compress(((self.length as u64 & 0xff) << 56) | self.tail);
+        state.v2 ^= 0xff;
+        state.round();
+        state.round();
+        state.round();
+
+        state.v0 ^ state.v1 ^ state.v2 ^ state.v3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hash::{BuildHasherDefault, DefaultHasher};
+
+    #[test]
+    fn matches_std_siphash_with_zero_keys() {
+        // `DefaultHasher::new()` is SipHash-1-3 keyed with zeros
+        let ours = SeededState::with_keys(0, 0);
+        let std = BuildHasherDefault::<DefaultHasher>::default();
+
+        for key in [
+            "",
+            "a",
+            "abcdefg",
+            "abcdefgh",
+            "abcdefghi",
+            "a much longer key of text",
+        ] {
+            assert_eq!(ours.hash_one(key), std.hash_one(key), "key {:?}", key);
+        }
+        for key in [0u64, 1, 255, u64::MAX] {
+            assert_eq!(ours.hash_one(key), std.hash_one(key));
+        }
+        assert_eq!(
+            ours.hash_one((1u8, "x", 7u32)),
+            std.hash_one((1u8, "x", 7u32))
+        );
+    }
+
+    #[test]
+    fn split_writes_match_single_write() {
+        let state = SeededState::new(7);
+        let mut whole = state.build_hasher();
+        whole.write(b"0123456789abcdefXYZ");
+
+        let mut split = state.build_hasher();
+        split.write(b"012");
+        split.write(b"3456789abc");
+        split.write(b"defXYZ");
+
+        assert_eq!(whole.finish(), split.finish());
+    }
+
+    #[test]
+    fn same_seed_same_hash() {
+        assert_eq!(
+            SeededState::new(42).hash_one("key"),
+            SeededState::new(42).hash_one("key")
+        );
+        assert_ne!(
+            SeededState::new(42).hash_one("key"),
+            SeededState::new(43).hash_one("key")
+        );
+    }
+
+    // registering a source is covered by tests/entropy_source.rs, in its own process, since it
+    // would change how every other test's maps are seeded
+    #[test]
+    fn random_seeds_differ() {
+        assert_ne!(SeededState::random(), SeededState::random());
+    }
+}
@@ -0,0 +1,109 @@
+//! Serializing maps, behind the `serde` feature.
+//!
+//! `ChainingHashMap` serializes its entries in iteration order, which depends on the hasher's
+//! seed and the history of the table, so the same contents can come out differently from one
+//! run to the next. Where output must be byte-stable, such as content-addressed storage or
+//! config dumps meant to be diffed, serialize through `Sorted`, or use `sorted` with
+//! `#[serde(serialize_with = "...")]`, to emit the entries in ascending key order instead.
+
+use crate::chaining_map::ChainingHashMap;
+use serde::{Serialize, Serializer};
+
+impl<K, V, S, M> Serialize for ChainingHashMap<K, V, S, M>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    fn serialize<Ser>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+    where
+        Ser: Serializer,
+    {
+        serializer.collect_map(self.iter())
+    }
+}
+
+/// Serializes the wrapped map with its entries in ascending key order.
+#[derive(Debug)]
+pub struct Sorted<'a, K, V, S, M = ()>(pub &'a ChainingHashMap<K, V, S, M>);
+
+impl<K, V, S, M> Serialize for Sorted<'_, K, V, S, M>
+where
+    K: Serialize + Ord,
+    V: Serialize,
+{
+    fn serialize<Ser>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+    where
+        Ser: Serializer,
+    {
+        serializer.collect_map(self.0.iter_sorted_by_key())
+    }
+}
+
+/// Serializes a map with its entries in ascending key order
+pub fn sorted<K, V, S, M, Ser>(
+    map: &ChainingHashMap<K, V, S, M>,
+    serializer: Ser,
+) -> Result<Ser::Ok, Ser::Error>
+where
+    K: Serialize + Ord,
+    V: Serialize,
+    Ser: Serializer,
+{
+    Sorted(map).serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seeded::SeededState;
+
+    fn filled(seed: u64) -> ChainingHashMap<String, usize, SeededState> {
+        let mut map = ChainingHashMap::with_seed(seed);
+        for i in 0..50 {
+            map.insert(format!("key-{:02}", i), i);
+        }
+        map
+    }
+
+    #[test]
+    fn sorted_output_is_stable() {
+        let expected = (0..50)
+            .map(|i| format!("\"key-{:02}\":{}", i, i))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        for seed in 0..4 {
+            let json = serde_json::to_string(&Sorted(&filled(seed))).unwrap();
+            assert_eq!(json, format!("{{{}}}", expected));
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let map = filled(1);
+
+        let json = serde_json::to_string(&map).unwrap();
+        let back = serde_json::from_str::<ChainingHashMap<String, usize>>(&json).unwrap();
+        assert_eq!(back.len(), map.len());
+        assert!(map.iter().all(|(key, value)| back.get(key) == Some(value)));
+    }
+
+    #[test]
+    fn serialize_with() {
+        #[derive(serde::Serialize)]
+        struct Dump {
+            #[serde(serialize_with = "sorted")]
+            fields: ChainingHashMap<&'static str, i32>,
+        }
+
+        let mut fields = ChainingHashMap::new();
+        fields.insert("b", 2);
+        fields.insert("a", 1);
+        fields.insert("c", 3);
+
+        assert_eq!(
+            serde_json::to_string(&Dump { fields }).unwrap(),
+            r#"{"fields":{"a":1,"b":2,"c":3}}"#
+        );
+    }
+}
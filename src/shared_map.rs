@@ -0,0 +1,716 @@
+//! A fixed-capacity `u64` to `u64` map laid out in a caller-provided block of memory.
+//!
+//! The table holds no pointers, only atomics at fixed offsets from the start of the region, so
+//! the region can be shared memory mapped at different addresses by different processes. One
+//! `SharedMapWriter` fills the table while any number of `SharedMapReader`s look things up
+//! without locking; each slot carries a sequence number that a reader checks before and after
+//! reading it, retrying if the writer touched the slot in between, so readers never see a key
+//! paired with the wrong value.
+//!
+//! The table never grows. Removing a key leaves a tombstone, so that keys further along the same
+//! probe run stay reachable; later inserts reuse tombstones, and one at the end of a run is freed
+//! along with the tombstones directly before it. Tombstones inside a run stay until reused, so a
+//! long churn of distinct keys can still leave no slot free, at which point looking up a missing
+//! key reads every slot in the table.
+//!
+//! A reader waits on a slot the writer is partway through writing. If the writer dies there, the
+//! slot stays that way until another `SharedMapWriter::open`s the table, which repairs it;
+//! `SharedMapReader::try_get` gives up with `Error::WriterStalled` instead of waiting.
+//!
+//! With the `shm` feature, `ShmRegion` creates or opens a named POSIX shared memory segment to
+//! put the table in.
+
+use crate::bounded_map::CapacityExceeded;
+use crate::error::Error;
+use crate::static_map::mix;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::sync::atomic::{fence, AtomicU64};
+
+// identifies an initialized table, and the layout version
+const MAGIC: u64 = 0x7361_6c74_6d61_7001;
+
+// magic, slot count, len, reserved
+const HEADER_WORDS: usize = 4;
+
+// sequence, state, key, value
+const SLOT_WORDS: usize = 4;
+
+const EMPTY: u64 = 0;
+const FULL: u64 = 1;
+const REMOVED: u64 = 2;
+
+// how many times `try_get` finds a slot mid-write before giving up on the writer
+const SPIN_LIMIT: u32 = if cfg!(miri) { 1 << 8 } else { 1 << 24 };
+
+/// The number of `AtomicU64` words a region needs to hold up to `max_len` entries
+pub fn region_words(max_len: usize) -> usize {
+    HEADER_WORDS + SLOT_WORDS * slots_for(max_len)
+}
+
+// a quarter of the slots stay free, to keep probe sequences short
+fn slots_for(max_len: usize) -> usize {
+    (max_len + max_len.div_ceil(3)).max(1).next_power_of_two()
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Table<'a> {
+    header: &'a [AtomicU64],
+    slots: &'a [AtomicU64],
+}
+
+// a consistent read of one slot
+struct Slot {
+    state: u64,
+    key: u64,
+    value: u64,
+}
+
+impl<'a> Table<'a> {
+    fn split(region: &'a [AtomicU64]) -> Option<Self> {
+        if region.len() < HEADER_WORDS {
+            return None;
+        }
+        let (header, slots) = region.split_at(HEADER_WORDS);
+        Some(Table { header, slots })
+    }
+
+    fn slot_count(&self) -> usize {
+        self.slots.len() / SLOT_WORDS
+    }
+
+    fn len(&self) -> usize {
+        self.header[2].load(Acquire) as usize
+    }
+
+    // the largest number of entries the table takes before inserts fail
+    fn max_len(&self) -> usize {
+        self.slot_count() - self.slot_count() / 4
+    }
+
+    fn words(&self, slot: usize) -> &'a [AtomicU64] {
+        &self.slots[slot * SLOT_WORDS..(slot + 1) * SLOT_WORDS]
+    }
+
+    // waits indefinitely on a slot mid-write without a `spin_limit`
+    fn read(&self, slot: usize, spin_limit: Option<u32>) -> Result<Slot, Error> {
+        let words = self.words(slot);
+        let mut spins = 0;
+
+        loop {
+            let before = words[0].load(Acquire);
+            if before % 2 == 1 {
+                if spin_limit.is_some_and(|limit| spins >= limit) {
+                    return Err(Error::WriterStalled);
+                }
+                spins += 1;
+                std::hint::spin_loop();
+                continue;
+            }
+
+            let read = Slot {
+                state: words[1].load(Relaxed),
+                key: words[2].load(Relaxed),
+                value: words[3].load(Relaxed),
+            };
+            fence(Acquire);
+
+            if words[0].load(Relaxed) == before {
+                return Ok(read);
+            }
+        }
+    }
+
+    // only ever called by the single writer
+    fn write(&self, slot: usize, state: u64, key: u64, value: u64) {
+        let words = self.words(slot);
+        let sequence = words[0].load(Relaxed);
+
+        words[0].store(sequence + 1, Relaxed);
+        fence(Release);
+        words[1].store(state, Relaxed);
+        words[2].store(key, Relaxed);
+        words[3].store(value, Relaxed);
+        words[0].store(sequence + 2, Release);
+    }
+
+    // the slots `key` may be in, in the order they're searched
+    fn probe(&self, key: u64) -> impl Iterator<Item = usize> {
+        let mask = self.slot_count() - 1;
+        let start = mix(key) as usize & mask;
+        (0..=mask).map(move |i| (start + i) & mask)
+    }
+
+    // only for the writer, which never finds its own slots mid-write
+    fn read_own(&self, slot: usize) -> Slot {
+        let words = self.words(slot);
+        Slot {
+            state: words[1].load(Relaxed),
+            key: words[2].load(Relaxed),
+            value: words[3].load(Relaxed),
+        }
+    }
+
+    fn find(&self, key: u64, spin_limit: Option<u32>) -> Result<Option<(usize, u64)>, Error> {
+        for slot in self.probe(key) {
+            let read = self.read(slot, spin_limit)?;
+            match read.state {
+                EMPTY => return Ok(None),
+                FULL if read.key == key => return Ok(Some((slot, read.value))),
+                _ => {}
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn get(&self, key: u64) -> Option<u64> {
+        let found = self.find(key, None).expect("reads without a limit wait");
+        found.map(|(_, value)| value)
+    }
+
+    fn iter(self) -> impl Iterator<Item = (u64, u64)> + 'a {
+        (0..self.slot_count()).filter_map(move |slot| {
+            let read = self.read(slot, None).expect("reads without a limit wait");
+            (read.state == FULL).then_some((read.key, read.value))
+        })
+    }
+}
+
+/// The one handle allowed to change a shared table.
+///
+/// Nothing stops two processes from both opening a writer on the same region; doing so breaks
+/// the protocol and readers may then loop or see stale entries.
+#[derive(Debug)]
+pub struct SharedMapWriter<'a> {
+    table: Table<'a>,
+}
+
+impl<'a> SharedMapWriter<'a> {
+    /// Lays out an empty table in `region`, overwriting whatever it held; size the region with
+    /// `region_words`
+    ///
+    /// Panics if the region is too small for even one entry; see `try_init`.
+    pub fn init(region: &'a [AtomicU64]) -> Self {
+        SharedMapWriter::try_init(region).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Like `init`, but fails with `Error::RegionTooSmall` instead of panicking
+    pub fn try_init(region: &'a [AtomicU64]) -> Result<Self, Error> {
+        let words = region.len().saturating_sub(HEADER_WORDS) / SLOT_WORDS;
+        if words == 0 {
+            return Err(Error::RegionTooSmall);
+        }
+        let slot_count = 1 << words.ilog2();
+
+        let table = Table::split(&region[..HEADER_WORDS + SLOT_WORDS * slot_count])
+            .expect("region holds at least the header");
+        // readers that open the region mid-init see no magic, and refuse it
+        table.header[0].store(0, Release);
+        for word in table.slots {
+            word.store(0, Relaxed);
+        }
+        table.header[1].store(slot_count as u64, Relaxed);
+        table.header[2].store(0, Relaxed);
+        table.header[0].store(MAGIC, Release);
+
+        Ok(SharedMapWriter { table })
+    }
+
+    /// Takes over writing a table laid out earlier, such as after the writing process restarts
+    ///
+    /// A write the previous writer died partway through is dropped: if it was changing an entry,
+    /// the entry is gone.
+    pub fn open(region: &'a [AtomicU64]) -> Option<Self> {
+        let table = SharedMapReader::open(region)?.table;
+        let mut len = 0;
+
+        for slot in 0..table.slot_count() {
+            let words = table.words(slot);
+
+            // an odd sequence left behind would hold readers up for good, and the next write to
+            // the slot would leave it odd whenever the slot is at rest
+            let sequence = words[0].load(Relaxed);
+            if sequence % 2 == 1 {
+                // the entry may be half written; a tombstone keeps any probe run through it intact
+                words[1].store(REMOVED, Relaxed);
+                words[3].store(0, Relaxed);
+                words[0].store(sequence + 1, Release);
+            }
+
+            if words[1].load(Relaxed) == FULL {
+                len += 1;
+            }
+        }
+        // the previous writer may have died between writing a slot and counting it
+        table.header[2].store(len, Release);
+
+        Some(SharedMapWriter { table })
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The most entries the table can hold
+    pub fn max_len(&self) -> usize {
+        self.table.max_len()
+    }
+
+    pub fn insert(
+        &mut self,
+        key: u64,
+        value: u64,
+    ) -> Result<Option<u64>, CapacityExceeded<u64, u64>> {
+        let mut free = None;
+
+        for slot in self.table.probe(key) {
+            let read = self.table.read_own(slot);
+            match read.state {
+                FULL if read.key == key => {
+                    self.table.write(slot, FULL, key, value);
+                    return Ok(Some(read.value));
+                }
+                FULL => {}
+                REMOVED => {
+                    free.get_or_insert(slot);
+                }
+                _ => {
+                    free.get_or_insert(slot);
+                    break;
+                }
+            }
+        }
+
+        match free {
+            Some(slot) if self.len() < self.max_len() => {
+                self.table.write(slot, FULL, key, value);
+                self.table.header[2].fetch_add(1, Release);
+                Ok(None)
+            }
+            _ => Err(CapacityExceeded { key, value }),
+        }
+    }
+
+    pub fn get(&self, key: u64) -> Option<u64> {
+        self.table.get(key)
+    }
+
+    pub fn remove(&mut self, key: u64) -> Option<u64> {
+        let (slot, value) = self
+            .table
+            .find(key, None)
+            .expect("reads without a limit wait")?;
+
+        self.table.write(slot, REMOVED, key, 0);
+        self.table.header[2].fetch_sub(1, Release);
+        self.free_tombstones(slot);
+        Some(value)
+    }
+
+    // no probe run passes through a tombstone followed by a free slot, so it can be freed too,
+    // and then so can a tombstone before it
+    fn free_tombstones(&mut self, mut slot: usize) {
+        let mask = self.table.slot_count() - 1;
+
+        while self.table.read_own(slot).state == REMOVED
+            && self.table.read_own((slot + 1) & mask).state == EMPTY
+        {
+            self.table.write(slot, EMPTY, 0, 0);
+            slot = slot.wrapping_sub(1) & mask;
+        }
+    }
+
+    /// Gives a reader over the same table
+    pub fn reader(&self) -> SharedMapReader<'a> {
+        SharedMapReader { table: self.table }
+    }
+}
+
+/// A handle that looks up entries in a shared table while a writer may be changing it.
+#[derive(Debug, Clone, Copy)]
+pub struct SharedMapReader<'a> {
+    table: Table<'a>,
+}
+
+impl<'a> SharedMapReader<'a> {
+    /// Opens a table laid out by `SharedMapWriter::init`, or `None` if the region doesn't hold
+    /// one
+    pub fn open(region: &'a [AtomicU64]) -> Option<Self> {
+        let header = Table::split(region)?.header;
+        if header[0].load(Acquire) != MAGIC {
+            return None;
+        }
+
+        let slot_count = usize::try_from(header[1].load(Relaxed)).ok()?;
+        if !slot_count.is_power_of_two()
+            || region.len() < HEADER_WORDS + SLOT_WORDS.checked_mul(slot_count)?
+        {
+            return None;
+        }
+
+        Some(SharedMapReader {
+            table: Table::split(&region[..HEADER_WORDS + SLOT_WORDS * slot_count])?,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Looks up the key, waiting on any slot the writer is partway through writing
+    ///
+    /// If the writer died partway through a write, that's until another writer opens the table;
+    /// see `try_get`.
+    pub fn get(&self, key: u64) -> Option<u64> {
+        self.table.get(key)
+    }
+
+    /// Like `get`, but fails with `Error::WriterStalled` if a slot stays mid-write for too long
+    ///
+    /// A stalled writer has most likely died; `SharedMapWriter::open` repairs the table.
+    pub fn try_get(&self, key: u64) -> Result<Option<u64>, Error> {
+        let found = self.table.find(key, Some(SPIN_LIMIT))?;
+        Ok(found.map(|(_, value)| value))
+    }
+
+    pub fn contains_key(&self, key: u64) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Iterates over the entries in slot order
+    ///
+    /// Each entry is read consistently, but the writer may change the table during the walk, so
+    /// the entries seen don't necessarily all come from one moment. Like `get`, it waits on slots
+    /// mid-write.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + 'a {
+        self.table.iter()
+    }
+}
+
+#[cfg(all(unix, feature = "shm"))]
+pub use shm::ShmRegion;
+
+#[cfg(all(unix, feature = "shm"))]
+mod shm {
+    use std::ffi::CString;
+    use std::io;
+    use std::sync::atomic::AtomicU64;
+
+    /// A named POSIX shared memory segment, mapped into this process.
+    ///
+    /// The segment outlives the mapping; call `unlink` once no process needs to open it by name
+    /// any more.
+    #[derive(Debug)]
+    pub struct ShmRegion {
+        ptr: *mut AtomicU64,
+        words: usize,
+    }
+
+    // the mapping is only ever accessed through atomics
+    unsafe impl Send for ShmRegion {}
+    unsafe impl Sync for ShmRegion {}
+
+    impl ShmRegion {
+        /// Creates the segment `name`, which must start with a `/`, sized for `words` words
+        pub fn create(name: &str, words: usize) -> io::Result<Self> {
+            ShmRegion::map(
+                name,
+                libc::O_CREAT | libc::O_EXCL | libc::O_RDWR,
+                Some(words),
+            )
+        }
+
+        /// Opens an existing segment, mapping all of it
+        pub fn open(name: &str) -> io::Result<Self> {
+            ShmRegion::map(name, libc::O_RDWR, None)
+        }
+
+        /// Removes the segment's name; existing mappings stay valid
+        pub fn unlink(name: &str) -> io::Result<()> {
+            let name = CString::new(name)?;
+            // SAFETY: `name` is a valid C string
+            if unsafe { libc::shm_unlink(name.as_ptr()) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        fn map(name: &str, flags: libc::c_int, words: Option<usize>) -> io::Result<Self> {
+            let name = CString::new(name)?;
+
+            // SAFETY: `name` is a valid C string, and the descriptor is closed on every path
+            unsafe {
+                let fd = libc::shm_open(name.as_ptr(), flags, 0o600);
+                if fd == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                let result = ShmRegion::map_fd(fd, words);
+                libc::close(fd);
+                result
+            }
+        }
+
+        unsafe fn map_fd(fd: libc::c_int, words: Option<usize>) -> io::Result<Self> {
+            let word = size_of::<AtomicU64>();
+
+            let bytes = match words {
+                Some(words) => {
+                    let bytes = words
+                        .checked_mul(word)
+                        .filter(|bytes| *bytes > 0)
+                        .ok_or(io::ErrorKind::InvalidInput)?;
+                    if libc::ftruncate(fd, bytes as libc::off_t) == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    bytes
+                }
+                None => {
+                    let mut stat = std::mem::zeroed::<libc::stat>();
+                    if libc::fstat(fd, &mut stat) == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    stat.st_size as usize / word * word
+                }
+            };
+            if bytes == 0 {
+                return Err(io::ErrorKind::InvalidData.into());
+            }
+
+            let ptr = libc::mmap(
+                std::ptr::null_mut(),
+                bytes,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            );
+            if ptr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(ShmRegion {
+                ptr: ptr.cast(),
+                words: bytes / word,
+            })
+        }
+
+        /// The mapped memory, to lay a table out in or open one from
+        pub fn words(&self) -> &[AtomicU64] {
+            // SAFETY: the mapping is page aligned, `words` long, and lives as long as `self`;
+            // every access to it goes through atomics
+            unsafe { std::slice::from_raw_parts(self.ptr, self.words) }
+        }
+    }
+
+    impl Drop for ShmRegion {
+        fn drop(&mut self) {
+            // SAFETY: unmaps exactly the mapping made in `map_fd`
+            unsafe {
+                libc::munmap(self.ptr.cast(), self.words * size_of::<AtomicU64>());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    fn region(max_len: usize) -> Vec<AtomicU64> {
+        (0..region_words(max_len))
+            .map(|_| AtomicU64::new(0))
+            .collect()
+    }
+
+    #[test]
+    fn insert_get_remove() {
+        let region = region(100);
+        let mut writer = SharedMapWriter::init(&region);
+        let reader = SharedMapReader::open(&region).unwrap();
+
+        assert!(writer.max_len() >= 100);
+        for key in 0..100 {
+            assert_eq!(writer.insert(key, key * 2), Ok(None));
+        }
+        assert_eq!(writer.insert(7, 0), Ok(Some(14)));
+
+        assert_eq!(reader.len(), 100);
+        assert_eq!(reader.get(7), Some(0));
+        assert_eq!(reader.get(8), Some(16));
+        assert_eq!(reader.get(100), None);
+
+        assert_eq!(writer.remove(8), Some(16));
+        assert_eq!(writer.remove(8), None);
+        assert!(!reader.contains_key(8));
+        assert_eq!(reader.iter().count(), 99);
+
+        // the freed slot, or its tombstone, is reused
+        assert_eq!(writer.insert(8, 1), Ok(None));
+        assert_eq!(reader.get(8), Some(1));
+    }
+
+    #[test]
+    fn frees_tombstones_at_the_end_of_a_run() {
+        let region = region(16);
+        let mut writer = SharedMapWriter::init(&region);
+        let table = writer.table;
+        let states = || {
+            (0..table.slot_count())
+                .map(|slot| table.read_own(slot).state)
+                .collect::<Vec<_>>()
+        };
+
+        for key in 1..=12 {
+            writer.insert(key, key).unwrap();
+        }
+        for key in 1..=12 {
+            writer.remove(key);
+        }
+
+        // every run ended in a free slot, so nothing is left behind
+        assert!(states().iter().all(|&state| state == EMPTY));
+
+        // a tombstone followed by an entry stays, so the entry can still be found
+        let mask = table.slot_count() - 1;
+        let home = |key| mix(key) as usize & mask;
+        let (a, b) = (1..)
+            .flat_map(|a| (a + 1..a + 100).map(move |b| (a, b)))
+            .find(|&(a, b)| home(a) == home(b))
+            .unwrap();
+        writer.insert(a, 1).unwrap();
+        writer.insert(b, 2).unwrap();
+        writer.remove(a);
+        assert_eq!(states()[home(a)], REMOVED);
+        assert_eq!(writer.get(b), Some(2));
+
+        writer.remove(b);
+        assert!(states().iter().all(|&state| state == EMPTY));
+    }
+
+    #[test]
+    fn reopening_repairs_a_write_cut_short() {
+        let region = region(16);
+        let mut writer = SharedMapWriter::init(&region);
+        for key in 0..10 {
+            writer.insert(key, key * 2).unwrap();
+        }
+        let (slot, _) = writer.table.find(3, None).unwrap().unwrap();
+        let sequence = &writer.table.words(slot)[0];
+
+        // the writer dies partway through writing a new value for 3, after counting a new entry
+        sequence.fetch_add(1, Relaxed);
+        writer.table.words(slot)[3].store(99, Relaxed);
+        writer.table.header[2].fetch_add(1, Relaxed);
+
+        let reader = SharedMapReader::open(&region).unwrap();
+        assert_eq!(reader.try_get(3), Err(Error::WriterStalled));
+
+        let mut writer = SharedMapWriter::open(&region).unwrap();
+        assert_eq!(sequence.load(Relaxed) % 2, 0);
+        assert_eq!(reader.try_get(3), Ok(None));
+        assert_eq!(reader.get(4), Some(8));
+        assert_eq!(reader.len(), 9);
+
+        // writes leave the sequence even again, so readers accept the slot only at rest
+        assert_eq!(writer.insert(3, 7), Ok(None));
+        assert_eq!(sequence.load(Relaxed) % 2, 0);
+        assert_eq!(reader.get(3), Some(7));
+        assert_eq!(reader.len(), 10);
+    }
+
+    #[test]
+    fn fills_up() {
+        let region = region(10);
+        let mut writer = SharedMapWriter::init(&region);
+
+        for key in 0..writer.max_len() as u64 {
+            writer.insert(key, key).unwrap();
+        }
+        assert_eq!(
+            writer.insert(u64::MAX, 1),
+            Err(CapacityExceeded {
+                key: u64::MAX,
+                value: 1
+            })
+        );
+        assert_eq!(writer.insert(0, 5), Ok(Some(0)));
+    }
+
+    #[test]
+    fn rejects_foreign_regions() {
+        let region = region(10);
+        assert!(SharedMapReader::open(&region).is_none());
+        assert!(SharedMapReader::open(&region[..2]).is_none());
+
+        SharedMapWriter::init(&region);
+        assert!(SharedMapReader::open(&region).is_some());
+        assert!(SharedMapReader::open(&region[..HEADER_WORDS]).is_none());
+
+        assert_eq!(
+            SharedMapWriter::try_init(&region[..HEADER_WORDS]).unwrap_err(),
+            Error::RegionTooSmall
+        );
+    }
+
+    #[test]
+    fn readers_see_consistent_pairs() {
+        let region = region(64);
+        let mut writer = SharedMapWriter::init(&region);
+        let done = AtomicBool::new(false);
+
+        std::thread::scope(|scope| {
+            for _ in 0..2 {
+                let reader = writer.reader();
+                let done = &done;
+                scope.spawn(move || {
+                    while !done.load(Relaxed) {
+                        for key in 0..64 {
+                            if let Some(value) = reader.get(key) {
+                                assert_eq!(value % 64, key);
+                            }
+                        }
+                    }
+                });
+            }
+
+            for round in 0..200 {
+                for key in 0..64 {
+                    if (key + round) % 3 == 0 {
+                        writer.remove(key);
+                    } else {
+                        writer.insert(key, round * 64 + key).unwrap();
+                    }
+                }
+            }
+            done.store(true, Relaxed);
+        });
+    }
+
+    #[cfg(all(unix, feature = "shm"))]
+    #[test]
+    fn shm_region() {
+        let name = format!("/salt-map-test-{}", std::process::id());
+
+        let created = ShmRegion::create(&name, region_words(16)).unwrap();
+        let mut writer = SharedMapWriter::init(created.words());
+        writer.insert(1, 10).unwrap();
+
+        let opened = ShmRegion::open(&name).unwrap();
+        ShmRegion::unlink(&name).unwrap();
+        let reader = SharedMapReader::open(opened.words()).unwrap();
+        assert_eq!(reader.get(1), Some(10));
+
+        writer.insert(2, 20).unwrap();
+        assert_eq!(reader.get(2), Some(20));
+    }
+}
@@ -0,0 +1,408 @@
+use crate::equivalent::Equivalent;
+use std::hash;
+use std::mem;
+
+// average entries per bucket above which the index table doubles
+const MAX_LOAD: usize = 1;
+
+/// A map storing its hashes, keys and values in three separate dense arrays.
+///
+/// Entry `i` is `keys[i]` and `values[i]`, with `hashes[i]` cached next to neither. Lookups
+/// compare hashes, then keys, and only touch a value once the key has matched, so misses, key
+/// iteration and rebuilding the index table never pull values into cache. Worth it over
+/// `DenseMap` when values are large and lookups often miss. Like `DenseMap`, removal swaps the
+/// last entry into the hole.
+#[derive(Debug)]
+pub struct SoaMap<K, V, S = hash::RandomState> {
+    // the three arrays always have the same length
+    hashes: Vec<u64>,
+    keys: Vec<K>,
+    values: Vec<V>,
+    buckets: Vec<Vec<usize>>,
+    hash_builder: S,
+}
+
+impl<K, V> SoaMap<K, V, hash::RandomState> {
+    pub fn new() -> Self {
+        SoaMap::with_hasher(hash::RandomState::new())
+    }
+}
+
+impl<K, V> Default for SoaMap<K, V, hash::RandomState> {
+    fn default() -> Self {
+        SoaMap::new()
+    }
+}
+
+impl<K, V, S> SoaMap<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        SoaMap {
+            hashes: Vec::new(),
+            keys: Vec::new(),
+            values: Vec::new(),
+            buckets: (0..16).map(|_| Vec::new()).collect(),
+            hash_builder,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.hashes.clear();
+        self.keys.clear();
+        self.values.clear();
+        self.buckets.iter_mut().for_each(Vec::clear);
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    /// Iterates over the entries in the order of the `keys` slice
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            keys: self.keys.iter(),
+            values: self.values.iter(),
+        }
+    }
+
+    /// Iterates over the entries with mutable references to the values, in the same order as
+    /// `iter`
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            keys: self.keys.iter(),
+            values: self.values.iter_mut(),
+        }
+    }
+
+    /// The keys, as one contiguous slice that holds no values
+    pub fn keys(&self) -> &[K] {
+        &self.keys
+    }
+
+    /// The values, in the same order as `keys`
+    pub fn values(&self) -> &[V] {
+        &self.values
+    }
+
+    pub fn values_mut(&mut self) -> &mut [V] {
+        &mut self.values
+    }
+
+    fn bucket_for(&self, hash: u64) -> usize {
+        (hash % self.buckets.len() as u64) as usize
+    }
+
+    fn resize(&mut self) {
+        let new_len = self.buckets.len() * 2;
+        self.buckets = (0..new_len).map(|_| Vec::new()).collect();
+
+        for (idx, hash) in self.hashes.iter().enumerate() {
+            let bucket = (hash % new_len as u64) as usize;
+            self.buckets[bucket].push(idx);
+        }
+    }
+
+    // the bucket and chain position of the index pointing at entry `idx`
+    fn slot_of(&self, idx: usize) -> (usize, usize) {
+        let bucket = self.bucket_for(self.hashes[idx]);
+        let pos = self.buckets[bucket]
+            .iter()
+            .position(|candidate| *candidate == idx)
+            .expect("every entry is indexed");
+
+        (bucket, pos)
+    }
+
+    // the index of the entry for `key`, along with where that index sits in the table
+    fn find<Q>(&self, hash: u64, key: &Q) -> Option<(usize, usize, usize)>
+    where
+        Q: ?Sized + Equivalent<K>,
+    {
+        let bucket = self.bucket_for(hash);
+        self.buckets[bucket]
+            .iter()
+            .enumerate()
+            .find(|(_, idx)| self.hashes[**idx] == hash && key.equivalent(&self.keys[**idx]))
+            .map(|(pos, idx)| (*idx, bucket, pos))
+    }
+}
+
+impl<K, V, S> SoaMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let result = self.insert_inner(key, value);
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        result
+    }
+
+    fn insert_inner(&mut self, key: K, value: V) -> Option<V> {
+        let hash = self.hash_builder.hash_one(&key);
+
+        if let Some((idx, _, _)) = self.find(hash, &key) {
+            return Some(mem::replace(&mut self.values[idx], value));
+        }
+
+        if self.len() >= self.buckets.len() * MAX_LOAD {
+            self.resize();
+        }
+
+        let bucket = self.bucket_for(hash);
+        let idx = self.len();
+        self.buckets[bucket].push(idx);
+        self.hashes.push(hash);
+        self.keys.push(key);
+        self.values.push(value);
+
+        None
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.get_key_value(key).map(|(_, value)| value)
+    }
+
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let (idx, _, _) = self.find(self.hash_builder.hash_one(key), key)?;
+        Some((&self.keys[idx], &self.values[idx]))
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let (idx, _, _) = self.find(self.hash_builder.hash_one(key), key)?;
+        Some(&mut self.values[idx])
+    }
+
+    /// Checks for a key without touching any values
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.find(self.hash_builder.hash_one(key), key).is_some()
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let (idx, bucket, pos) = self.find(self.hash_builder.hash_one(key), key)?;
+        self.buckets[bucket].swap_remove(pos);
+
+        // the last entry is about to move into the hole, so repoint its index first
+        let last = self.len() - 1;
+        if idx != last {
+            let (bucket, pos) = self.slot_of(last);
+            self.buckets[bucket][pos] = idx;
+        }
+        self.hashes.swap_remove(idx);
+        let entry = (self.keys.swap_remove(idx), self.values.swap_remove(idx));
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        Some(entry)
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.remove_entry(key).map(|entry| entry.1)
+    }
+
+    /// Checks that the key, value and hash arrays line up and that the index table holds each entry
+    /// exactly once, in the bucket of its hash
+    #[cfg(any(test, feature = "validate"))]
+    pub fn debug_validate(&self) {
+        assert!(
+            self.hashes.len() == self.keys.len() && self.keys.len() == self.values.len(),
+            "arrays have different lengths"
+        );
+
+        let mut indexed = vec![false; self.len()];
+        for (bucket, chain) in self.buckets.iter().enumerate() {
+            for idx in chain.iter() {
+                let hash = self
+                    .hashes
+                    .get(*idx)
+                    .expect("index points past the entries");
+                assert_eq!(
+                    self.bucket_for(*hash),
+                    bucket,
+                    "index is stored in the wrong bucket"
+                );
+                assert!(!indexed[*idx], "entry is indexed more than once");
+                indexed[*idx] = true;
+            }
+        }
+
+        assert!(
+            indexed.iter().all(|indexed| *indexed),
+            "entry is not indexed"
+        );
+
+        let mut seen = std::collections::HashSet::with_capacity(self.len());
+        for (hash, key) in self.hashes.iter().zip(&self.keys) {
+            assert_eq!(
+                *hash,
+                self.hash_builder.hash_one(key),
+                "cached hash is stale"
+            );
+            assert!(seen.insert(key), "key is stored more than once");
+        }
+    }
+}
+
+/// An iterator over a `SoaMap`'s entries, from `SoaMap::iter`.
+///
+/// Walks the key and value arrays side by side.
+#[derive(Debug)]
+pub struct Iter<'a, K, V> {
+    keys: std::slice::Iter<'a, K>,
+    values: std::slice::Iter<'a, V>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        Some((self.keys.next()?, self.values.next()?))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.keys.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        Some((self.keys.next_back()?, self.values.next_back()?))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> std::iter::FusedIterator for Iter<'_, K, V> {}
+
+impl<K, V> Clone for Iter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Iter {
+            keys: self.keys.clone(),
+            values: self.values.clone(),
+        }
+    }
+}
+
+/// A mutable iterator over a `SoaMap`'s entries, from `SoaMap::iter_mut`.
+#[derive(Debug)]
+pub struct IterMut<'a, K, V> {
+    keys: std::slice::Iter<'a, K>,
+    values: std::slice::IterMut<'a, V>,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        Some((self.keys.next()?, self.values.next()?))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.keys.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IterMut<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        Some((self.keys.next_back()?, self.values.next_back()?))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+
+impl<K, V> std::iter::FusedIterator for IterMut<'_, K, V> {}
+
+crate::iter::borrowed_into_iterator!(SoaMap<K, V, S>, Iter<K, V>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashers::ConstantState;
+
+    crate::testkit::map_conformance_tests!(SoaMap::<usize, usize>::new());
+
+    #[test]
+    fn iterators() {
+        let mut map = SoaMap::new();
+        for i in 0..10 {
+            map.insert(i, i);
+        }
+
+        let iter = map.iter();
+        assert_eq!(iter.len(), 10);
+        assert_eq!(iter.clone().next_back(), Some((&9, &9)));
+        assert!(iter.eq(map.keys().iter().zip(map.values())));
+
+        for (key, value) in &mut map {
+            *value += key;
+        }
+        assert_eq!(map.iter_mut().rev().len(), 10);
+        assert!((&map).into_iter().all(|(key, value)| *value == key * 2));
+    }
+
+    #[test]
+    fn arrays_stay_parallel() {
+        let mut map = SoaMap::new();
+
+        for i in 0..10 {
+            map.insert(i, [i; 16]);
+        }
+        assert_eq!(map.keys(), (0..10).collect::<Vec<_>>());
+
+        // the last entry fills the hole, in both arrays
+        assert_eq!(map.remove(&3), Some([3; 16]));
+        assert_eq!(map.keys(), [0, 1, 2, 9, 4, 5, 6, 7, 8]);
+        assert_eq!(map.values()[3], [9; 16]);
+
+        map.values_mut()[0][0] = 100;
+        assert_eq!(map.get(&0).unwrap()[0], 100);
+        assert!(map.contains_key(&9));
+        assert!(!map.contains_key(&3));
+    }
+
+    #[test]
+    fn total_collision() {
+        let mut map = SoaMap::with_hasher(ConstantState::default());
+
+        for i in 0..200 {
+            map.insert(i, i);
+        }
+        for i in (0..200).step_by(2) {
+            assert_eq!(map.remove(&i), Some(i));
+        }
+
+        assert_eq!(map.len(), 100);
+        for i in (1..200).step_by(2) {
+            assert_eq!(map.get(&i), Some(&i));
+        }
+    }
+}
@@ -0,0 +1,409 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::hash;
+use std::mem;
+
+// average entries per bucket above which the table doubles
+const MAX_LOAD: usize = 1;
+
+// exponential search: doubles a probe index until it passes `target`, then binary searches the
+// last doubling; finds entries near the front of a chain in a handful of comparisons while
+// staying logarithmic for long ones. `cmp` compares an element against the target.
+fn gallop<T, F>(chain: &[T], mut cmp: F) -> Result<usize, usize>
+where
+    F: FnMut(&T) -> Ordering,
+{
+    let mut bound = 1;
+    while bound < chain.len() && cmp(&chain[bound]) == Ordering::Less {
+        bound *= 2;
+    }
+
+    let start = bound / 2;
+    let end = (bound + 1).min(chain.len());
+    chain[start..end]
+        .binary_search_by(cmp)
+        .map(|idx| idx + start)
+        .map_err(|idx| idx + start)
+}
+
+/// A chaining map whose chains are kept sorted by hash, then key.
+///
+/// Lookups gallop through a chain instead of scanning it, so even a chain that every key
+/// collides into costs `O(log n)` comparisons. Needs `K: Ord` for that, and lookups go through
+/// `Borrow` rather than `Equivalent` since they compare order, not just equality. Doubling the
+/// table splits every chain into two subsequences that are still sorted, so resizing never
+/// compares keys.
+#[derive(Debug)]
+pub struct SortedChainMap<K, V, S = hash::RandomState> {
+    buckets: Vec<Vec<(u64, K, V)>>,
+    load: usize,
+    hash_builder: S,
+}
+
+impl<K, V> SortedChainMap<K, V, hash::RandomState> {
+    pub fn new() -> Self {
+        SortedChainMap::with_hasher(hash::RandomState::new())
+    }
+}
+
+impl<K, V> Default for SortedChainMap<K, V, hash::RandomState> {
+    fn default() -> Self {
+        SortedChainMap::new()
+    }
+}
+
+impl<K, V, S> SortedChainMap<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        SortedChainMap {
+            buckets: (0..16).map(|_| Vec::new()).collect(),
+            load: 0,
+            hash_builder,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.load
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.load == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets.iter_mut().for_each(Vec::clear);
+        self.load = 0;
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    /// Iterates over the entries, in no particular order
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            buckets: self.buckets.iter(),
+            chain: [].iter(),
+            remaining: self.load,
+        }
+    }
+
+    /// Iterates over the entries with mutable references to the values, in the same order as
+    /// `iter`
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            buckets: self.buckets.iter_mut(),
+            chain: [].iter_mut(),
+            remaining: self.load,
+        }
+    }
+
+    /// Iterates over the keys, in the same order as `iter`
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.iter() }
+    }
+
+    /// Iterates over the values, in the same order as `iter`
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { inner: self.iter() }
+    }
+
+    fn bucket_for(&self, hash: u64) -> usize {
+        (hash % self.buckets.len() as u64) as usize
+    }
+
+    fn resize(&mut self) {
+        let new_len = self.buckets.len() * 2;
+        let old = mem::replace(
+            &mut self.buckets,
+            (0..new_len).map(|_| Vec::new()).collect(),
+        );
+
+        // bucket `i` only ever receives entries from old bucket `i % old_len`, in their old
+        // order, so every new chain comes out sorted
+        for item in old.into_iter().flatten() {
+            let bucket = self.bucket_for(item.0);
+            self.buckets[bucket].push(item);
+        }
+    }
+}
+
+impl<K, V, S> SortedChainMap<K, V, S>
+where
+    K: Ord + hash::Hash,
+    S: hash::BuildHasher,
+{
+    // where an entry for `key` with `hash` is, or would go, in its chain
+    fn search<Q>(&self, hash: u64, key: &Q) -> (usize, Result<usize, usize>)
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        let bucket = self.bucket_for(hash);
+        let found = gallop(&self.buckets[bucket], |item| {
+            item.0.cmp(&hash).then_with(|| item.1.borrow().cmp(key))
+        });
+
+        (bucket, found)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let result = self.insert_inner(key, value);
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        result
+    }
+
+    fn insert_inner(&mut self, key: K, value: V) -> Option<V> {
+        let hash = self.hash_builder.hash_one(&key);
+
+        if let (bucket, Ok(idx)) = self.search(hash, &key) {
+            return Some(mem::replace(&mut self.buckets[bucket][idx].2, value));
+        }
+
+        if self.load >= self.buckets.len() * MAX_LOAD {
+            self.resize();
+        }
+
+        // searched again, since the resize may have moved it
+        let (bucket, Err(idx)) = self.search(hash, &key) else {
+            unreachable!("key was just found to be missing");
+        };
+        self.buckets[bucket].insert(idx, (hash, key, value));
+        self.load += 1;
+
+        None
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord + hash::Hash,
+    {
+        self.get_key_value(key).map(|(_, value)| value)
+    }
+
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord + hash::Hash,
+    {
+        let (bucket, idx) = self.search(self.hash_builder.hash_one(key), key);
+        let item = &self.buckets[bucket][idx.ok()?];
+        Some((&item.1, &item.2))
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord + hash::Hash,
+    {
+        let (bucket, idx) = self.search(self.hash_builder.hash_one(key), key);
+        Some(&mut self.buckets[bucket][idx.ok()?].2)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord + hash::Hash,
+    {
+        let (bucket, idx) = self.search(self.hash_builder.hash_one(key), key);
+        let (_, key, value) = self.buckets[bucket].remove(idx.ok()?);
+        self.load -= 1;
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        Some((key, value))
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord + hash::Hash,
+    {
+        self.remove_entry(key).map(|entry| entry.1)
+    }
+
+    /// Panics unless every chain is strictly sorted and in its hash's bucket, cached hashes are
+    /// current and `len()` is the entry count
+    #[cfg(any(test, feature = "validate"))]
+    pub fn debug_validate(&self) {
+        let mut entries = 0;
+
+        for (idx, bucket) in self.buckets.iter().enumerate() {
+            for item in bucket.iter() {
+                assert_eq!(
+                    item.0,
+                    self.hash_builder.hash_one(&item.1),
+                    "cached hash is stale"
+                );
+                assert_eq!(
+                    self.bucket_for(item.0),
+                    idx,
+                    "entry is stored in the wrong bucket"
+                );
+            }
+
+            assert!(
+                bucket
+                    .windows(2)
+                    .all(|pair| (pair[0].0, &pair[0].1) < (pair[1].0, &pair[1].1)),
+                "chain is not strictly sorted"
+            );
+            entries += bucket.len();
+        }
+
+        assert_eq!(
+            entries, self.load,
+            "load does not match the number of stored entries"
+        );
+    }
+}
+
+/// An iterator over a `SortedChainMap`'s entries, from `SortedChainMap::iter`.
+///
+/// Walks the buckets in order, each one's chain in its sorted order.
+#[derive(Debug)]
+pub struct Iter<'a, K, V> {
+    buckets: std::slice::Iter<'a, Vec<(u64, K, V)>>,
+    chain: std::slice::Iter<'a, (u64, K, V)>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            if let Some(item) = self.chain.next() {
+                self.remaining -= 1;
+                return Some((&item.1, &item.2));
+            }
+            self.chain = self.buckets.next()?.iter();
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> std::iter::FusedIterator for Iter<'_, K, V> {}
+
+impl<K, V> Clone for Iter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Iter {
+            buckets: self.buckets.clone(),
+            chain: self.chain.clone(),
+            remaining: self.remaining,
+        }
+    }
+}
+
+/// A mutable iterator over a `SortedChainMap`'s entries, from `SortedChainMap::iter_mut`.
+#[derive(Debug)]
+pub struct IterMut<'a, K, V> {
+    buckets: std::slice::IterMut<'a, Vec<(u64, K, V)>>,
+    chain: std::slice::IterMut<'a, (u64, K, V)>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        loop {
+            if let Some(item) = self.chain.next() {
+                self.remaining -= 1;
+                return Some((&item.1, &mut item.2));
+            }
+            self.chain = self.buckets.next()?.iter_mut();
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+
+impl<K, V> std::iter::FusedIterator for IterMut<'_, K, V> {}
+
+crate::iter::keys_and_values!(SortedChainMap, Iter<K, V>);
+crate::iter::borrowed_into_iterator!(SortedChainMap<K, V, S>, Iter<K, V>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashers::ConstantState;
+
+    crate::testkit::map_conformance_tests!(SortedChainMap::<usize, usize>::new());
+
+    #[test]
+    fn iterators() {
+        let mut map = SortedChainMap::new();
+        for i in 0..100 {
+            map.insert(i, i);
+        }
+
+        let iter = map.iter();
+        assert_eq!(iter.len(), 100);
+        assert_eq!(iter.clone().count(), 100);
+        assert_eq!(map.keys().sum::<i32>(), 4950);
+
+        for (key, value) in &mut map {
+            *value += key;
+        }
+        map.iter_mut().take(10).for_each(|(_, value)| *value = -1);
+        assert_eq!(map.iter_mut().len(), 100);
+        assert_eq!(map.values().filter(|value| **value == -1).count(), 10);
+        assert!((&map)
+            .into_iter()
+            .all(|(key, value)| *value == -1 || *value == key * 2));
+    }
+
+    #[test]
+    fn gallop_matches_binary_search() {
+        let chain = (0..100).map(|i| i * 2).collect::<Vec<_>>();
+
+        for target in -1..202 {
+            assert_eq!(
+                gallop(&chain, |item| item.cmp(&target)),
+                chain.binary_search(&target)
+            );
+        }
+        assert_eq!(gallop(&[] as &[i32], |item| item.cmp(&0)), Err(0));
+    }
+
+    #[test]
+    fn total_collision() {
+        // a single chain of every key, searched in log time
+        let mut map = SortedChainMap::with_hasher(ConstantState::default());
+
+        for i in (0..500).rev() {
+            map.insert(i, i);
+        }
+        for i in (0..500).step_by(2) {
+            assert_eq!(map.remove(&i), Some(i));
+        }
+
+        assert_eq!(map.len(), 250);
+        assert_eq!(map.get(&99), Some(&99));
+        assert_eq!(map.get(&100), None);
+    }
+
+    #[test]
+    fn borrowed_lookups() {
+        let mut map = SortedChainMap::new();
+
+        map.insert("key".to_string(), 1);
+
+        assert_eq!(map.get("key"), Some(&1));
+        assert_eq!(map.remove("key"), Some(1));
+    }
+}
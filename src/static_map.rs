@@ -0,0 +1,392 @@
+//! Lookup tables built entirely at compile time.
+//!
+//! `static_salt_map!` turns string literal keys and constant values into a `StaticMap`: a
+//! perfect hash table, computed during constant evaluation, that needs no initialization at
+//! runtime and can even be queried in `const` context. Every key has a slot of its own, so a
+//! lookup is one hash and at most one key comparison.
+//!
+//! The table is laid out with "hash, displace and compress": keys are grouped into small
+//! buckets by one part of their hash, and each bucket, largest first, gets the first pair of
+//! displacements that moves all of its keys into free slots. Repeated keys fail the build.
+
+// keys per bucket, on average; larger buckets make the table smaller but slower to build
+const LAMBDA: usize = 5;
+
+// seeds to try before giving up; a handful is almost always enough
+const MAX_SEEDS: u64 = 64;
+
+/// Builds a `static` `StaticMap` from string literal keys and constant values.
+///
+/// ```
+/// salt_map::static_salt_map! {
+///     static MIME: StaticMap<&str> = {
+///         "html" => "text/html",
+///         "png" => "image/png",
+///     };
+/// }
+///
+/// assert_eq!(MIME.get("png"), Some(&"image/png"));
+/// assert_eq!(MIME.get("gif"), None);
+/// ```
+#[macro_export]
+macro_rules! static_salt_map {
+    ($(#[$attr:meta])* $vis:vis static $name:ident: StaticMap<$value:ty> = {
+        $($key:literal => $val:expr),* $(,)?
+    };) => {
+        $(#[$attr])*
+        $vis static $name: $crate::static_map::StaticMap<$value> = {
+            const KEYS: &[&str] = &[$($key),*];
+            const ENTRIES: &[(&str, $value)] = &[$(($key, $val)),*];
+            const LAYOUT: $crate::static_map::Layout<
+                { KEYS.len() },
+                { $crate::static_map::bucket_count(KEYS.len()) },
+            > = $crate::static_map::Layout::build(KEYS);
+
+            $crate::static_map::StaticMap::from_parts(ENTRIES, &LAYOUT)
+        };
+    };
+}
+
+// FNV-1a folded through a final mix, since FNV alone leaves the low bits of short keys poorly
+// distributed
+const fn hash_str(key: &str, seed: u64) -> u64 {
+    let bytes = key.as_bytes();
+    let mut hash = 0xcbf2_9ce4_8422_2325 ^ seed;
+
+    let mut i = 0;
+    while i < bytes.len() {
+        hash = (hash ^ bytes[i] as u64).wrapping_mul(0x0100_0000_01b3);
+        i += 1;
+    }
+
+    mix(hash)
+}
+
+// splitmix64's finalizer
+pub(crate) const fn mix(mut hash: u64) -> u64 {
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+// the bucket a key is grouped into, and the two terms its displaced slot is computed from
+const fn split_hash(key: &str, seed: u64, buckets: usize) -> (usize, u64, u64) {
+    let hash = hash_str(key, seed);
+    let bucket = ((hash >> 32) % buckets as u64) as usize;
+
+    (bucket, hash & 0xffff_ffff, mix(hash) & 0xffff_ffff)
+}
+
+const fn slot_for(f1: u64, f2: u64, displacement: (u32, u32), len: usize) -> usize {
+    let (d1, d2) = (displacement.0 as u64, displacement.1 as u64);
+    (f1.wrapping_add(d1.wrapping_mul(f2)).wrapping_add(d2) % len as u64) as usize
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+
+    true
+}
+
+#[doc(hidden)]
+pub const fn bucket_count(len: usize) -> usize {
+    if len == 0 {
+        1
+    } else {
+        len.div_ceil(LAMBDA)
+    }
+}
+
+/// The perfect hash for a set of `N` keys: a seed, a displacement per bucket, and the key that
+/// landed in each slot.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct Layout<const N: usize, const B: usize> {
+    seed: u64,
+    displacements: [(u32, u32); B],
+    // the index into the entries of the key in each slot
+    slots: [usize; N],
+}
+
+impl<const N: usize, const B: usize> Layout<N, B> {
+    pub const fn build(keys: &[&str]) -> Self {
+        assert!(keys.len() == N, "layout size does not match the keys");
+
+        let mut i = 0;
+        while i < N {
+            let mut j = i + 1;
+            while j < N {
+                assert!(!str_eq(keys[i], keys[j]), "static map has a repeated key");
+                j += 1;
+            }
+            i += 1;
+        }
+
+        let mut seed = 0;
+        while seed < MAX_SEEDS {
+            if let Some(layout) = Layout::try_seed(keys, seed) {
+                return layout;
+            }
+            seed += 1;
+        }
+
+        panic!("could not find a perfect hash for the static map's keys");
+    }
+
+    const fn try_seed(keys: &[&str], seed: u64) -> Option<Self> {
+        let mut layout = Layout {
+            seed,
+            displacements: [(0, 0); B],
+            slots: [usize::MAX; N],
+        };
+        if N == 0 {
+            return Some(layout);
+        }
+
+        // group the keys by bucket, with a counting sort
+        let mut hashes = [(0, 0, 0); N];
+        let mut starts = [0; B];
+        let mut sizes = [0; B];
+        let mut i = 0;
+        while i < N {
+            hashes[i] = split_hash(keys[i], seed, B);
+            sizes[hashes[i].0] += 1;
+            i += 1;
+        }
+
+        let mut bucket = 1;
+        while bucket < B {
+            starts[bucket] = starts[bucket - 1] + sizes[bucket - 1];
+            bucket += 1;
+        }
+
+        let mut members = [0; N];
+        let mut filled = [0; B];
+        i = 0;
+        while i < N {
+            let bucket = hashes[i].0;
+            members[starts[bucket] + filled[bucket]] = i;
+            filled[bucket] += 1;
+            i += 1;
+        }
+
+        // the most crowded buckets are the hardest to place, so they go first
+        let mut order = [0; B];
+        bucket = 0;
+        while bucket < B {
+            order[bucket] = bucket;
+            bucket += 1;
+        }
+        i = 0;
+        while i < B {
+            let mut largest = i;
+            let mut j = i + 1;
+            while j < B {
+                if sizes[order[j]] > sizes[order[largest]] {
+                    largest = j;
+                }
+                j += 1;
+            }
+            let swapped = order[i];
+            order[i] = order[largest];
+            order[largest] = swapped;
+            i += 1;
+        }
+
+        // slots claimed by the displacement currently being tried, marked with its attempt
+        // number so the array never needs clearing
+        let mut claimed = [0usize; N];
+        let mut attempt = 0;
+
+        i = 0;
+        while i < B {
+            let bucket = order[i];
+            let (start, size) = (starts[bucket], sizes[bucket]);
+            i += 1;
+            if size == 0 {
+                continue;
+            }
+
+            let mut placed = false;
+            let mut d1 = 0;
+            'search: while d1 < N as u32 {
+                let mut d2 = 0;
+                while d2 < N as u32 {
+                    attempt += 1;
+
+                    let mut fits = true;
+                    let mut k = 0;
+                    while k < size {
+                        let (_, f1, f2) = hashes[members[start + k]];
+                        let slot = slot_for(f1, f2, (d1, d2), N);
+                        if layout.slots[slot] != usize::MAX || claimed[slot] == attempt {
+                            fits = false;
+                            break;
+                        }
+                        claimed[slot] = attempt;
+                        k += 1;
+                    }
+
+                    if fits {
+                        k = 0;
+                        while k < size {
+                            let key = members[start + k];
+                            let (_, f1, f2) = hashes[key];
+                            layout.slots[slot_for(f1, f2, (d1, d2), N)] = key;
+                            k += 1;
+                        }
+                        layout.displacements[bucket] = (d1, d2);
+                        placed = true;
+                        break 'search;
+                    }
+                    d2 += 1;
+                }
+                d1 += 1;
+            }
+
+            if !placed {
+                return None;
+            }
+        }
+
+        Some(layout)
+    }
+}
+
+/// A read-only map from strings to `V`, laid out at compile time by `static_salt_map!`.
+#[derive(Debug)]
+pub struct StaticMap<V: 'static> {
+    seed: u64,
+    displacements: &'static [(u32, u32)],
+    slots: &'static [usize],
+    entries: &'static [(&'static str, V)],
+}
+
+impl<V> StaticMap<V> {
+    #[doc(hidden)]
+    pub const fn from_parts<const N: usize, const B: usize>(
+        entries: &'static [(&'static str, V)],
+        layout: &'static Layout<N, B>,
+    ) -> Self {
+        assert!(entries.len() == N, "layout size does not match the entries");
+
+        StaticMap {
+            seed: layout.seed,
+            displacements: &layout.displacements,
+            slots: &layout.slots,
+            entries,
+        }
+    }
+
+    pub const fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Gets the entry for `key`; usable in `const` context
+    pub const fn get_key_value(&self, key: &str) -> Option<(&'static str, &V)> {
+        if self.entries.is_empty() {
+            return None;
+        }
+
+        let (bucket, f1, f2) = split_hash(key, self.seed, self.displacements.len());
+        let slot = slot_for(f1, f2, self.displacements[bucket], self.slots.len());
+        let entry = &self.entries[self.slots[slot]];
+
+        if str_eq(entry.0, key) {
+            Some((entry.0, &entry.1))
+        } else {
+            None
+        }
+    }
+
+    pub const fn get(&self, key: &str) -> Option<&V> {
+        match self.get_key_value(key) {
+            Some((_, value)) => Some(value),
+            None => None,
+        }
+    }
+
+    pub const fn contains_key(&self, key: &str) -> bool {
+        self.get_key_value(key).is_some()
+    }
+
+    /// Iterates over the entries in the order they were written in the macro
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &V)> {
+        self.entries.iter().map(|(key, value)| (*key, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static_salt_map! {
+        static KEYWORDS: StaticMap<u8> = {
+            "as" => 0, "break" => 1, "const" => 2, "continue" => 3, "crate" => 4, "else" => 5,
+            "enum" => 6, "extern" => 7, "false" => 8, "fn" => 9, "for" => 10, "if" => 11,
+            "impl" => 12, "in" => 13, "let" => 14, "loop" => 15, "match" => 16, "mod" => 17,
+        };
+    }
+
+    static_salt_map! {
+        static EMPTY: StaticMap<u8> = {};
+    }
+
+    #[test]
+    fn lookups() {
+        assert_eq!(KEYWORDS.len(), 18);
+        for (i, (key, value)) in KEYWORDS.iter().enumerate() {
+            assert_eq!(*value as usize, i);
+            assert_eq!(KEYWORDS.get(key), Some(value));
+        }
+
+        assert_eq!(KEYWORDS.get("while"), None);
+        assert_eq!(KEYWORDS.get(""), None);
+        assert!(!KEYWORDS.contains_key("matches"));
+
+        assert!(EMPTY.is_empty());
+        assert_eq!(EMPTY.get("as"), None);
+    }
+
+    #[test]
+    fn const_lookup() {
+        const LOOP: Option<&u8> = KEYWORDS.get("loop");
+        assert_eq!(LOOP, Some(&15));
+    }
+
+    #[test]
+    fn every_key_gets_its_own_slot() {
+        // `build` is a plain function outside of const context, which allows a bigger key set
+        const N: usize = 1000;
+        let keys = (0..N)
+            .map(|i| &*format!("key-{}", i).leak())
+            .collect::<Vec<_>>();
+        let layout = Layout::<N, { bucket_count(N) }>::build(&keys);
+
+        let mut slots = layout.slots.to_vec();
+        slots.sort_unstable();
+        assert!(slots.into_iter().eq(0..N));
+
+        let entries = keys.iter().map(|key| (*key, key.len())).collect::<Vec<_>>();
+        let map = StaticMap::from_parts(entries.leak(), Box::leak(Box::new(layout)));
+        for key in keys.iter() {
+            assert_eq!(map.get(key), Some(&key.len()));
+        }
+        assert_eq!(map.get("key-1000"), None);
+    }
+}
@@ -0,0 +1,74 @@
+use crate::chaining_map::ChainingHashMap;
+use std::borrow::Borrow;
+use std::hash;
+use std::sync::Arc;
+
+/// A string-keyed map storing keys as `Box<str>`, 8 bytes smaller per key than `String` since
+/// there is no spare capacity to track. Look keys up with plain `&str`s.
+pub type BoxStrMap<V, S = hash::RandomState> = ChainingHashMap<Box<str>, V, S>;
+
+/// A string-keyed map storing keys as `Arc<str>`, so the same key allocation can be shared
+/// between several maps; see `ChainingHashMap::get_key_value` for getting the stored key back.
+pub type ArcStrMap<V, S = hash::RandomState> = ChainingHashMap<Arc<str>, V, S>;
+
+impl<K, V, S> ChainingHashMap<K, V, S>
+where
+    K: Eq + hash::Hash + Borrow<str> + for<'a> From<&'a str>,
+    S: hash::BuildHasher,
+{
+    /// Inserts with a borrowed key, only converting it into an owned key if it isn't already
+    /// present
+    pub fn insert_str(&mut self, key: &str, value: V) -> Option<V> {
+        match self.get_mut(key) {
+            Some(existing) => Some(std::mem::replace(existing, value)),
+            None => self.insert(K::from(key), value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::size_of;
+
+    #[test]
+    fn box_str_keys_are_smaller() {
+        assert_eq!(
+            size_of::<Box<str>>() + size_of::<usize>(),
+            size_of::<String>()
+        );
+        assert_eq!(
+            size_of::<Arc<str>>() + size_of::<usize>(),
+            size_of::<String>()
+        );
+    }
+
+    #[test]
+    fn box_str_map() {
+        let mut map = BoxStrMap::new();
+
+        assert_eq!(map.insert_str("content-type", 1), None);
+        assert_eq!(map.insert_str("content-type", 2), Some(1));
+        map.insert("accept".into(), 3);
+
+        assert_eq!(map.get("content-type"), Some(&2));
+        assert_eq!(map.get("accept"), Some(&3));
+        assert_eq!(map.remove("accept"), Some(3));
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn arc_str_keys_are_shared() {
+        let mut first = ArcStrMap::new();
+        let mut second = ArcStrMap::new();
+
+        first.insert_str("shared", 1);
+
+        let (key, _) = first.get_key_value("shared").unwrap();
+        second.insert(key.clone(), 2);
+
+        let (other, _) = second.get_key_value("shared").unwrap();
+        assert!(Arc::ptr_eq(first.get_key_value("shared").unwrap().0, other));
+        assert_eq!(second.get("shared"), Some(&2));
+    }
+}
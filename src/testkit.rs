@@ -0,0 +1,234 @@
+// behavioral suite shared by every `Map` implementation; see `map_conformance_tests!`
+use crate::map::Map;
+
+pub(crate) fn insert_and_get<M: Map<usize, usize>>(mut map: M) {
+    assert!(map.is_empty());
+
+    for i in 0..100 {
+        assert_eq!(map.insert(i, i * 2), None);
+        assert_eq!(map.len(), i + 1);
+        assert_eq!(map.get(&i), Some(&(i * 2)));
+    }
+
+    for i in 0..100 {
+        assert_eq!(map.get(&i), Some(&(i * 2)));
+        assert!(map.contains_key(&i));
+    }
+
+    for i in 100..200 {
+        assert_eq!(map.get(&i), None);
+        assert!(!map.contains_key(&i));
+    }
+}
+
+pub(crate) fn overwrite<M: Map<usize, usize>>(mut map: M) {
+    for i in 0..50 {
+        map.insert(i, i);
+    }
+
+    for i in 0..50 {
+        assert_eq!(map.insert(i, i + 1000), Some(i));
+        assert_eq!(map.len(), 50);
+    }
+
+    for i in 0..50 {
+        assert_eq!(map.get(&i), Some(&(i + 1000)));
+    }
+}
+
+pub(crate) fn get_mut<M: Map<usize, usize>>(mut map: M) {
+    for i in 0..50 {
+        map.insert(i, i);
+    }
+
+    for i in 0..50 {
+        *map.get_mut(&i).expect("key was inserted") += 1;
+    }
+
+    assert_eq!(map.get_mut(&50), None);
+
+    for i in 0..50 {
+        assert_eq!(map.get(&i), Some(&(i + 1)));
+    }
+}
+
+pub(crate) fn remove<M: Map<usize, usize>>(mut map: M) {
+    for i in 0..100 {
+        map.insert(i, i);
+    }
+
+    assert_eq!(map.remove(&100), None);
+    assert_eq!(map.len(), 100);
+
+    for i in (0..100).step_by(2) {
+        assert_eq!(map.remove(&i), Some(i));
+        assert_eq!(map.remove(&i), None);
+    }
+
+    assert_eq!(map.len(), 50);
+
+    for i in 0..100 {
+        let expected = if i % 2 == 0 { None } else { Some(&i) };
+        assert_eq!(map.get(&i), expected);
+    }
+
+    for i in (1..100).step_by(2) {
+        assert_eq!(map.remove(&i), Some(i));
+    }
+
+    assert!(map.is_empty());
+}
+
+pub(crate) fn reinsert_after_remove<M: Map<usize, usize>>(mut map: M) {
+    for round in 0..3 {
+        for i in 0..40 {
+            assert_eq!(map.insert(i, i + round), None);
+        }
+        assert_eq!(map.len(), 40);
+
+        for i in 0..40 {
+            assert_eq!(map.remove(&i), Some(i + round));
+        }
+        assert!(map.is_empty());
+    }
+}
+
+pub(crate) fn resize_preserves_entries<M: Map<usize, usize>>(mut map: M) {
+    // enough entries to force several rounds of growth in any implementation
+    let count = 1000;
+
+    for i in 0..count {
+        map.insert(i * 7919, i);
+    }
+
+    assert_eq!(map.len(), count);
+
+    for i in 0..count {
+        assert_eq!(map.get(&(i * 7919)), Some(&i));
+    }
+}
+
+pub(crate) fn iter_matches_len<M: Map<usize, usize>>(mut map: M) {
+    assert_eq!(map.iter().count(), 0);
+
+    for i in 0..300 {
+        map.insert(i, i * 3);
+    }
+    for i in (0..300).step_by(3) {
+        map.remove(&i);
+    }
+
+    let mut pairs = map.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+    pairs.sort();
+
+    let expected = (0..300)
+        .filter(|i| i % 3 != 0)
+        .map(|i| (i, i * 3))
+        .collect::<Vec<_>>();
+
+    assert_eq!(pairs.len(), map.len());
+    assert_eq!(pairs, expected);
+}
+
+// runs `f` with a subscriber that records the name of every span and the message of every event
+// it emits, in order
+#[cfg(feature = "tracing")]
+pub(crate) fn record_traces(f: impl FnOnce()) -> Vec<String> {
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    struct Message(String);
+
+    impl Visit for Message {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            if field.name() == "message" {
+                self.0 = format!("{value:?}");
+            }
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut seen = self.0.lock().unwrap();
+            seen.push(span.metadata().name().to_string());
+            Id::from_u64(seen.len() as u64)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut message = Message(String::new());
+            event.record(&mut message);
+            self.0.lock().unwrap().push(message.0);
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    tracing::subscriber::with_default(Recorder(seen.clone()), f);
+
+    let seen = seen.lock().unwrap();
+    seen.clone()
+}
+
+/// Generates a `conformance` test module running the whole suite against the maps produced by
+/// the given constructor expression, which must build an empty `Map<usize, usize>`.
+macro_rules! map_conformance_tests {
+    ($make:expr) => {
+        mod conformance {
+            #[allow(unused_imports)]
+            use super::*;
+            use $crate::testkit;
+
+            #[test]
+            fn insert_and_get() {
+                testkit::insert_and_get($make);
+            }
+
+            #[test]
+            fn overwrite() {
+                testkit::overwrite($make);
+            }
+
+            #[test]
+            fn get_mut() {
+                testkit::get_mut($make);
+            }
+
+            #[test]
+            fn remove() {
+                testkit::remove($make);
+            }
+
+            #[test]
+            fn reinsert_after_remove() {
+                testkit::reinsert_after_remove($make);
+            }
+
+            #[test]
+            fn resize_preserves_entries() {
+                testkit::resize_preserves_entries($make);
+            }
+
+            #[test]
+            fn iter_matches_len() {
+                testkit::iter_matches_len($make);
+            }
+        }
+    };
+}
+
+pub(crate) use map_conformance_tests;
@@ -0,0 +1,398 @@
+use crate::chaining_map::ChainStats;
+use crate::equivalent::Equivalent;
+use std::hash;
+use std::mem;
+
+// average entries per bucket above which the table doubles
+const MAX_LOAD: usize = 1;
+
+// derives the second choice from the same hash, so each key is only hashed once
+fn second_choice(hash: u64) -> u64 {
+    (hash.rotate_left(32) ^ hash).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+}
+
+fn choices(hash: u64, buckets: usize) -> (usize, usize) {
+    let buckets = buckets as u64;
+    (
+        (hash % buckets) as usize,
+        (second_choice(hash) % buckets) as usize,
+    )
+}
+
+/// A chaining map using the "power of two choices".
+///
+/// Every key has two candidate buckets and is stored in whichever chain is shorter at insert
+/// time; lookups check both. Compared to a single choice, this brings the longest chain down
+/// from roughly `log n / log log n` to `log log n`, at the price of probing two chains on a
+/// miss. Compare `chain_stats` against `ChainingHashMap::chain_stats` to see the difference.
+#[derive(Debug)]
+pub struct TwoChoiceMap<K, V, S = hash::RandomState> {
+    // each entry keeps its full hash, so resizing can place it again without rehashing
+    buckets: Vec<Vec<(u64, K, V)>>,
+    load: usize,
+    hash_builder: S,
+}
+
+impl<K, V> TwoChoiceMap<K, V, hash::RandomState> {
+    pub fn new() -> Self {
+        TwoChoiceMap::with_hasher(hash::RandomState::new())
+    }
+}
+
+impl<K, V> Default for TwoChoiceMap<K, V, hash::RandomState> {
+    fn default() -> Self {
+        TwoChoiceMap::new()
+    }
+}
+
+impl<K, V, S> TwoChoiceMap<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        TwoChoiceMap::with_capacity_and_hasher(16, hash_builder)
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        TwoChoiceMap {
+            buckets: (0..capacity.max(1)).map(|_| Vec::new()).collect(),
+            load: 0,
+            hash_builder,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.load
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.load == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets.iter_mut().for_each(Vec::clear);
+        self.load = 0;
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    pub fn chain_stats(&self) -> ChainStats {
+        ChainStats::from_lengths(self.buckets.iter().map(Vec::len))
+    }
+
+    /// Iterates over the entries, in no particular order
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            buckets: self.buckets.iter(),
+            chain: [].iter(),
+            remaining: self.load,
+        }
+    }
+
+    /// Iterates over the entries with mutable references to the values, in the same order as
+    /// `iter`
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            buckets: self.buckets.iter_mut(),
+            chain: [].iter_mut(),
+            remaining: self.load,
+        }
+    }
+
+    /// Iterates over the keys, in the same order as `iter`
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.iter() }
+    }
+
+    /// Iterates over the values, in the same order as `iter`
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { inner: self.iter() }
+    }
+
+    // the shorter of the two candidate chains, preferring the first on a tie
+    fn shorter_choice(&self, hash: u64) -> usize {
+        let (first, second) = choices(hash, self.buckets.len());
+
+        if self.buckets[second].len() < self.buckets[first].len() {
+            second
+        } else {
+            first
+        }
+    }
+
+    fn resize(&mut self) {
+        let new_len = self.buckets.len() * 2;
+        let old = mem::replace(
+            &mut self.buckets,
+            (0..new_len).map(|_| Vec::new()).collect(),
+        );
+
+        for item in old.into_iter().flatten() {
+            let bucket = self.shorter_choice(item.0);
+            self.buckets[bucket].push(item);
+        }
+    }
+
+    // the bucket and position of the entry for `key`, searching both of its chains
+    fn position<Q>(&self, hash: u64, key: &Q) -> Option<(usize, usize)>
+    where
+        Q: ?Sized + Equivalent<K>,
+    {
+        let (first, second) = choices(hash, self.buckets.len());
+
+        [first, second].into_iter().find_map(|bucket| {
+            self.buckets[bucket]
+                .iter()
+                .position(|item| item.0 == hash && key.equivalent(&item.1))
+                .map(|idx| (bucket, idx))
+        })
+    }
+}
+
+impl<K, V, S> TwoChoiceMap<K, V, S>
+where
+    K: Eq + hash::Hash,
+    S: hash::BuildHasher,
+{
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let result = self.insert_inner(key, value);
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        result
+    }
+
+    fn insert_inner(&mut self, key: K, value: V) -> Option<V> {
+        let hash = self.hash_builder.hash_one(&key);
+
+        if let Some((bucket, idx)) = self.position(hash, &key) {
+            return Some(mem::replace(&mut self.buckets[bucket][idx].2, value));
+        }
+
+        if self.load >= self.buckets.len() * MAX_LOAD {
+            self.resize();
+        }
+
+        let bucket = self.shorter_choice(hash);
+        self.buckets[bucket].push((hash, key, value));
+        self.load += 1;
+
+        None
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.get_key_value(key).map(|(_, value)| value)
+    }
+
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let (bucket, idx) = self.position(self.hash_builder.hash_one(key), key)?;
+        let item = &self.buckets[bucket][idx];
+        Some((&item.1, &item.2))
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let (bucket, idx) = self.position(self.hash_builder.hash_one(key), key)?;
+        Some(&mut self.buckets[bucket][idx].2)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        let (bucket, idx) = self.position(self.hash_builder.hash_one(key), key)?;
+        let (_, key, value) = self.buckets[bucket].swap_remove(idx);
+        self.load -= 1;
+
+        #[cfg(any(test, feature = "validate"))]
+        self.debug_validate();
+
+        Some((key, value))
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + hash::Hash + Equivalent<K>,
+    {
+        self.remove_entry(key).map(|entry| entry.1)
+    }
+
+    /// Checks that each entry lives in one of its two candidate buckets under a current cached hash,
+    /// that no key repeats and that `len()` is right; panics otherwise
+    #[cfg(any(test, feature = "validate"))]
+    pub fn debug_validate(&self) {
+        let mut seen = std::collections::HashSet::with_capacity(self.load);
+
+        for (idx, bucket) in self.buckets.iter().enumerate() {
+            for item in bucket.iter() {
+                assert_eq!(
+                    item.0,
+                    self.hash_builder.hash_one(&item.1),
+                    "cached hash is stale"
+                );
+
+                let (first, second) = choices(item.0, self.buckets.len());
+                assert!(
+                    idx == first || idx == second,
+                    "entry is stored outside its candidate buckets"
+                );
+                assert!(seen.insert(&item.1), "key is stored more than once");
+            }
+        }
+
+        assert_eq!(
+            seen.len(),
+            self.load,
+            "load does not match the number of stored entries"
+        );
+    }
+}
+
+/// An iterator over a `TwoChoiceMap`'s entries, from `TwoChoiceMap::iter`.
+///
+/// Walks the buckets in order, each one's chain in turn.
+#[derive(Debug)]
+pub struct Iter<'a, K, V> {
+    buckets: std::slice::Iter<'a, Vec<(u64, K, V)>>,
+    chain: std::slice::Iter<'a, (u64, K, V)>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            if let Some(item) = self.chain.next() {
+                self.remaining -= 1;
+                return Some((&item.1, &item.2));
+            }
+            self.chain = self.buckets.next()?.iter();
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> std::iter::FusedIterator for Iter<'_, K, V> {}
+
+impl<K, V> Clone for Iter<'_, K, V> {
+    fn clone(&self) -> Self {
+        Iter {
+            buckets: self.buckets.clone(),
+            chain: self.chain.clone(),
+            remaining: self.remaining,
+        }
+    }
+}
+
+/// A mutable iterator over a `TwoChoiceMap`'s entries, from `TwoChoiceMap::iter_mut`.
+#[derive(Debug)]
+pub struct IterMut<'a, K, V> {
+    buckets: std::slice::IterMut<'a, Vec<(u64, K, V)>>,
+    chain: std::slice::IterMut<'a, (u64, K, V)>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        loop {
+            if let Some(item) = self.chain.next() {
+                self.remaining -= 1;
+                return Some((&item.1, &mut item.2));
+            }
+            self.chain = self.buckets.next()?.iter_mut();
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+
+impl<K, V> std::iter::FusedIterator for IterMut<'_, K, V> {}
+
+crate::iter::keys_and_values!(TwoChoiceMap, Iter<K, V>);
+crate::iter::borrowed_into_iterator!(TwoChoiceMap<K, V, S>, Iter<K, V>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chaining_map::ChainingHashMap;
+    use crate::hashers::ConstantState;
+    use crate::seeded::SeededState;
+
+    crate::testkit::map_conformance_tests!(TwoChoiceMap::<usize, usize>::new());
+
+    #[test]
+    fn iterators() {
+        let mut map = TwoChoiceMap::new();
+        for i in 0..100 {
+            map.insert(i, i);
+        }
+
+        let iter = map.iter();
+        assert_eq!(iter.len(), 100);
+        assert_eq!(iter.clone().count(), 100);
+        assert_eq!(map.keys().sum::<i32>(), 4950);
+
+        for (key, value) in &mut map {
+            *value += key;
+        }
+        map.iter_mut().take(10).for_each(|(_, value)| *value = -1);
+        assert_eq!(map.iter_mut().len(), 100);
+        assert_eq!(map.values().filter(|value| **value == -1).count(), 10);
+        assert!((&map)
+            .into_iter()
+            .all(|(key, value)| *value == -1 || *value == key * 2));
+    }
+
+    #[test]
+    fn shorter_chains_than_single_choice() {
+        // seeded, so the comparison is the same on every run
+        let mut single = ChainingHashMap::with_seed(3);
+        let mut double = TwoChoiceMap::with_hasher(SeededState::new(3));
+
+        for i in 0..2000 {
+            single.insert(i, i);
+            double.insert(i, i);
+        }
+
+        let (single, double) = (single.chain_stats(), double.chain_stats());
+        assert_eq!(double.entries, 2000);
+        // despite running at a higher load
+        assert!(double.buckets < single.buckets, "{:?} {:?}", double, single);
+        assert!(double.longest < single.longest, "{:?} {:?}", double, single);
+    }
+
+    #[test]
+    fn total_collision() {
+        let mut map = TwoChoiceMap::with_hasher(ConstantState::default());
+
+        for i in 0..200 {
+            map.insert(i, i);
+        }
+        for i in (0..200).step_by(2) {
+            assert_eq!(map.remove(&i), Some(i));
+        }
+
+        assert_eq!(map.len(), 100);
+        assert_eq!(map.get(&99), Some(&99));
+    }
+}
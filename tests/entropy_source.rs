@@ -0,0 +1,8 @@
+use salt_map::seeded::{set_entropy_source, SeededState};
+
+#[test]
+fn entropy_source() {
+    set_entropy_source(|| 7);
+    assert_eq!(SeededState::random(), SeededState::new(7));
+    assert_eq!(SeededState::default(), SeededState::new(7));
+}